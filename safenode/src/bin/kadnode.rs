@@ -37,7 +37,11 @@ async fn main() -> Result<()> {
     let storage = DataStorage::new(&temp_dir);
 
     // Spawn the network task for it to run in the background.
-    spawn(network_event_loop.run());
+    spawn(async move {
+        if let Err(err) = network_event_loop.run().await {
+            warn!("The network event loop stopped: {err}");
+        }
+    });
 
     let mut api_clone = network_api.clone();
     let storage_clone = storage.clone();
@@ -50,7 +54,7 @@ async fn main() -> Result<()> {
                 None => continue,
             };
             match event {
-                NetworkEvent::RequestReceived { req, channel } => {
+                NetworkEvent::RequestReceived { req, channel, .. } => {
                     // Reply with the content of the file on incoming requests.
                     if let Request::GetChunk(xor_name) = req {
                         let addr = ChunkAddress(xor_name);
@@ -63,7 +67,102 @@ async fn main() -> Result<()> {
                         }
                     }
                 }
-                NetworkEvent::PeerDiscovered => {
+                NetworkEvent::RawRequestReceived { .. } => {
+                    warn!("Ignoring raw request, the node does not serve any raw protocol");
+                }
+                NetworkEvent::ExternalAddressConfirmed { addr } => {
+                    info!("The node is now publicly reachable at {addr:?}");
+                }
+                NetworkEvent::ExternalAddressExpired { addr } => {
+                    info!("The node is no longer confirmed to be reachable at {addr:?}");
+                }
+                NetworkEvent::PeerIdentified {
+                    peer_id,
+                    agent_version,
+                    ..
+                } => {
+                    info!("Peer {peer_id:?} runs {agent_version}");
+                }
+                NetworkEvent::PeerRejectedVersion { peer, version } => {
+                    warn!("Rejected {peer:?} running protocol version {version}");
+                }
+                NetworkEvent::PeerIdMismatch {
+                    expected,
+                    actual,
+                    addr,
+                } => {
+                    warn!("Dialed {expected:?} at {addr}, but {actual:?} answered");
+                }
+                NetworkEvent::GossipMessage {
+                    topic,
+                    source,
+                    data,
+                } => {
+                    info!("Gossip from {source:?} on {topic}: {} bytes", data.len());
+                }
+                NetworkEvent::BootstrapProgress {
+                    connected,
+                    failed,
+                    total,
+                } => {
+                    info!("Bootstrap: connected to {connected}, failed {failed}, of {total} peers");
+                }
+                NetworkEvent::BootstrapComplete { error: None } => info!("Bootstrapped"),
+                NetworkEvent::BootstrapComplete { error: Some(error) } => {
+                    warn!("Bootstrap failed: {error}");
+                }
+                NetworkEvent::DataStored { xor_name, attempts } => {
+                    info!("Stored {xor_name:?} after {attempts} attempts");
+                }
+                NetworkEvent::DataStoreFailed { xor_name, attempts } => {
+                    warn!("Failed to store {xor_name:?} after {attempts} attempts");
+                }
+                NetworkEvent::PeerAlreadyConnected { peer_id } => {
+                    info!("Reusing the existing connection to {peer_id:?}");
+                }
+                NetworkEvent::LoadSheddingActivated {
+                    in_flight,
+                    stored_records,
+                } => {
+                    warn!(
+                        "Shedding load: {in_flight} operations in flight, {stored_records} records"
+                    );
+                }
+                NetworkEvent::LoadSheddingDeactivated => {
+                    info!("Stopped shedding load");
+                }
+                NetworkEvent::RecordConflict {
+                    key,
+                    source,
+                    kept_existing,
+                } => {
+                    info!(
+                        "Conflicting record {} put by {source:?}, kept existing: {kept_existing}",
+                        hex::encode(key)
+                    );
+                }
+                NetworkEvent::InboundStreamLimitReached { peer, limit } => {
+                    warn!("{peer:?} reached the limit of {limit} concurrent inbound requests");
+                }
+                NetworkEvent::RoutingTableEmpty => {
+                    warn!("The routing table is empty, the node is isolated");
+                }
+                NetworkEvent::RoutingTableRecovered => {
+                    info!("The routing table has peers again");
+                }
+                NetworkEvent::ProtocolsChanged { active } => {
+                    info!("The active request protocols are now {active:?}");
+                }
+                NetworkEvent::PeerConnected { peer_id, addr } => {
+                    info!("Connected to {peer_id:?} at {addr:?}");
+                }
+                NetworkEvent::PeerDisconnected { peer_id, addr } => {
+                    info!("Disconnected from {peer_id:?} at {addr:?}");
+                }
+                NetworkEvent::PeerReaped { peer } => {
+                    info!("Disconnected from idle peer {peer:?}");
+                }
+                NetworkEvent::PeerDiscovered { .. } => {
                     if let Some(sender) = peer_dicovered_send.take() {
                         if let Err(err) = sender.send(()) {
                             warn!("Error while sending through channel: {err:?}");
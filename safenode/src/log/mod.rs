@@ -43,7 +43,7 @@ where
         // Write level and target
         let level = *event.metadata().level();
         let module = event.metadata().module_path().unwrap_or("<unknown module>");
-        let time = SystemTime::default();
+        let time = SystemTime;
 
         write!(writer, "[")?;
         time.format_time(&mut writer)?;
@@ -96,7 +96,7 @@ impl TracingLayers {
             let fmt_layer = fmt_layer.with_writer(non_blocking);
 
             let layer = fmt_layer
-                .event_format(LogFormatter::default())
+                .event_format(LogFormatter)
                 .with_filter(target_filter)
                 .boxed();
             self.layers.push(layer);
@@ -105,7 +105,7 @@ impl TracingLayers {
 
             let layer = fmt_layer
                 .with_target(false)
-                .event_format(LogFormatter::default())
+                .event_format(LogFormatter)
                 .with_filter(target_filter)
                 .boxed();
             self.layers.push(layer);
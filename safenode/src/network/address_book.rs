@@ -0,0 +1,224 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::NetworkSwarmLoop;
+use libp2p::{Multiaddr, PeerId};
+use std::{collections::HashMap, time::SystemTime};
+
+/// The number of peers a default `MemoryAddressBook` holds the addresses of.
+const DEFAULT_MAX_PEERS: usize = 4096;
+
+/// The number of addresses of a peer a default `MemoryAddressBook` holds.
+const DEFAULT_MAX_ADDRESSES_PER_PEER: usize = 8;
+
+/// How the address of a peer was learnt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressSource {
+    /// We successfully dialed the peer at the address.
+    Dialed,
+    /// The address was given to `Network::bootstrap`.
+    Bootstrap,
+    /// The peer was discovered at the address through mDNS.
+    Mdns,
+    /// The peer advertised the address as one of its listen addresses, through identify.
+    Identify,
+    /// The address was imported through `Network::import_routing_snapshot`.
+    Snapshot,
+}
+
+/// An address of a peer, as recorded by an `AddressBook`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressEntry {
+    /// The address of the peer.
+    pub addr: Multiaddr,
+    /// When the address was last recorded.
+    pub last_seen: SystemTime,
+    /// How the address was last learnt.
+    pub source: AddressSource,
+}
+
+/// The node's knowledge of the addresses of the peers, outliving the Kademlia buckets, which
+/// only hold a bounded number of peers and forget those that go unresponsive.
+///
+/// The `NetworkSwarmLoop` records every address it learns in the book and adds the known
+/// addresses of a peer to the ones given to `Network::dial`. The addresses of the book are added
+/// to the routing table when the book is installed through `NetworkSwarmLoop::with_address_book`,
+/// so an implementation persisting the book, e.g. to disk or a database, lets the node reconnect
+/// after a restart. `MemoryAddressBook` is used by default.
+///
+/// The methods are called from within the event loop, hence must not block for long; a
+/// persisting implementation would typically buffer the writes.
+pub trait AddressBook: Send {
+    /// Records that `peer` is reachable at `addr`, as learnt through `source`, now.
+    fn record(&mut self, peer: PeerId, addr: Multiaddr, source: AddressSource);
+
+    /// Returns the known addresses of `peer`.
+    fn addresses(&self, peer: &PeerId) -> Vec<AddressEntry>;
+
+    /// Returns every known peer, with its addresses.
+    fn entries(&self) -> Vec<(PeerId, Vec<AddressEntry>)>;
+}
+
+/// An `AddressBook` held in memory, hence lost on restart.
+///
+/// The book is bounded: past `max_addresses_per_peer` addresses of a peer, the one recorded the
+/// longest ago is forgotten, and past `max_peers` peers, the peer recorded the longest ago is.
+#[derive(Debug)]
+pub struct MemoryAddressBook {
+    peers: HashMap<PeerId, PeerAddresses>,
+    max_peers: usize,
+    max_addresses_per_peer: usize,
+    // The number of addresses recorded so far, ordering the peers by their last record.
+    records: u64,
+}
+
+// The addresses of a peer, the last recorded last.
+#[derive(Debug, Default)]
+struct PeerAddresses {
+    entries: Vec<AddressEntry>,
+    last_record: u64,
+}
+
+impl MemoryAddressBook {
+    /// Creates an empty book holding the addresses of up to `max_peers` peers, and up to
+    /// `max_addresses_per_peer` addresses of each of them.
+    pub fn new(max_peers: usize, max_addresses_per_peer: usize) -> Self {
+        Self {
+            peers: HashMap::new(),
+            max_peers,
+            max_addresses_per_peer,
+            records: 0,
+        }
+    }
+
+    // Forgets the peer recorded the longest ago.
+    fn evict_oldest_peer(&mut self) {
+        let oldest = self
+            .peers
+            .iter()
+            .min_by_key(|(_, addresses)| addresses.last_record)
+            .map(|(peer, _)| *peer);
+        if let Some(peer) = oldest {
+            let _ = self.peers.remove(&peer);
+        }
+    }
+}
+
+impl Default for MemoryAddressBook {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_PEERS, DEFAULT_MAX_ADDRESSES_PER_PEER)
+    }
+}
+
+impl AddressBook for MemoryAddressBook {
+    fn record(&mut self, peer: PeerId, addr: Multiaddr, source: AddressSource) {
+        if self.max_peers == 0 || self.max_addresses_per_peer == 0 {
+            return;
+        }
+        if !self.peers.contains_key(&peer) && self.peers.len() >= self.max_peers {
+            self.evict_oldest_peer();
+        }
+        self.records += 1;
+        let addresses = self.peers.entry(peer).or_default();
+        addresses.last_record = self.records;
+        addresses.entries.retain(|entry| entry.addr != addr);
+        if addresses.entries.len() >= self.max_addresses_per_peer {
+            let _ = addresses.entries.remove(0);
+        }
+        addresses.entries.push(AddressEntry {
+            addr,
+            last_seen: SystemTime::now(),
+            source,
+        });
+    }
+
+    fn addresses(&self, peer: &PeerId) -> Vec<AddressEntry> {
+        self.peers
+            .get(peer)
+            .map(|addresses| addresses.entries.clone())
+            .unwrap_or_default()
+    }
+
+    fn entries(&self) -> Vec<(PeerId, Vec<AddressEntry>)> {
+        self.peers
+            .iter()
+            .map(|(peer, addresses)| (*peer, addresses.entries.clone()))
+            .collect()
+    }
+}
+
+impl NetworkSwarmLoop {
+    /// Records every address learnt through the `NetworkSwarmLoop` in `address_book`, instead of
+    /// the default `MemoryAddressBook`. The addresses already in the book are added to the
+    /// routing table.
+    pub fn with_address_book(mut self, address_book: impl AddressBook + 'static) -> Self {
+        for (peer, entries) in address_book.entries() {
+            for entry in entries {
+                let _routing_update = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .add_address(&peer, entry.addr);
+            }
+        }
+        self.address_book = Box::new(address_book);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddressBook, AddressSource, MemoryAddressBook};
+    use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+
+    fn addr(port: u16) -> Multiaddr {
+        Multiaddr::empty().with(Protocol::Tcp(port))
+    }
+
+    fn addrs(book: &MemoryAddressBook, peer: &PeerId) -> Vec<Multiaddr> {
+        book.addresses(peer)
+            .into_iter()
+            .map(|entry| entry.addr)
+            .collect()
+    }
+
+    #[test]
+    fn the_addresses_recorded_the_longest_ago_are_evicted() {
+        let mut book = MemoryAddressBook::new(2, 2);
+        let peer = PeerId::random();
+        book.record(peer, addr(1), AddressSource::Dialed);
+        book.record(peer, addr(2), AddressSource::Identify);
+        // Recording an address again makes it the latest.
+        book.record(peer, addr(1), AddressSource::Mdns);
+        book.record(peer, addr(3), AddressSource::Identify);
+        assert_eq!(addrs(&book, &peer), [addr(1), addr(3)]);
+        assert_eq!(
+            book.addresses(&peer).first().map(|entry| entry.source),
+            Some(AddressSource::Mdns)
+        );
+    }
+
+    #[test]
+    fn the_peers_recorded_the_longest_ago_are_evicted() {
+        let mut book = MemoryAddressBook::new(2, 2);
+        let [first, second, third] = [PeerId::random(), PeerId::random(), PeerId::random()];
+        book.record(first, addr(1), AddressSource::Dialed);
+        book.record(second, addr(2), AddressSource::Dialed);
+        book.record(first, addr(3), AddressSource::Dialed);
+        book.record(third, addr(4), AddressSource::Dialed);
+        assert_eq!(book.entries().len(), 2);
+        assert_eq!(addrs(&book, &first), [addr(1), addr(3)]);
+        assert!(book.addresses(&second).is_empty());
+        assert_eq!(addrs(&book, &third), [addr(4)]);
+
+        // A book without room records nothing.
+        let mut book = MemoryAddressBook::new(0, 2);
+        book.record(first, addr(1), AddressSource::Dialed);
+        assert!(book.entries().is_empty());
+    }
+}
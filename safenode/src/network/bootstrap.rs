@@ -0,0 +1,352 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    address_book::AddressSource,
+    error::{Error, Result},
+    NetworkEvent, NetworkSwarmLoop,
+};
+use futures::channel::oneshot;
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use std::collections::{HashSet, VecDeque};
+use tracing::{info, warn};
+
+/// The bootstrap peers being dialed, at most `NetworkConfig::bootstrap_dial_concurrency` at once.
+pub(super) struct BootstrapDials {
+    queued: VecDeque<(PeerId, Multiaddr)>,
+    in_flight: HashSet<PeerId>,
+    connected: usize,
+    failed: usize,
+    total: usize,
+    /// The caller waiting for the outcome, `None` for the startup bootstrap.
+    sender: Option<oneshot::Sender<Result<()>>>,
+}
+
+impl BootstrapDials {
+    fn progress(&self) -> NetworkEvent {
+        NetworkEvent::BootstrapProgress {
+            connected: self.connected,
+            failed: self.failed,
+            total: self.total,
+        }
+    }
+
+    /// Fails the bootstrap with the given error, without dialing the peers left.
+    pub(super) fn abandon(self, error: Error) {
+        if let Some(sender) = self.sender {
+            let _ = sender.send(Err(error));
+        }
+    }
+
+    // Whether the caller gave up waiting for the outcome, e.g. on timeout. The startup
+    // bootstrap is never given up on.
+    fn is_abandoned(&self) -> bool {
+        self.sender
+            .as_ref()
+            .is_some_and(oneshot::Sender::is_canceled)
+    }
+}
+
+impl NetworkSwarmLoop {
+    /// Adds the peers to the routing table and starts dialing them in waves. The Kademlia
+    /// bootstrap is performed once all of them have been dialed, provided at least one of them
+    /// could be reached.
+    pub(super) async fn start_bootstrap(
+        &mut self,
+        peers: Vec<(PeerId, Multiaddr)>,
+        sender: Option<oneshot::Sender<Result<()>>>,
+    ) -> Result<()> {
+        // The caller of an ongoing bootstrap may have given up on it, e.g. on timeout.
+        if let Some(dials) = &self.bootstrap_dials {
+            if !dials.is_abandoned() {
+                if let Some(sender) = sender {
+                    let _ = sender.send(Err(Error::Other(
+                        "Bootstrap already in progress.".to_string(),
+                    )));
+                }
+                return Ok(());
+            }
+        }
+        for (peer_id, peer_addr) in &peers {
+            let _ = self.explicit_peers.insert(*peer_id);
+            self.address_book
+                .record(*peer_id, peer_addr.clone(), AddressSource::Bootstrap);
+            let _routing_update = self
+                .swarm
+                .behaviour_mut()
+                .kademlia
+                .add_address(peer_id, peer_addr.clone());
+        }
+        self.bootstrap_dials = Some(BootstrapDials {
+            total: peers.len(),
+            queued: peers.into(),
+            in_flight: Default::default(),
+            connected: 0,
+            failed: 0,
+            sender,
+        });
+        self.dial_bootstrap_peers().await
+    }
+
+    /// Accounts for the outcome of a bootstrap dial, then dials the next peers in the queue.
+    pub(super) async fn on_bootstrap_dial_completed(
+        &mut self,
+        peer_id: PeerId,
+        connected: bool,
+    ) -> Result<()> {
+        let dials = match &mut self.bootstrap_dials {
+            Some(dials) => dials,
+            None => return Ok(()),
+        };
+        if !dials.in_flight.remove(&peer_id) {
+            return Ok(());
+        }
+        if connected {
+            dials.connected += 1;
+        } else {
+            dials.failed += 1;
+        }
+        let progress = dials.progress();
+        self.send_event(progress).await?;
+        self.dial_bootstrap_peers().await
+    }
+
+    /// Dials the bootstrap peers held back by the `NetworkConfig::max_pending_dials`, if any.
+    pub(super) async fn resume_bootstrap_dials(&mut self) -> Result<()> {
+        let held_back = self
+            .bootstrap_dials
+            .as_ref()
+            .is_some_and(|dials| !dials.queued.is_empty());
+        if held_back {
+            self.dial_bootstrap_peers().await?;
+        }
+        Ok(())
+    }
+
+    // Dials the queued peers until the concurrency limit is reached. Once every peer has been
+    // dialed, runs the Kademlia bootstrap to populate the routing table.
+    async fn dial_bootstrap_peers(&mut self) -> Result<()> {
+        let concurrency = self.bootstrap_dial_concurrency.max(1);
+        let dials = match &mut self.bootstrap_dials {
+            Some(dials) => dials,
+            None => return Ok(()),
+        };
+        let mut progress = Vec::new();
+        while dials.in_flight.len() < concurrency {
+            // Resumed by `dequeue_dials` once the pending connections settle.
+            if self.swarm.behaviour().dial_limit.limit_reached() {
+                break;
+            }
+            let (peer_id, peer_addr) = match dials.queued.pop_front() {
+                Some(peer) => peer,
+                None => break,
+            };
+            if self.swarm.is_connected(&peer_id) {
+                dials.connected += 1;
+            } else {
+                match self
+                    .swarm
+                    .dial(peer_addr.with(Protocol::P2p(peer_id.into())))
+                {
+                    Ok(()) => {
+                        let _ = dials.in_flight.insert(peer_id);
+                        continue;
+                    }
+                    Err(err) => {
+                        warn!("Failed to dial bootstrap peer {peer_id:?}: {err:?}");
+                        dials.failed += 1;
+                    }
+                }
+            }
+            progress.push(dials.progress());
+        }
+        let dialing = !dials.in_flight.is_empty() || !dials.queued.is_empty();
+        for event in progress {
+            self.send_event(event).await?;
+        }
+        if dialing {
+            return Ok(());
+        }
+
+        let dials = match self.bootstrap_dials.take() {
+            Some(dials) => dials,
+            None => return Ok(()),
+        };
+        info!(
+            "Dialed {} bootstrap peers, {} connected",
+            dials.total, dials.connected
+        );
+        if dials.total > 0 && dials.connected == 0 {
+            let error = Error::BootstrapPeersUnreachable(dials.total);
+            return self.complete_bootstrap(dials.sender, Err(error)).await;
+        }
+        match self.swarm.behaviour_mut().kademlia.bootstrap() {
+            Ok(query_id) => {
+                let _ = self.pending_bootstrap.insert(query_id, dials.sender);
+                Ok(())
+            }
+            Err(no_known_peers) => {
+                self.complete_bootstrap(dials.sender, Err(no_known_peers.into()))
+                    .await
+            }
+        }
+    }
+
+    /// Resolves the bootstrap with its outcome, also reported by a
+    /// `NetworkEvent::BootstrapComplete`.
+    pub(super) async fn complete_bootstrap(
+        &mut self,
+        sender: Option<oneshot::Sender<Result<()>>>,
+        result: Result<()>,
+    ) -> Result<()> {
+        let error = result.as_ref().err().map(ToString::to_string);
+        if let Some(sender) = sender {
+            let _ = sender.send(result);
+        }
+        self.send_event(NetworkEvent::BootstrapComplete { error })
+            .await
+    }
+
+    /// Drops the bootstrap its caller gave up on, e.g. on `Error::BootstrapTimeout`, without
+    /// dialing the peers left or reporting its progress and completion.
+    pub(super) fn abandon_bootstrap(&mut self) {
+        if self
+            .bootstrap_dials
+            .as_ref()
+            .is_some_and(BootstrapDials::is_abandoned)
+        {
+            self.bootstrap_dials = None;
+        }
+        self.pending_bootstrap
+            .retain(|_, sender| sender.as_ref().is_none_or(|sender| !sender.is_canceled()));
+    }
+
+    /// Starts bootstrapping from the `NetworkConfig::bootstrap_peers`, if any. No one waits for
+    /// the outcome, only reported by the `NetworkEvent::BootstrapComplete`.
+    pub(super) async fn start_startup_bootstrap(&mut self) -> Result<()> {
+        let peers = std::mem::take(&mut self.startup_bootstrap_peers);
+        if peers.is_empty() {
+            return Ok(());
+        }
+        self.start_bootstrap(peers, None).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        error::Result,
+        testing::{isolated_config, listen_addr},
+        Error, NetworkConfig, NetworkEvent, NetworkSwarmLoop,
+    };
+    use async_std::{future::timeout, task};
+    use futures::{Stream, StreamExt};
+    use libp2p::{Multiaddr, PeerId};
+    use std::{net::TcpListener, time::Duration};
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    // A peer that can't be dialed, nothing listening on its port.
+    fn unreachable_peer() -> (PeerId, Multiaddr) {
+        let addr = "/ip4/127.0.0.1/tcp/1".parse().expect("a valid multiaddr");
+        (PeerId::random(), addr)
+    }
+
+    // Runs a node bootstrapping from `bootstrap_peers` on startup, returning the error of its
+    // `NetworkEvent::BootstrapComplete`.
+    async fn startup_bootstrap(
+        bootstrap_peers: Vec<(PeerId, Multiaddr)>,
+    ) -> Result<Option<String>> {
+        let (_network, events, event_loop) = NetworkSwarmLoop::with_config(NetworkConfig {
+            bootstrap_peers,
+            ..isolated_config()
+        })?;
+        let _event_loop = task::spawn(event_loop.run());
+        Ok(timeout(TEST_TIMEOUT, bootstrap_outcome(events))
+            .await
+            .expect("the bootstrap to complete"))
+    }
+
+    async fn bootstrap_outcome(
+        mut events: impl Stream<Item = NetworkEvent> + Unpin,
+    ) -> Option<String> {
+        while let Some(event) = events.next().await {
+            if let NetworkEvent::BootstrapComplete { error } = event {
+                return error;
+            }
+        }
+        Some("event stream ended".to_string())
+    }
+
+    #[async_std::test]
+    async fn startup_bootstrap_proceeds_if_any_peer_is_reachable() -> Result<()> {
+        let (mut seed, seed_events, seed_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let _seed_loop = task::spawn(seed_loop.run());
+        let _seed_events = task::spawn(seed_events.for_each(|_| async {}));
+        let addr = listen_addr(&mut seed).await?;
+
+        let error =
+            startup_bootstrap(vec![unreachable_peer(), (seed.local_peer_id(), addr)]).await?;
+        assert_eq!(error, None);
+
+        let error = startup_bootstrap(vec![unreachable_peer(), unreachable_peer()]).await?;
+        assert!(error.is_some());
+
+        // Nothing to bootstrap from, nothing happens.
+        let (_network, mut events, event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let _event_loop = task::spawn(event_loop.run());
+        let outcome = timeout(Duration::from_millis(500), async {
+            while let Some(event) = events.next().await {
+                if let NetworkEvent::BootstrapComplete { .. } = event {
+                    return true;
+                }
+            }
+            false
+        })
+        .await;
+        assert!(outcome.is_err(), "no bootstrap to be reported");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn a_timed_out_bootstrap_reports_nothing_afterwards() -> Result<()> {
+        // Accepts the connections at the TCP level only, the dial hanging in the handshake
+        // until the listener is closed.
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let addr = format!("/ip4/127.0.0.1/tcp/{port}")
+            .parse()
+            .expect("a valid multiaddr");
+
+        let (mut network, mut events, event_loop) = NetworkSwarmLoop::with_config(NetworkConfig {
+            bootstrap_timeout: Duration::from_millis(500),
+            ..isolated_config()
+        })?;
+        let _event_loop = task::spawn(event_loop.run());
+        match network.bootstrap(vec![(PeerId::random(), addr)]).await {
+            Err(Error::BootstrapTimeout { connected_peers }) => assert_eq!(connected_peers, 0),
+            other => panic!("expected a bootstrap timeout, got {other:?}"),
+        }
+
+        // The dial now fails, which no longer counts as the progress of the bootstrap.
+        drop(listener);
+        let reported = timeout(Duration::from_secs(1), async {
+            while let Some(event) = events.next().await {
+                if let NetworkEvent::BootstrapProgress { .. }
+                | NetworkEvent::BootstrapComplete { .. } = event
+                {
+                    return true;
+                }
+            }
+            false
+        })
+        .await;
+        assert!(reported.is_err(), "no bootstrap to be reported");
+        Ok(())
+    }
+}
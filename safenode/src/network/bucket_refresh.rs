@@ -0,0 +1,114 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::NetworkSwarmLoop;
+use libp2p::{
+    kad::{Addresses, KBucketKey, KBucketRef},
+    PeerId,
+};
+use tracing::trace;
+
+// The number of random `PeerId`s tried when looking for one whose key falls into a given bucket.
+const BUCKET_KEY_TRIALS: usize = 256;
+
+impl NetworkSwarmLoop {
+    /// Refreshes the non-empty buckets that are due as per the `BucketRefreshConfig` schedule,
+    /// by looking up the closest peers to a random key within each of them.
+    pub(super) fn refresh_buckets(&mut self) {
+        let max_backoff = match &self.bucket_refresh {
+            Some(config) => config.max_backoff,
+            None => return,
+        };
+        let round = self.bucket_refresh_round;
+        self.bucket_refresh_round = round.wrapping_add(1);
+
+        let local_key = KBucketKey::from(*self.swarm.local_peer_id());
+        // `kbuckets` yields the non-empty buckets starting from the one closest to us.
+        let targets: Vec<PeerId> = self
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .kbuckets()
+            .enumerate()
+            .filter(|(rank, _)| {
+                let backoff = u32::try_from(*rank).unwrap_or(u32::MAX).min(max_backoff);
+                round.is_multiple_of(2u64.saturating_pow(backoff))
+            })
+            .map(|(_, bucket)| random_peer_in_bucket(&local_key, &bucket))
+            .collect();
+
+        trace!("Refreshing {} buckets in round {round}", targets.len());
+        for target in targets {
+            let _query_id = self
+                .swarm
+                .behaviour_mut()
+                .kademlia
+                .get_closest_peers(target);
+        }
+    }
+}
+
+// Returns a random `PeerId` whose key falls into the given bucket, if one is found.
+//
+// The target key is the local one with the bit of the bucket flipped and the bits below it
+// randomised. The preimage of a key being sent over the wire, the target itself can't be
+// looked up: the `PeerId` whose key shares the longest prefix with it is, out of the ones tried.
+// That's one in the bucket with a high probability for the distant buckets, where most of the
+// peers are, and one in the nearest bucket possible otherwise.
+fn random_peer_in_bucket(
+    local_key: &KBucketKey<PeerId>,
+    bucket: &KBucketRef<'_, KBucketKey<PeerId>, Addresses>,
+) -> PeerId {
+    let target = local_key.for_distance(bucket.rand_distance(&mut rand::thread_rng()));
+    let mut closest = PeerId::random();
+    let mut closest_distance = KBucketKey::from(closest).distance(&target);
+    for _ in 1..BUCKET_KEY_TRIALS {
+        if bucket.contains(&local_key.distance(&KBucketKey::from(closest))) {
+            break;
+        }
+        let peer = PeerId::random();
+        let distance = KBucketKey::from(peer).distance(&target);
+        if distance < closest_distance {
+            closest = peer;
+            closest_distance = distance;
+        }
+    }
+    closest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::random_peer_in_bucket;
+    use crate::network::{error::Result, testing::isolated_config, NetworkSwarmLoop};
+    use libp2p::{kad::KBucketKey, PeerId};
+
+    #[test]
+    fn the_random_peer_of_a_distant_bucket_falls_into_it() -> Result<()> {
+        let (_network, _events, mut event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let local_key = KBucketKey::from(*event_loop.swarm.local_peer_id());
+        let kademlia = &mut event_loop.swarm.behaviour_mut().kademlia;
+        // Random peers fall into the most distant buckets by far.
+        for _ in 0..8 {
+            let _routing_update =
+                kademlia.add_address(&PeerId::random(), "/ip4/127.0.0.1/tcp/12000".parse()?);
+        }
+        // One in sixteen keys falls into the fourth most distant bucket, hence the trials all but
+        // certainly find one.
+        let mut checked = 0;
+        for bucket in kademlia.kbuckets() {
+            if bucket.range().0.ilog2().unwrap_or_default() < 252 {
+                continue;
+            }
+            let peer = random_peer_in_bucket(&local_key, &bucket);
+            assert!(bucket.contains(&local_key.distance(&KBucketKey::from(peer))));
+            checked += 1;
+        }
+        assert!(checked > 0);
+        Ok(())
+    }
+}
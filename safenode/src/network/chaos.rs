@@ -0,0 +1,228 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use futures::{AsyncRead, AsyncWrite, Future};
+use futures_timer::Delay;
+use libp2p::{
+    core::{
+        muxing::{StreamMuxerBox, StreamMuxerEvent, SubstreamBox},
+        transport::Boxed,
+        StreamMuxer,
+    },
+    PeerId, Transport,
+};
+use rand::Rng;
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Adverse network conditions injected into the transport, for testing the resilience to
+/// timeouts and failures. Only available with the `chaos` feature.
+///
+/// The conditions apply to each substream, i.e. to each request or response of the
+/// request-response protocols and to each Kademlia, identify or AutoNAT exchange: the first
+/// write of the substream is delayed, or the substream is reset instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChaosConfig {
+    /// The delay added before the first write of each substream.
+    pub latency: Duration,
+    /// The maximum random variation of the `latency`, either way.
+    pub jitter: Duration,
+    /// The fraction, between 0 and 1, of the substreams reset instead of being written to.
+    /// Out of range values are clamped, and a NaN or infinite one counts as 0.
+    pub loss_rate: f64,
+}
+
+impl ChaosConfig {
+    // The probability of a new substream being lost, valid for `gen_bool`.
+    fn loss_probability(&self) -> f64 {
+        if self.loss_rate.is_finite() {
+            self.loss_rate.clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    // Returns the delay of the first write of a new substream, `None` if it is to be lost.
+    fn draw(&self) -> Option<Duration> {
+        let mut rng = rand::thread_rng();
+        if rng.gen_bool(self.loss_probability()) {
+            return None;
+        }
+        let jitter = self.jitter.as_secs_f64();
+        let offset = if jitter > 0.0 {
+            rng.gen_range(-jitter..=jitter)
+        } else {
+            0.0
+        };
+        Some(Duration::from_secs_f64(
+            (self.latency.as_secs_f64() + offset).max(0.0),
+        ))
+    }
+}
+
+/// Wraps the connections of `transport` so they are subject to the `config` conditions.
+pub(super) fn apply(
+    transport: Boxed<(PeerId, StreamMuxerBox)>,
+    config: ChaosConfig,
+) -> Boxed<(PeerId, StreamMuxerBox)> {
+    transport
+        .map(move |(peer_id, muxer), _| {
+            (
+                peer_id,
+                StreamMuxerBox::new(ChaosMuxer {
+                    inner: muxer,
+                    config,
+                }),
+            )
+        })
+        .boxed()
+}
+
+struct ChaosMuxer {
+    inner: StreamMuxerBox,
+    config: ChaosConfig,
+}
+
+impl ChaosMuxer {
+    fn wrap(&self, substream: SubstreamBox) -> ChaosSubstream {
+        let first_write = match self.config.draw() {
+            Some(latency) if latency.is_zero() => FirstWrite::Done,
+            Some(latency) => FirstWrite::Delayed(Delay::new(latency)),
+            None => FirstWrite::Lost,
+        };
+        ChaosSubstream {
+            inner: substream,
+            first_write,
+        }
+    }
+}
+
+impl StreamMuxer for ChaosMuxer {
+    type Substream = ChaosSubstream;
+    type Error = io::Error;
+
+    fn poll_inbound(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let substream = futures::ready!(Pin::new(&mut self.inner).poll_inbound(cx))?;
+        Poll::Ready(Ok(self.wrap(substream)))
+    }
+
+    fn poll_outbound(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let substream = futures::ready!(Pin::new(&mut self.inner).poll_outbound(cx))?;
+        Poll::Ready(Ok(self.wrap(substream)))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
+        Pin::new(&mut self.inner).poll(cx)
+    }
+}
+
+enum FirstWrite {
+    Delayed(Delay),
+    Lost,
+    Done,
+}
+
+struct ChaosSubstream {
+    inner: SubstreamBox,
+    first_write: FirstWrite,
+}
+
+impl ChaosSubstream {
+    // Applies the conditions drawn for the substream, before its first write goes through.
+    fn poll_first_write(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.first_write {
+            FirstWrite::Delayed(delay) => {
+                futures::ready!(Pin::new(delay).poll(cx));
+                self.first_write = FirstWrite::Done;
+                Poll::Ready(Ok(()))
+            }
+            FirstWrite::Lost => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "substream lost by the chaos transport",
+            ))),
+            FirstWrite::Done => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl AsyncRead for ChaosSubstream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ChaosSubstream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        futures::ready!(self.poll_first_write(cx))?;
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChaosConfig;
+    use std::time::Duration;
+
+    #[test]
+    fn a_loss_rate_out_of_range_is_still_a_probability() {
+        for (loss_rate, expected) in [
+            (f64::NAN, 0.0),
+            (f64::INFINITY, 0.0),
+            (f64::NEG_INFINITY, 0.0),
+            (-0.5, 0.0),
+            (0.25, 0.25),
+            (1.5, 1.0),
+        ] {
+            let config = ChaosConfig {
+                loss_rate,
+                ..Default::default()
+            };
+            assert_eq!(config.loss_probability(), expected, "for {loss_rate}");
+        }
+
+        let config = ChaosConfig {
+            latency: Duration::from_millis(5),
+            loss_rate: f64::NAN,
+            ..Default::default()
+        };
+        assert_eq!(config.draw(), Some(Duration::from_millis(5)));
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    error::{Error, Result},
+    msg::Response,
+    NetworkSwarmLoop,
+};
+use futures::channel::oneshot;
+use libp2p::{request_response::RequestId, PeerId};
+use std::time::Instant;
+use tracing::trace;
+use xor_name::XorName;
+
+/// The latest request sent through `Network::send_coalesced_request` for a key to a peer, which
+/// the identical requests can join for the `NetworkConfig::request_coalescing_window`.
+pub(super) struct CoalescedRequest {
+    request_id: RequestId,
+    sent_at: Instant,
+}
+
+/// The requests waiting for the response to a coalesced request.
+pub(super) struct CoalescedWaiters {
+    peer: PeerId,
+    key: XorName,
+    senders: Vec<oneshot::Sender<Result<Response>>>,
+}
+
+impl NetworkSwarmLoop {
+    /// Joins the request for `key` in flight to `peer`, if it was sent within the coalescing
+    /// window, to be answered with its response. The sender is handed back otherwise, for the
+    /// request to be sent.
+    pub(super) fn join_coalesced_request(
+        &mut self,
+        peer: PeerId,
+        key: XorName,
+        sender: oneshot::Sender<Result<Response>>,
+    ) -> Option<oneshot::Sender<Result<Response>>> {
+        let window = match self.request_coalescing_window {
+            Some(window) => window,
+            None => return Some(sender),
+        };
+        let request_id = match self.coalesced_requests.get(&(peer, key)) {
+            Some(request) if request.sent_at.elapsed() < window => request.request_id,
+            _ => return Some(sender),
+        };
+        match self.coalesced_waiters.get_mut(&request_id) {
+            Some(waiters) => {
+                trace!("Coalescing the request for {key:?} to {peer:?} into {request_id:?}");
+                waiters.senders.push(sender);
+                None
+            }
+            None => Some(sender),
+        }
+    }
+
+    /// Makes the request for `key` just sent to `peer` joinable by the next identical requests.
+    pub(super) fn track_coalesced_request(
+        &mut self,
+        peer: PeerId,
+        key: XorName,
+        request_id: RequestId,
+    ) {
+        if self.request_coalescing_window.is_none() {
+            return;
+        }
+        // A previous request still in flight is out of the window, its waiters are answered
+        // along with it all the same.
+        let _ = self.coalesced_requests.insert(
+            (peer, key),
+            CoalescedRequest {
+                request_id,
+                sent_at: Instant::now(),
+            },
+        );
+        let _ = self.coalesced_waiters.insert(
+            request_id,
+            CoalescedWaiters {
+                peer,
+                key,
+                senders: Vec::new(),
+            },
+        );
+    }
+
+    /// Returns the senders of the requests that joined the completed one.
+    pub(super) fn take_coalesced_waiters(
+        &mut self,
+        request_id: &RequestId,
+    ) -> Vec<oneshot::Sender<Result<Response>>> {
+        let waiters = match self.coalesced_waiters.remove(request_id) {
+            Some(waiters) => waiters,
+            None => return Vec::new(),
+        };
+        let key = (waiters.peer, waiters.key);
+        if self
+            .coalesced_requests
+            .get(&key)
+            .is_some_and(|request| request.request_id == *request_id)
+        {
+            let _ = self.coalesced_requests.remove(&key);
+        }
+        waiters.senders
+    }
+
+    /// Carries the requests that joined `request_id` over to `retry_id`, which sent it again.
+    pub(super) fn rekey_coalesced_request(&mut self, request_id: &RequestId, retry_id: RequestId) {
+        if let Some(waiters) = self.coalesced_waiters.remove(request_id) {
+            if let Some(request) = self
+                .coalesced_requests
+                .get_mut(&(waiters.peer, waiters.key))
+                .filter(|request| request.request_id == *request_id)
+            {
+                request.request_id = retry_id;
+            }
+            let _ = self.coalesced_waiters.insert(retry_id, waiters);
+        }
+    }
+
+    /// The number of requests waiting for the response to a coalesced request.
+    pub(super) fn coalesced_waiter_count(&self) -> usize {
+        self.coalesced_waiters
+            .values()
+            .map(|waiters| waiters.senders.len())
+            .sum()
+    }
+
+    /// Fails the requests waiting for a coalesced request with `Error::Shutdown`.
+    pub(super) fn abandon_coalesced_requests(&mut self) {
+        self.coalesced_requests.clear();
+        for (_, waiters) in self.coalesced_waiters.drain() {
+            for sender in waiters.senders {
+                let _ = sender.send(Err(Error::Shutdown));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        error::Result, msg::Request, testing::isolated_config, NetworkConfig, NetworkSwarmLoop,
+    };
+    use futures::channel::oneshot;
+    use libp2p::{request_response::RequestId, PeerId};
+    use std::time::Duration;
+    use xor_name::XorName;
+
+    fn send_request(event_loop: &mut NetworkSwarmLoop, peer: PeerId) -> RequestId {
+        event_loop
+            .swarm
+            .behaviour_mut()
+            .request_response
+            .send_request(&peer, Request::GetDBC.into())
+    }
+
+    #[async_std::test]
+    async fn the_identical_requests_within_the_window_join_the_one_in_flight() -> Result<()> {
+        let (_network, _events, mut event_loop) = NetworkSwarmLoop::with_config(NetworkConfig {
+            request_coalescing_window: Some(Duration::from_millis(50)),
+            ..isolated_config()
+        })?;
+        let (peer, key) = (PeerId::random(), XorName([1; 32]));
+        let join = |event_loop: &mut NetworkSwarmLoop, key| {
+            let (sender, _receiver) = oneshot::channel();
+            event_loop
+                .join_coalesced_request(peer, key, sender)
+                .is_none()
+        };
+
+        // Nothing to join until a request is sent.
+        assert!(!join(&mut event_loop, key));
+        let request_id = send_request(&mut event_loop, peer);
+        event_loop.track_coalesced_request(peer, key, request_id);
+        assert!(join(&mut event_loop, key));
+        assert!(join(&mut event_loop, key));
+        // Only for the same key.
+        assert!(!join(&mut event_loop, XorName([2; 32])));
+        assert_eq!(event_loop.coalesced_waiter_count(), 2);
+
+        // The waiters follow a retry of the request.
+        let retry_id = send_request(&mut event_loop, peer);
+        event_loop.rekey_coalesced_request(&request_id, retry_id);
+        assert!(event_loop.take_coalesced_waiters(&request_id).is_empty());
+        assert_eq!(event_loop.take_coalesced_waiters(&retry_id).len(), 2);
+        // Once completed, the request can no longer be joined.
+        assert!(!join(&mut event_loop, key));
+
+        let request_id = send_request(&mut event_loop, peer);
+        event_loop.track_coalesced_request(peer, key, request_id);
+        async_std::task::sleep(Duration::from_millis(60)).await;
+        assert!(!join(&mut event_loop, key));
+        Ok(())
+    }
+
+    #[test]
+    fn nothing_is_coalesced_without_a_window() -> Result<()> {
+        let (_network, _events, mut event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let (peer, key) = (PeerId::random(), XorName([1; 32]));
+        let request_id = send_request(&mut event_loop, peer);
+        event_loop.track_coalesced_request(peer, key, request_id);
+        let (sender, _receiver) = oneshot::channel();
+        assert!(event_loop
+            .join_coalesced_request(peer, key, sender)
+            .is_some());
+        Ok(())
+    }
+}
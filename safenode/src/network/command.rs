@@ -7,16 +7,29 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::{
+    address_book::AddressEntry,
+    config::DataClass,
     error::Error,
-    msg::{Request, Response},
-    NetworkSwarmLoop,
+    identity_bundle::IdentityBundle,
+    metrics::Metrics,
+    msg::{RawResponse, Request, Response, MSG_PROTOCOL_NAME, RAW_PROTOCOL_NAME},
+    op_trace::OpSpan,
+    queries::QueryInfo,
+    shutdown::ShutdownSummary,
+    subscription::Subscriber,
+    testing::{Direction, WireMessage},
+    Distance, NetworkEvent, NetworkSwarmLoop,
 };
 use crate::network::error::Result;
 use futures::channel::oneshot;
-use libp2p::{multiaddr::Protocol, request_response::ResponseChannel, Multiaddr, PeerId};
-use std::collections::{hash_map, HashSet};
-use tracing::warn;
-use xor_name::XorName;
+use libp2p::{
+    kad::{store::RecordStore, Quorum, Record, RecordKey},
+    request_response::ResponseChannel,
+    Multiaddr, PeerId,
+};
+use std::{collections::HashSet, time::Duration};
+use tracing::{info, warn};
+use xor_name::{Prefix, XorName};
 
 /// Commands to send to the Swarm
 #[derive(Debug)]
@@ -30,51 +43,302 @@ pub(crate) enum SwarmCmd {
         peer_addr: Multiaddr,
         sender: oneshot::Sender<Result<()>>,
     },
+    Bootstrap {
+        peers: Vec<(PeerId, Multiaddr)>,
+        sender: oneshot::Sender<Result<()>>,
+    },
+    AbandonBootstrap,
+    ConnectedPeersCount {
+        sender: oneshot::Sender<usize>,
+    },
+    Listeners {
+        sender: oneshot::Sender<Vec<Multiaddr>>,
+    },
+    SelectPeers {
+        peers: Vec<PeerId>,
+        count: usize,
+        sender: oneshot::Sender<Vec<PeerId>>,
+    },
+    ActiveProtocols {
+        sender: oneshot::Sender<Vec<String>>,
+    },
+    SetProtocolEnabled {
+        name: String,
+        enabled: bool,
+        sender: oneshot::Sender<Result<()>>,
+    },
+    Health {
+        sender: oneshot::Sender<()>,
+    },
+    ActiveQueries {
+        sender: oneshot::Sender<Vec<QueryInfo>>,
+    },
+    IsReady {
+        sender: oneshot::Sender<bool>,
+    },
+    RoutingTablePeers {
+        sender: oneshot::Sender<HashSet<PeerId>>,
+    },
+    ImportRoutingSnapshot {
+        peers: Vec<(PeerId, Vec<Multiaddr>)>,
+        sender: oneshot::Sender<usize>,
+    },
+    KnownAddresses {
+        peer: PeerId,
+        sender: oneshot::Sender<Vec<AddressEntry>>,
+    },
+    ExportIdentityBundle {
+        sender: oneshot::Sender<Result<IdentityBundle>>,
+    },
+    Subscribe {
+        subscriber: Subscriber,
+    },
+    CountRecordsInRange {
+        prefix: Prefix,
+        sender: oneshot::Sender<usize>,
+    },
+    Pause {
+        sender: oneshot::Sender<Result<()>>,
+    },
+    Resume {
+        sender: oneshot::Sender<Result<()>>,
+    },
     StoreData {
         xor_name: XorName,
+        class: DataClass,
+        sender: oneshot::Sender<Result<()>>,
+    },
+    StoreDataPersistent {
+        xor_name: XorName,
+        sender: oneshot::Sender<Result<()>>,
+    },
+    TouchRecord {
+        key: XorName,
         sender: oneshot::Sender<Result<()>>,
     },
     GetDataProviders {
         xor_name: XorName,
-        sender: oneshot::Sender<HashSet<PeerId>>,
+        sender: oneshot::Sender<Result<HashSet<PeerId>>>,
+        op: OpSpan,
+    },
+    PutRecord {
+        key: XorName,
+        value: Vec<u8>,
+        quorum: Quorum,
+        sender: oneshot::Sender<Result<()>>,
+    },
+    GetRecord {
+        key: XorName,
+        sender: oneshot::Sender<Result<Option<super::Record>>>,
+    },
+    GetClosestPeers {
+        key: XorName,
+        sender: oneshot::Sender<Result<Vec<(PeerId, Distance)>>>,
     },
     SendRequest {
         req: Request,
         peer: PeerId,
+        coalesce_key: Option<XorName>,
         sender: oneshot::Sender<Result<Response>>,
+        op: OpSpan,
     },
     SendResponse {
         resp: Response,
+        peer: PeerId,
         channel: ResponseChannel<Response>,
     },
+    SendRawRequest {
+        bytes: Vec<u8>,
+        peer: PeerId,
+        sender: oneshot::Sender<Result<Vec<u8>>>,
+    },
+    SendRawResponse {
+        bytes: Vec<u8>,
+        channel: ResponseChannel<RawResponse>,
+    },
+    GossipSubscribe {
+        topic: String,
+        sender: oneshot::Sender<Result<()>>,
+    },
+    GossipPublish {
+        topic: String,
+        data: Vec<u8>,
+        sender: oneshot::Sender<Result<()>>,
+    },
+    Shutdown {
+        drain_timeout: Duration,
+        sender: oneshot::Sender<Result<ShutdownSummary>>,
+    },
+}
+
+impl SwarmCmd {
+    /// Fails the command with the given error, for the commands that start new outbound work,
+    /// i.e. listening, dialing, queries and requests. Returns the command back otherwise.
+    fn reject_outbound(self, error: fn() -> Error) -> Option<Self> {
+        match self {
+            SwarmCmd::StartListening { sender, .. }
+            | SwarmCmd::Dial { sender, .. }
+            | SwarmCmd::Bootstrap { sender, .. }
+            | SwarmCmd::StoreData { sender, .. }
+            | SwarmCmd::StoreDataPersistent { sender, .. }
+            | SwarmCmd::TouchRecord { sender, .. }
+            | SwarmCmd::PutRecord { sender, .. }
+            | SwarmCmd::GossipPublish { sender, .. } => {
+                let _ = sender.send(Err(error()));
+            }
+            SwarmCmd::GetDataProviders { sender, .. } => {
+                let _ = sender.send(Err(error()));
+            }
+            SwarmCmd::GetRecord { sender, .. } => {
+                let _ = sender.send(Err(error()));
+            }
+            SwarmCmd::GetClosestPeers { sender, .. } => {
+                let _ = sender.send(Err(error()));
+            }
+            SwarmCmd::SendRequest { sender, .. } => {
+                let _ = sender.send(Err(error()));
+            }
+            SwarmCmd::SendRawRequest { sender, .. } => {
+                let _ = sender.send(Err(error()));
+            }
+            cmd => return Some(cmd),
+        }
+        None
+    }
+
+    /// Fails the command with `Error::Overloaded`, for the outbound operations that can be
+    /// postponed while shedding load, see `LoadSheddingConfig`.
+    fn reject_if_overloaded(self) -> Option<Self> {
+        match self {
+            // The connectivity is kept up while shedding load.
+            cmd @ (SwarmCmd::StartListening { .. }
+            | SwarmCmd::Dial { .. }
+            | SwarmCmd::Bootstrap { .. }) => Some(cmd),
+            cmd => cmd.reject_outbound(|| Error::Overloaded),
+        }
+    }
+
+    /// Fails the command with `Error::Shutdown`, for the commands that are not allowed once
+    /// a graceful shutdown started. Only the responses and the queries on the local state are
+    /// still served while draining.
+    fn reject_if_shutting_down(self) -> Option<Self> {
+        match self {
+            SwarmCmd::Pause { sender } | SwarmCmd::Resume { sender } => {
+                let _ = sender.send(Err(Error::Shutdown));
+                None
+            }
+            SwarmCmd::Shutdown { sender, .. } => {
+                let _ = sender.send(Err(Error::Shutdown));
+                None
+            }
+            cmd => cmd.reject_outbound(|| Error::Shutdown),
+        }
+    }
 }
 
 impl NetworkSwarmLoop {
-    pub(crate) fn handle_command(&mut self, command: SwarmCmd) -> Result<(), Error> {
+    pub(crate) async fn handle_command(&mut self, command: SwarmCmd) -> Result<(), Error> {
+        let command = if self.shutdown.is_some() {
+            match command.reject_if_shutting_down() {
+                Some(command) => command,
+                None => return Ok(()),
+            }
+        } else if self.paused_listen_addrs.is_some() {
+            match command.reject_outbound(|| Error::Paused) {
+                Some(command) => command,
+                None => return Ok(()),
+            }
+        } else if self.shedding_load {
+            match command.reject_if_overloaded() {
+                Some(command) => command,
+                None => return Ok(()),
+            }
+        } else {
+            command
+        };
+
         match command {
             SwarmCmd::StartListening { addr, sender } => {
-                let _ = match self.swarm.listen_on(addr) {
-                    Ok(_) => sender.send(Ok(())),
+                let _ = match self.swarm.listen_on(addr.clone()) {
+                    Ok(listener_id) => {
+                        let _ = self.listeners.insert(listener_id, addr);
+                        sender.send(Ok(()))
+                    }
                     Err(e) => sender.send(Err(e.into())),
                 };
             }
+            SwarmCmd::Pause { sender } => {
+                if self.paused_listen_addrs.is_some() {
+                    let _ = sender.send(Ok(()));
+                    return Ok(());
+                }
+                let mut addrs = Vec::new();
+                for (listener_id, addr) in self.listeners.drain() {
+                    let _ = self.swarm.remove_listener(listener_id);
+                    addrs.push(addr);
+                }
+                let connected_peers: Vec<PeerId> = self.swarm.connected_peers().copied().collect();
+                for peer_id in connected_peers {
+                    let _ = self.swarm.disconnect_peer_id(peer_id);
+                }
+                info!("Networking paused");
+                self.paused_listen_addrs = Some(addrs);
+                let _ = sender.send(Ok(()));
+            }
+            SwarmCmd::Resume { sender } => {
+                let addrs = self.paused_listen_addrs.take().unwrap_or_default();
+                let mut result = Ok(());
+                for addr in addrs {
+                    match self.swarm.listen_on(addr.clone()) {
+                        Ok(listener_id) => {
+                            let _ = self.listeners.insert(listener_id, addr);
+                        }
+                        Err(err) => {
+                            warn!("Failed to listen on {addr:?} when resuming: {err:?}");
+                            result = Err(err.into());
+                        }
+                    }
+                }
+                info!("Networking resumed");
+                let _ = sender.send(result);
+            }
             SwarmCmd::Dial {
                 peer_id,
                 peer_addr,
                 sender,
             } => {
-                if let hash_map::Entry::Vacant(e) = self.pending_dial.entry(peer_id) {
+                let _ = self.explicit_peers.insert(peer_id);
+                // No new connection would be established, hence the dial would never complete.
+                if self.swarm.is_connected(&peer_id) {
                     let _routing_update = self
                         .swarm
                         .behaviour_mut()
                         .kademlia
-                        .add_address(&peer_id, peer_addr.clone());
-                    match self
+                        .add_address(&peer_id, peer_addr);
+                    let _ = sender.send(Ok(()));
+                    self.send_event(NetworkEvent::PeerAlreadyConnected { peer_id })
+                        .await?;
+                } else if self.dial_limit_reached() {
+                    self.queue_dial(peer_id, peer_addr, sender);
+                } else if !self.pending_dial.contains_key(&peer_id) {
+                    let _routing_update = self
                         .swarm
-                        .dial(peer_addr.with(Protocol::P2p(peer_id.into())))
-                    {
+                        .behaviour_mut()
+                        .kademlia
+                        .add_address(&peer_id, peer_addr.clone());
+                    // The known addresses are dialed too, in case the given one is stale.
+                    let mut addrs = vec![peer_addr.clone()];
+                    addrs.extend(
+                        self.address_book
+                            .addresses(&peer_id)
+                            .into_iter()
+                            .map(|entry| entry.addr)
+                            .filter(|addr| *addr != peer_addr),
+                    );
+                    match self.dial_addresses(peer_id, addrs) {
                         Ok(()) => {
-                            let _ = e.insert(sender);
+                            self.track_dial_deadline(peer_id);
+                            let _ = self.pending_dial.insert(peer_id, sender);
                         }
                         Err(e) => {
                             let _ = sender.send(Err(e.into()));
@@ -84,34 +348,243 @@ impl NetworkSwarmLoop {
                     warn!("Already dialing peer.");
                 }
             }
+            SwarmCmd::Bootstrap { peers, sender } => {
+                self.start_bootstrap(peers, Some(sender)).await?;
+            }
+            SwarmCmd::AbandonBootstrap => self.abandon_bootstrap(),
+            SwarmCmd::ConnectedPeersCount { sender } => {
+                let _ = sender.send(self.swarm.connected_peers().count());
+            }
+            SwarmCmd::Listeners { sender } => {
+                let _ = sender.send(self.listen_addrs.clone());
+            }
+            SwarmCmd::SelectPeers {
+                peers,
+                count,
+                sender,
+            } => {
+                let _ = sender.send(self.select_peers(peers, count));
+            }
+            SwarmCmd::ActiveProtocols { sender } => {
+                let _ = sender.send(self.active_protocols());
+            }
+            SwarmCmd::SetProtocolEnabled {
+                name,
+                enabled,
+                sender,
+            } => {
+                let _ = sender.send(self.set_protocol_enabled(&name, enabled).await);
+            }
+            SwarmCmd::GossipSubscribe { topic, sender } => {
+                let _ = sender.send(self.subscribe_topic(&topic));
+            }
+            SwarmCmd::GossipPublish {
+                topic,
+                data,
+                sender,
+            } => {
+                let _ = sender.send(self.publish_to_topic(topic, data));
+            }
+            SwarmCmd::Health { sender } => {
+                let _ = sender.send(());
+            }
+            SwarmCmd::ActiveQueries { sender } => {
+                let _ = sender.send(self.active_queries());
+            }
+            SwarmCmd::ExportIdentityBundle { sender } => {
+                let _ = sender.send(self.export_identity_bundle());
+            }
+            SwarmCmd::KnownAddresses { peer, sender } => {
+                let _ = sender.send(self.address_book.addresses(&peer));
+            }
+            SwarmCmd::ImportRoutingSnapshot { peers, sender } => {
+                let _ = sender.send(self.import_routing_snapshot(peers));
+            }
+            SwarmCmd::RoutingTablePeers { sender } => {
+                let peers = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .kbuckets()
+                    .flat_map(|bucket| {
+                        bucket
+                            .iter()
+                            .map(|entry| *entry.node.key.preimage())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect();
+                let _ = sender.send(peers);
+            }
+            SwarmCmd::IsReady { sender } => {
+                let routing_table_peers: usize = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .kbuckets()
+                    .map(|bucket| bucket.num_entries())
+                    .sum();
+                let _ =
+                    sender.send(self.bootstrapped && routing_table_peers >= self.min_ready_peers);
+            }
+            SwarmCmd::Subscribe { subscriber } => self.subscribers.push(subscriber),
+            SwarmCmd::CountRecordsInRange { prefix, sender } => {
+                let count = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .store_mut()
+                    .records()
+                    // Only the records keyed by an `XorName` can fall within the prefix.
+                    .filter_map(|record| <[u8; 32]>::try_from(record.key.as_ref()).ok())
+                    .filter(|bytes| prefix.matches(&XorName(*bytes)))
+                    .count();
+                let _ = sender.send(count);
+            }
             // todo: the `provider` api should not be used for chunks/dbcs.
             // 1. get the closest nodes to the data
             // 2. store data in them directly, not via provider
-            SwarmCmd::StoreData { xor_name, sender } => {
+            SwarmCmd::StoreDataPersistent { xor_name, sender } => {
+                if let Err(err) = self.verify_held(xor_name).await {
+                    let _ = sender.send(Err(err));
+                    return Ok(());
+                }
+                if let Err(err) = self.log_provided(xor_name) {
+                    let _ = sender.send(Err(err));
+                    return Ok(());
+                }
+                let _ = sender.send(Ok(()));
+                self.start_persistent_store(xor_name).await?;
+            }
+            SwarmCmd::StoreData {
+                xor_name,
+                class,
+                sender,
+            } => {
+                if let Err(err) = self.verify_held(xor_name).await {
+                    let _ = sender.send(Err(err));
+                    return Ok(());
+                }
+                if let Err(err) = self.log_provided(xor_name) {
+                    let _ = sender.send(Err(err));
+                    return Ok(());
+                }
+                let record_key: RecordKey = xor_name.0.to_vec().into();
                 let query_id = self
                     .swarm
                     .behaviour_mut()
                     .kademlia
-                    .start_providing(xor_name.0.to_vec().into())?;
+                    .start_providing(record_key.clone())?;
+                self.track_provided(record_key, class);
                 let _ = self.pending_start_providing.insert(query_id, sender);
             }
-            SwarmCmd::GetDataProviders { xor_name, sender } => {
+            SwarmCmd::TouchRecord { key, sender } => {
+                let record_key: RecordKey = key.0.to_vec().into();
+                let is_provided = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .store_mut()
+                    .provided()
+                    .any(|record| record.key == record_key);
+                if !is_provided {
+                    let _ = sender.send(Err(Error::RecordNotProvided(key)));
+                    return Ok(());
+                }
+                if let Err(err) = self.verify_held(key).await {
+                    let _ = sender.send(Err(err));
+                    return Ok(());
+                }
                 let query_id = self
                     .swarm
                     .behaviour_mut()
                     .kademlia
-                    .get_providers(xor_name.0.to_vec().into());
+                    .start_providing(record_key.clone())?;
+                self.track_republished(record_key);
+                let _ = self.pending_start_providing.insert(query_id, sender);
+            }
+            SwarmCmd::GetDataProviders {
+                xor_name,
+                sender,
+                mut op,
+            } => {
+                op.dequeued();
+                let query_id = op.in_scope(|| {
+                    self.swarm
+                        .behaviour_mut()
+                        .kademlia
+                        .get_providers(xor_name.0.to_vec().into())
+                });
+                self.op_traces.query_started(query_id, &op);
                 let _ = self.pending_get_providers.insert(query_id, sender);
             }
-            SwarmCmd::SendRequest { req, peer, sender } => {
-                let request_id = self
+            SwarmCmd::PutRecord {
+                key,
+                value,
+                quorum,
+                sender,
+            } => {
+                let record = Record::new(key.0.to_vec(), value);
+                if !self.accept_record(&record, None).await? {
+                    let _ = sender.send(Err(Error::RecordExists(key)));
+                    return Ok(());
+                }
+                let query_id = self
                     .swarm
                     .behaviour_mut()
-                    .request_response
-                    .send_request(&peer, req);
+                    .kademlia
+                    .put_record(record, quorum)?;
+                let _ = self.pending_put_record.insert(query_id, sender);
+            }
+            SwarmCmd::GetRecord { key, sender } => {
+                let query_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .get_record(key.0.to_vec().into());
+                let _ = self.pending_get_record.insert(query_id, sender);
+            }
+            SwarmCmd::GetClosestPeers { key, sender } => {
+                let query_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .get_closest_peers(key.0.to_vec());
+                let _ = self.pending_get_closest_peers.insert(query_id, sender);
+            }
+            SwarmCmd::SendRequest {
+                req,
+                peer,
+                coalesce_key,
+                sender,
+                mut op,
+            } => {
+                op.dequeued();
+                if !self.protocol_enabled(MSG_PROTOCOL_NAME) {
+                    let _ = sender.send(Err(Error::ProtocolDisabled(MSG_PROTOCOL_NAME)));
+                    return Ok(());
+                }
+                let sender = match coalesce_key {
+                    Some(key) => match self.join_coalesced_request(peer, key, sender) {
+                        Some(sender) => sender,
+                        None => return Ok(()),
+                    },
+                    None => sender,
+                };
+                let request_id = op.in_scope(|| self.send_msg_request(peer, req.clone(), &op));
+                if let Some(key) = coalesce_key {
+                    self.track_coalesced_request(peer, key, request_id);
+                }
+                self.track_request_deadline(request_id, peer, req, op);
                 let _ = self.pending_requests.insert(request_id, sender);
             }
-            SwarmCmd::SendResponse { resp, channel } => {
+            SwarmCmd::SendResponse {
+                resp,
+                peer,
+                channel,
+            } => {
+                self.record_message(peer, Direction::Outbound, || {
+                    WireMessage::Response(resp.clone())
+                });
                 self.swarm
                     .behaviour_mut()
                     .request_response
@@ -120,7 +593,130 @@ impl NetworkSwarmLoop {
                         Error::Other("Connection to peer to be still open.".to_string())
                     })?;
             }
+            SwarmCmd::SendRawRequest {
+                bytes,
+                peer,
+                sender,
+            } => {
+                if !self.protocol_enabled(RAW_PROTOCOL_NAME) {
+                    let _ = sender.send(Err(Error::ProtocolDisabled(RAW_PROTOCOL_NAME)));
+                    return Ok(());
+                }
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .raw_request_response
+                    .send_request(&peer, bytes);
+                Metrics::inc(&self.metrics.requests_sent);
+                let _ = self.pending_raw_requests.insert(request_id, sender);
+            }
+            SwarmCmd::SendRawResponse { bytes, channel } => {
+                self.swarm
+                    .behaviour_mut()
+                    .raw_request_response
+                    .send_response(channel, RawResponse::Bytes(bytes))
+                    .map_err(|_| {
+                        Error::Other("Connection to peer to be still open.".to_string())
+                    })?;
+            }
+            SwarmCmd::Shutdown {
+                drain_timeout,
+                sender,
+            } => self.start_shutdown(drain_timeout, sender),
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        error::{Error, Result},
+        testing::{isolated_config, listen_addr},
+        NetworkEvent, NetworkSwarmLoop,
+    };
+    use async_std::{future::timeout, task};
+    use futures::StreamExt;
+    use libp2p::PeerId;
+    use std::time::Duration;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[async_std::test]
+    async fn dialing_an_already_connected_peer_completes_right_away() -> Result<()> {
+        let (mut dialer, mut dialer_events, dialer_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let (mut listener, listener_events, listener_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _dialer_loop = task::spawn(dialer_loop.run());
+        let _listener_loop = task::spawn(listener_loop.run());
+        let _listener_events = task::spawn(listener_events.for_each(|_| async {}));
+
+        let addr = listen_addr(&mut listener).await?;
+        let peer_id = listener.local_peer_id;
+
+        // Keep draining the events of the dialer, so its loop makes progress.
+        let reused = task::spawn(async move {
+            while let Some(event) = dialer_events.next().await {
+                if let NetworkEvent::PeerAlreadyConnected { peer_id } = event {
+                    return Some(peer_id);
+                }
+            }
+            None
+        });
+
+        timeout(TEST_TIMEOUT, dialer.dial(peer_id, addr.clone()))
+            .await
+            .expect("the first dial to complete")?;
+        timeout(TEST_TIMEOUT, dialer.dial(peer_id, addr))
+            .await
+            .expect("the dial to the connected peer to complete")?;
+        let reused = timeout(TEST_TIMEOUT, reused)
+            .await
+            .expect("the connection reuse to be reported");
+        assert_eq!(reused, Some(peer_id));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn dialing_an_address_of_another_peer_fails_with_a_mismatch() -> Result<()> {
+        let (mut dialer, mut dialer_events, dialer_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let (mut listener, listener_events, listener_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _dialer_loop = task::spawn(dialer_loop.run());
+        let _listener_loop = task::spawn(listener_loop.run());
+        let _listener_events = task::spawn(listener_events.for_each(|_| async {}));
+
+        let addr = listen_addr(&mut listener).await?;
+        let actual = listener.local_peer_id;
+        let expected = PeerId::random();
+
+        let reported = task::spawn(async move {
+            while let Some(event) = dialer_events.next().await {
+                if let NetworkEvent::PeerIdMismatch {
+                    expected, actual, ..
+                } = event
+                {
+                    return Some((expected, actual));
+                }
+            }
+            None
+        });
+
+        let result = timeout(TEST_TIMEOUT, dialer.dial(expected, addr))
+            .await
+            .expect("the dial to complete");
+        assert!(matches!(
+            result,
+            Err(Error::PeerIdMismatch { expected: e, actual: a }) if *e == expected && *a == actual
+        ));
+        let reported = timeout(TEST_TIMEOUT, reported)
+            .await
+            .expect("the mismatch to be reported");
+        assert_eq!(reported, Some((expected, actual)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::command::SwarmCmd;
+use futures::{channel::mpsc, SinkExt};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// The sending end of the command channel, keeping count of the commands not yet picked up by
+/// the `NetworkSwarmLoop`, for the producers to tell how backed up it is.
+#[derive(Clone)]
+pub(crate) struct CommandSender {
+    sender: mpsc::Sender<SwarmCmd>,
+    queued: Arc<AtomicUsize>,
+    capacity: usize,
+}
+
+/// The receiving end of the command channel, see `CommandSender`.
+pub(super) struct CommandReceiver {
+    pub(super) receiver: mpsc::Receiver<SwarmCmd>,
+    queued: Arc<AtomicUsize>,
+}
+
+/// Creates the command channel, buffering up to `capacity` commands.
+pub(super) fn command_channel(capacity: usize) -> (CommandSender, CommandReceiver) {
+    let (sender, receiver) = mpsc::channel(capacity);
+    let queued = Arc::new(AtomicUsize::new(0));
+    (
+        CommandSender {
+            sender,
+            queued: queued.clone(),
+            capacity,
+        },
+        CommandReceiver { receiver, queued },
+    )
+}
+
+impl CommandSender {
+    /// Sends the command, waiting for room in the channel if it is full.
+    pub(super) async fn send(&mut self, cmd: SwarmCmd) -> Result<(), mpsc::SendError> {
+        let _ = self.queued.fetch_add(1, Ordering::Relaxed);
+        let result = self.sender.send(cmd).await;
+        if result.is_err() {
+            let _ = self.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// How full the channel is, from 0.0 for empty to 1.0 for full, the senders then waiting.
+    pub(super) fn pressure(&self) -> f32 {
+        if self.capacity == 0 {
+            return if self.queued.load(Ordering::Relaxed) == 0 {
+                0.0
+            } else {
+                1.0
+            };
+        }
+        let queued = self.queued.load(Ordering::Relaxed).min(self.capacity);
+        queued as f32 / self.capacity as f32
+    }
+}
+
+impl CommandReceiver {
+    /// Accounts for a command received from the channel.
+    pub(super) fn received(&self) {
+        let _ = self.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        error::{Error, Result},
+        testing::{isolated_config, listen_addr},
+        NetworkConfig, NetworkEvent, NetworkSwarmLoop, Request, Response,
+    };
+    use async_std::{future::timeout, task};
+    use futures::{future, StreamExt};
+    use std::time::Duration;
+    use xor_name::XorName;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn zero_channel_capacities_are_refused() {
+        for config in [
+            NetworkConfig {
+                command_channel_capacity: 0,
+                ..isolated_config()
+            },
+            NetworkConfig {
+                event_channel_capacity: 0,
+                ..isolated_config()
+            },
+        ] {
+            assert!(matches!(
+                NetworkSwarmLoop::with_config(config),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[async_std::test]
+    async fn concurrent_calls_make_progress_through_small_channels() -> Result<()> {
+        // Far fewer slots than calls in flight, for the callers to wait on the full channels.
+        let (mut requester, requester_events, requester_loop) =
+            NetworkSwarmLoop::with_config(NetworkConfig {
+                command_channel_capacity: 2,
+                event_channel_capacity: 2,
+                ..isolated_config()
+            })?;
+        // The responder calls into the `Network` from its event consumer, so its channels have
+        // to be large enough not to both fill up; it serves all the requests at once.
+        let (mut responder, mut responder_events, responder_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _requester_loop = task::spawn(requester_loop.run());
+        let _responder_loop = task::spawn(responder_loop.run());
+        let _requester_events = task::spawn(requester_events.for_each(|_| async {}));
+
+        let addr = listen_addr(&mut responder).await?;
+        let responder_id = responder.local_peer_id();
+        let mut responding = responder.clone();
+        let _responder = task::spawn(async move {
+            while let Some(event) = responder_events.next().await {
+                if let NetworkEvent::RequestReceived { channel, .. } = event {
+                    let _ = responding.send_response(Response::Pong, channel).await;
+                }
+            }
+        });
+        timeout(TEST_TIMEOUT, requester.dial(responder_id, addr))
+            .await
+            .expect("the dial to complete")?;
+
+        let requests = (0..100).map(|_| {
+            let mut requester = requester.clone();
+            task::spawn(async move { requester.send_request(Request::GetDBC, responder_id).await })
+        });
+        let stores = (0..20).map(|_| {
+            let mut requester = requester.clone();
+            let xor_name = XorName::random(&mut rand::thread_rng());
+            task::spawn(async move { requester.store_data(xor_name).await })
+        });
+        let (responses, stored) = timeout(
+            TEST_TIMEOUT,
+            future::join(future::join_all(requests), future::join_all(stores)),
+        )
+        .await
+        .expect("all the calls to complete");
+
+        for response in responses {
+            assert_eq!(response?, Response::Pong);
+        }
+        for result in stored {
+            result?;
+        }
+        Ok(())
+    }
+}
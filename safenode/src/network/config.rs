@@ -0,0 +1,648 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+#[cfg(feature = "chaos")]
+use super::ChaosConfig;
+use super::{
+    error::{Error, Result},
+    msg::{MsgFormat, MAX_MSG_SIZE},
+};
+use crate::storage::DataStorage;
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use std::{net::Ipv4Addr, path::PathBuf, time::Duration};
+
+/// The maximum length, in bytes, of the `NetworkConfig::node_metadata`.
+pub const MAX_NODE_METADATA_LEN: usize = 128;
+
+/// The protocol version advertised through identify.
+pub(super) const IDENTIFY_PROTOCOL_VERSION: &str = "/safe/1.0.0";
+
+/// The prefix of the identify protocol version, followed by the `ProtocolVersion`.
+const PROTOCOL_VERSION_PREFIX: &str = "/safe/";
+
+/// The agent version prefix advertised through identify.
+const AGENT_VERSION_PREFIX: &str = concat!("safenode/", env!("CARGO_PKG_VERSION"));
+
+/// The default deadline for the whole `Network::bootstrap` operation.
+pub const DEFAULT_BOOTSTRAP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The delay between the dials to the addresses of a peer recommended by RFC 8305, for the
+/// `NetworkConfig::dial_stagger`.
+pub const DEFAULT_DIAL_STAGGER: Duration = Duration::from_millis(250);
+
+/// The default number of commands buffered between the `Network` handles and the event loop.
+pub const DEFAULT_COMMAND_CHANNEL_CAPACITY: usize = 64;
+
+/// The default number of events buffered between the event loop and each of the event streams.
+pub const DEFAULT_EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Returns the addresses listened on by default, one per transport: all the interfaces, on
+/// whatever port the OS assigns.
+pub(super) fn default_listen_addrs(transport: TransportKind) -> Vec<Multiaddr> {
+    let quic = Multiaddr::empty()
+        .with(Protocol::Ip4(Ipv4Addr::UNSPECIFIED))
+        .with(Protocol::Udp(0))
+        .with(Protocol::QuicV1);
+    let tcp = Multiaddr::empty()
+        .with(Protocol::Ip4(Ipv4Addr::UNSPECIFIED))
+        .with(Protocol::Tcp(0));
+    match transport {
+        TransportKind::Quic => vec![quic],
+        TransportKind::Tcp => vec![tcp],
+        TransportKind::QuicAndTcp => vec![quic, tcp],
+    }
+}
+
+/// The time `Network::health` waits for the event loop to respond.
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Configuration used to build the `NetworkSwarmLoop`
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    /// The maximum time `Network::bootstrap` waits for the routing table to be populated before
+    /// returning `Error::BootstrapTimeout`.
+    pub bootstrap_timeout: Duration,
+    /// The address to listen on, e.g. to pin the port; `None` listens on all the interfaces, on
+    /// whatever port the OS assigns, over each of the transports, i.e. `/ip4/0.0.0.0/udp/0/quic-v1`
+    /// and/or `/ip4/0.0.0.0/tcp/0`. More addresses can be listened on through
+    /// `Network::start_listening`.
+    pub listen_addr: Option<Multiaddr>,
+    /// The transports to listen on and dial the peers over. The addresses listened on, whatever
+    /// their transport, are advertised to the local peers through mDNS.
+    pub transport: TransportKind,
+    /// The maximum number of bootstrap peers dialed at once by `Network::bootstrap`; the
+    /// remaining ones are dialed as the previous dials complete.
+    pub bootstrap_dial_concurrency: usize,
+    /// The peers to join the network through once the `NetworkSwarmLoop` runs, as per
+    /// `Network::bootstrap`, e.g. for the nodes that can't discover each other through mDNS.
+    /// The outcome is reported by a `NetworkEvent::BootstrapComplete`; an empty list doesn't
+    /// bootstrap at all.
+    pub bootstrap_peers: Vec<(PeerId, Multiaddr)>,
+    /// The delay between the dials to the addresses of a peer by `Network::dial`, each address
+    /// being dialed once the previous ones failed or took this long to connect, happy-eyeballs
+    /// style, e.g. `DEFAULT_DIAL_STAGGER`. The connections of the dials still in flight once
+    /// connected are refused. `None`, the default, dials all the addresses at once.
+    pub dial_stagger: Option<Duration>,
+    /// The maximum number of connections being established at once, whoever dialed them, e.g.
+    /// Kademlia or the request protocols dialing on their own; `None` doesn't limit them.
+    /// Beyond it, the `Network::dial`s and the bootstrap dials are queued until the pending
+    /// connections settle, while those of the protocols are refused, failing as any dial does.
+    pub max_pending_dials: Option<usize>,
+    /// How long a request sent through `Network::send_coalesced_request` can be joined by the
+    /// requests to the same peer with the same key, these being answered with its response
+    /// instead of being sent; `None` sends all the requests.
+    pub request_coalescing_window: Option<Duration>,
+    /// How long `Network::send_request` waits for a response before sending the request again,
+    /// as per `request_retries`, or failing with `Error::Timeout`; `None` waits for the request
+    /// protocol to time out on its own.
+    pub request_timeout: Option<Duration>,
+    /// The number of times a request is sent again to the peer once `request_timeout` elapsed.
+    /// The requests that are not `Request::is_idempotent` are only sent again if none of their
+    /// bytes were written to the stream.
+    pub request_retries: u32,
+    /// How long `Network::dial` waits for a connection before failing with `Error::Timeout`;
+    /// `None` waits for the transport to give up on its own.
+    pub dial_timeout: Option<Duration>,
+    /// The minimum number of peers in the routing table for `Network::is_ready` to report the
+    /// node as ready.
+    pub min_ready_peers: usize,
+    /// Run the Kademlia lookups over disjoint paths, S/Kademlia style, so that they succeed as
+    /// long as one path avoids the malicious peers, e.g. a group controlling a region of the
+    /// keyspace. Each of the parallel requests of a lookup follows its own path, through peers
+    /// the other paths don't use, which takes more hops and requests to converge: expect the
+    /// lookups to be slower and to cost more traffic. Disabled by default.
+    pub disjoint_query_paths: bool,
+    /// The schedule used to refresh the Kademlia buckets; `None` disables the periodic refresh.
+    pub bucket_refresh: Option<BucketRefreshConfig>,
+    /// Discover the peers on the local network through mDNS, adding them to the routing table
+    /// as they announce themselves. Disabled, the peers are only learnt about through
+    /// `Network::dial`, the bootstrap and Kademlia.
+    pub mdns: bool,
+    /// Disconnect from the peers whose mDNS records expired, if they were only known through
+    /// mDNS. Their addresses are always removed from the routing table.
+    pub disconnect_expired_mdns_peers: bool,
+    /// Free form metadata (e.g. operator name, region) advertised to the peers as part of the
+    /// identify agent version. Limited to `MAX_NODE_METADATA_LEN` bytes.
+    pub node_metadata: String,
+    /// Close the connections to the peers we haven't exchanged any request or response with for
+    /// this long; `None` disables reaping. The peers explicitly dialed or bootstrapped from are
+    /// never reaped.
+    pub idle_connection_timeout: Option<Duration>,
+    /// The size limits of the `Request`/`Response` protocol, applied to the encoded messages.
+    pub msg_limits: ProtocolLimits,
+    /// The format the `Request`/`Response` messages are written in; those of the peers are read
+    /// whatever their format.
+    pub msg_format: MsgFormat,
+    /// The size limits of the raw bytes protocol, see `Network::send_raw`.
+    pub raw_limits: ProtocolLimits,
+    /// The maximum number of bytes per second sent through the `Request`/`Response` and raw
+    /// protocols; `None` doesn't limit the egress.
+    ///
+    /// The limit applies per message: a write exceeding the budget waits, i.e. the outbound
+    /// messages queue up, until enough budget has been refilled. Up to one second worth of
+    /// budget can be accumulated while idle and then spent in a burst. Messages are never split,
+    /// a single message larger than the limit delaying the following ones instead. The small
+    /// control traffic of Kademlia, identify, AutoNAT and mDNS is not limited.
+    pub egress_rate_limit: Option<u64>,
+    /// What to do when storing a record under a key that already holds a different value,
+    /// whether the record is put by the local node or by a peer.
+    pub record_conflict_policy: RecordConflictPolicy,
+    /// The maximum number of inbound requests, typed and raw together, a single peer can have
+    /// waiting for a response at once; the requests beyond it are dropped, failing on both
+    /// ends, and reported by a `NetworkEvent::InboundStreamLimitReached`. `None` doesn't limit
+    /// the concurrency.
+    pub max_inbound_streams_per_peer: Option<usize>,
+    /// The resource usage beyond which new work is refused, see `LoadSheddingConfig`; `None`
+    /// never sheds load.
+    pub load_shedding: Option<LoadSheddingConfig>,
+    /// The advertisement schedule of each class of provided data, see `DataClass`.
+    pub data_classes: DataClassSchedules,
+    /// The retry schedule of `Network::store_data_persistent`.
+    pub store_retry: StoreRetryConfig,
+    /// The file logging the keys the node provides, through `Network::store_data` and
+    /// `Network::store_data_persistent`; `None` disables the logging. The logged keys are
+    /// advertised again after a restart, including one following a crash, once the node has
+    /// bootstrapped.
+    pub provider_log: Option<PathBuf>,
+    /// The local store the data is checked against every time it is advertised: the data that
+    /// is missing or doesn't match its name is refused with `Error::DataNotHeld` by
+    /// `Network::store_data`, `Network::store_data_persistent` and `Network::touch_record`, and
+    /// is no longer provided once due to be republished or resumed, so the node never provides
+    /// data it can't serve. `None` advertises the data without checking.
+    pub held_data: Option<DataStorage>,
+    /// The file holding the protobuf encoding of the node's keypair, for the node to keep its
+    /// `PeerId` across restarts: a new ed25519 keypair is generated and saved there on the first
+    /// run, then loaded on the next ones. `None` generates a new keypair on every run.
+    pub keypair_path: Option<PathBuf>,
+    /// The lowest protocol version accepted from the peers, as advertised through identify;
+    /// the connections to the peers below it, or not advertising a valid version, are closed
+    /// with a `NetworkEvent::PeerRejectedVersion`. `None` accepts all the versions.
+    pub min_peer_version: Option<ProtocolVersion>,
+    /// How `Network::select_providers` picks among the providers found for some data.
+    pub provider_selection: ProviderSelectionStrategy,
+    /// The number of commands the `Network` handles can queue for the event loop before their
+    /// calls wait for room, see `Network::command_channel_pressure`. Must not be zero, as for
+    /// the `event_channel_capacity`, or `Error::InvalidConfig` is returned.
+    pub command_channel_capacity: usize,
+    /// The number of events the event loop can queue for the event stream returned by
+    /// `NetworkSwarmLoop::new` before waiting for room, and for each of the
+    /// `Network::subscribe_events` streams before disconnecting it.
+    ///
+    /// The event loop handles one thing at a time, so while it waits for room in the event
+    /// stream it handles no command either: an event consumer that doesn't keep up eventually
+    /// stalls all the `Network` calls, with the command channel filling up in turn. Conversely,
+    /// a consumer calling into the `Network`, e.g. to respond to a request, only waits on the
+    /// event loop once the command channel is full. Hence the event streams have to be kept
+    /// drained, with a capacity large enough to absorb the bursts of events, e.g. of inbound
+    /// requests, and the consumers shouldn't wait on the `Network` calls for long enough to let
+    /// both channels fill up, or the two would wait on each other forever.
+    pub event_channel_capacity: usize,
+    /// The adverse conditions injected into the transport; `None` leaves it untouched.
+    #[cfg(feature = "chaos")]
+    pub chaos: Option<ChaosConfig>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            bootstrap_timeout: DEFAULT_BOOTSTRAP_TIMEOUT,
+            listen_addr: None,
+            transport: TransportKind::default(),
+            bootstrap_dial_concurrency: 8,
+            bootstrap_peers: Vec::new(),
+            dial_stagger: None,
+            max_pending_dials: None,
+            request_coalescing_window: None,
+            request_timeout: None,
+            request_retries: 0,
+            dial_timeout: None,
+            min_ready_peers: 1,
+            disjoint_query_paths: false,
+            bucket_refresh: Some(BucketRefreshConfig::default()),
+            mdns: true,
+            disconnect_expired_mdns_peers: false,
+            node_metadata: String::new(),
+            idle_connection_timeout: None,
+            msg_limits: ProtocolLimits::default(),
+            msg_format: MsgFormat::default(),
+            raw_limits: ProtocolLimits::default(),
+            egress_rate_limit: None,
+            record_conflict_policy: RecordConflictPolicy::default(),
+            max_inbound_streams_per_peer: None,
+            load_shedding: None,
+            data_classes: DataClassSchedules::default(),
+            store_retry: StoreRetryConfig::default(),
+            provider_log: None,
+            held_data: None,
+            keypair_path: None,
+            min_peer_version: None,
+            provider_selection: ProviderSelectionStrategy::default(),
+            command_channel_capacity: DEFAULT_COMMAND_CHANNEL_CAPACITY,
+            event_channel_capacity: DEFAULT_EVENT_CHANNEL_CAPACITY,
+            #[cfg(feature = "chaos")]
+            chaos: None,
+        }
+    }
+}
+
+/// The transports the node connects to its peers over, see `NetworkConfig::transport`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransportKind {
+    /// QUIC, over UDP.
+    #[default]
+    Quic,
+    /// TCP, secured with Noise and multiplexed with Yamux, for the environments where UDP is
+    /// blocked or rate limited.
+    Tcp,
+    /// Both QUIC and TCP, the peers being dialed over whichever their addresses use.
+    QuicAndTcp,
+}
+
+/// The class of importance of the data provided through `Network::store_data_with_class`, each
+/// class being advertised as per its own `DataClassSchedule`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum DataClass {
+    /// Data to keep available at all costs, advertised the most often.
+    Critical,
+    /// Regular data, the class of the data provided through `Network::store_data`.
+    #[default]
+    Standard,
+    /// Data that can be fetched again elsewhere, only advertised for a while.
+    Cache,
+}
+
+/// How often the data of a `DataClass` is advertised again, and for how long.
+///
+/// The peers holding the provider records expire them as per their own Kademlia provider
+/// record TTL (24h by default), hence the republish interval is to be shorter for the data
+/// to be found all along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataClassSchedule {
+    /// The interval between two advertisements of the data.
+    pub republish_interval: Duration,
+    /// How long the local node keeps advertising the data after it has been stored, or last
+    /// touched through `Network::touch_record`; `None` advertises it for as long as the node
+    /// runs.
+    pub ttl: Option<Duration>,
+}
+
+/// The `DataClassSchedule` of each `DataClass`, see `NetworkConfig::data_classes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataClassSchedules {
+    /// The schedule of `DataClass::Critical`.
+    pub critical: DataClassSchedule,
+    /// The schedule of `DataClass::Standard`.
+    pub standard: DataClassSchedule,
+    /// The schedule of `DataClass::Cache`.
+    pub cache: DataClassSchedule,
+}
+
+impl DataClassSchedules {
+    /// Returns the schedule of the given class.
+    pub fn get(&self, class: DataClass) -> DataClassSchedule {
+        match class {
+            DataClass::Critical => self.critical,
+            DataClass::Standard => self.standard,
+            DataClass::Cache => self.cache,
+        }
+    }
+}
+
+impl Default for DataClassSchedules {
+    fn default() -> Self {
+        Self {
+            critical: DataClassSchedule {
+                republish_interval: Duration::from_secs(60 * 60),
+                ttl: None,
+            },
+            // Kademlia's default provider publication interval.
+            standard: DataClassSchedule {
+                republish_interval: Duration::from_secs(12 * 60 * 60),
+                ttl: None,
+            },
+            cache: DataClassSchedule {
+                republish_interval: Duration::from_secs(24 * 60 * 60),
+                ttl: Some(Duration::from_secs(24 * 60 * 60)),
+            },
+        }
+    }
+}
+
+/// What to do when a record is stored under a key already holding a different value, see
+/// `NetworkConfig::record_conflict_policy`. The policy of each node applies to its own copy of
+/// the record: peers acknowledge a put even if they keep their existing value, so the conflicts
+/// on the remote ends are not reported to the putter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecordConflictPolicy {
+    /// The new record replaces the existing one.
+    #[default]
+    Overwrite,
+    /// The existing record is kept, e.g. for immutable content-addressed data; a local put
+    /// fails with `Error::RecordExists`.
+    FailIfExists,
+    /// The record with the larger timestamp, i.e. expiring last, is kept. The local puts always
+    /// carry the latest timestamp, hence replace the existing record.
+    KeepLargerTimestamp,
+}
+
+/// The thresholds beyond which the node sheds load, i.e. refuses new work instead of running
+/// out of memory. Load is shed as soon as any threshold is reached, and until all the usages
+/// are back below their threshold; the usage is checked every second.
+///
+/// While shedding load, the inbound requests are answered with `Response::Overloaded`, or
+/// `RawResponse::Overloaded` for the raw ones, and the outbound lookups, stores and requests fail with
+/// `Error::Overloaded`. Dialing, bootstrapping, listening, the responses and the queries on the
+/// local state are still served, so the node can recover.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadSheddingConfig {
+    /// The number of requests and queries waiting for their outcome.
+    pub max_in_flight: usize,
+    /// The number of records held by the local store.
+    pub max_stored_records: usize,
+}
+
+impl Default for LoadSheddingConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 1024,
+            // Just below the capacity of the record store.
+            max_stored_records: 1000,
+        }
+    }
+}
+
+/// How `Network::select_providers` picks among the providers found for some data.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ProviderSelectionStrategy {
+    /// The providers as found by the lookup, in no particular order.
+    #[default]
+    Unordered,
+    /// The nearest providers first, as per the round trip of the requests sent to them, with a
+    /// fraction of distant ones kept for resilience, see `LatencyBuckets`.
+    LatencyBucketed(LatencyBuckets),
+}
+
+/// The latency classes `ProviderSelectionStrategy::LatencyBucketed` sorts the peers into.
+///
+/// The latency of a peer is the smoothed round trip of the requests sent to it while connected,
+/// and is forgotten once disconnected. A peer falls into the first bucket whose bound is at
+/// least its latency, past the last bucket if none is, and the peers of unknown latency after
+/// these. The providers are taken from the nearest bucket onwards, except for the
+/// `distant_fraction` of the selection (rounded down) that goes to the peers outside the
+/// nearest bucket, the farthest first, so that the data stays reachable should the nearby
+/// peers go down together, e.g. all being hosted in the same region.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyBuckets {
+    bounds: Vec<Duration>,
+    distant_fraction: f64,
+}
+
+impl LatencyBuckets {
+    /// Creates the buckets bounded by `bounds`, which must be strictly increasing, keeping the
+    /// `distant_fraction`, between 0 and 1, of the selected providers for the distant peers.
+    /// Returns `Error::InvalidConfig` otherwise.
+    pub fn new(bounds: Vec<Duration>, distant_fraction: f64) -> Result<Self> {
+        if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(Error::InvalidConfig(format!(
+                "the latency bucket bounds must be strictly increasing: {bounds:?}"
+            )));
+        }
+        if !(0.0..=1.0).contains(&distant_fraction) {
+            return Err(Error::InvalidConfig(format!(
+                "the fraction of distant peers must be between 0 and 1: {distant_fraction}"
+            )));
+        }
+        Ok(Self {
+            bounds,
+            distant_fraction,
+        })
+    }
+
+    /// The upper bounds of the buckets, in increasing order.
+    pub fn bounds(&self) -> &[Duration] {
+        &self.bounds
+    }
+
+    /// The fraction, between 0 and 1, of the selected providers kept for the distant peers.
+    pub fn distant_fraction(&self) -> f64 {
+        self.distant_fraction
+    }
+}
+
+impl Default for LatencyBuckets {
+    fn default() -> Self {
+        Self {
+            bounds: vec![
+                Duration::from_millis(20),
+                Duration::from_millis(80),
+                Duration::from_millis(200),
+            ],
+            distant_fraction: 0.2,
+        }
+    }
+}
+
+/// Schedule of the retries of `Network::store_data_persistent`: the backoff between two attempts
+/// starts at `initial_backoff` and doubles after each failure, up to `max_backoff`.
+#[derive(Debug, Clone, Copy)]
+pub struct StoreRetryConfig {
+    /// The backoff after the first failed attempt.
+    pub initial_backoff: Duration,
+    /// The maximum backoff between two attempts.
+    pub max_backoff: Duration,
+    /// How long to keep retrying for, from the first attempt.
+    pub deadline: Duration,
+}
+
+impl Default for StoreRetryConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            deadline: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// The size of the pool serving the inbound requests, see `Network::serve_requests`.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestPoolConfig {
+    /// The number of requests handled concurrently.
+    pub workers: usize,
+    /// The number of requests waiting for a worker, beyond which the requests are shed.
+    pub queue_capacity: usize,
+}
+
+impl Default for RequestPoolConfig {
+    fn default() -> Self {
+        Self {
+            workers: 8,
+            queue_capacity: 64,
+        }
+    }
+}
+
+/// The maximum sizes, in bytes, of the requests and responses of a single protocol.
+///
+/// The limits are enforced independently for each protocol, by both ends: oversized outbound
+/// messages are refused with `Error::MessageTooLarge` and oversized inbound ones are dropped
+/// from their length prefix alone, before anything is allocated for them. Peers are expected
+/// to agree on the limits, which default to 2 MiB.
+#[derive(Debug, Clone, Copy)]
+pub struct ProtocolLimits {
+    /// The maximum size of a request.
+    pub max_request_size: usize,
+    /// The maximum size of a response.
+    pub max_response_size: usize,
+}
+
+impl Default for ProtocolLimits {
+    fn default() -> Self {
+        Self {
+            max_request_size: MAX_MSG_SIZE,
+            max_response_size: MAX_MSG_SIZE,
+        }
+    }
+}
+
+/// A `major.minor.patch` protocol version, as advertised through identify in the form
+/// `/safe/<major>.<minor>.<patch>`. Versions are ordered by major, then minor, then patch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProtocolVersion {
+    /// Incremented for incompatible changes.
+    pub major: u64,
+    /// Incremented for backward compatible additions.
+    pub minor: u64,
+    /// Incremented for backward compatible fixes.
+    pub patch: u64,
+}
+
+impl ProtocolVersion {
+    /// Parses the protocol version advertised through identify, e.g. `/safe/1.0.0`.
+    pub fn parse(protocol_version: &str) -> Option<Self> {
+        let mut parts = protocol_version
+            .strip_prefix(PROTOCOL_VERSION_PREFIX)?
+            .split('.')
+            .map(|part| part.parse().ok());
+        let version = Self {
+            major: parts.next()??,
+            minor: parts.next()??,
+            patch: parts.next()??,
+        };
+        parts.next().is_none().then_some(version)
+    }
+}
+
+/// Returns the identify agent version: `safenode/<version>`, followed by ` (<node_metadata>)`
+/// if any metadata is provided.
+pub(super) fn agent_version(node_metadata: &str) -> String {
+    if node_metadata.is_empty() {
+        AGENT_VERSION_PREFIX.to_string()
+    } else {
+        format!("{AGENT_VERSION_PREFIX} ({node_metadata})")
+    }
+}
+
+/// Extracts the node metadata out of an agent version built by `agent_version`.
+/// Metadata longer than `MAX_NODE_METADATA_LEN` is discarded.
+pub(super) fn node_metadata(agent_version: &str) -> Option<String> {
+    let (_, metadata) = agent_version.split_once(" (")?;
+    let metadata = metadata.strip_suffix(')')?;
+    (metadata.len() <= MAX_NODE_METADATA_LEN).then(|| metadata.to_string())
+}
+
+/// Schedule for the periodic refresh of the Kademlia buckets, weighted towards the buckets that
+/// are close to the local node.
+///
+/// The closest non-empty bucket is refreshed every `interval`, and each non-empty bucket farther
+/// away is refreshed half as often as the previous one, until `max_backoff` halvings are reached.
+/// With the defaults the closest bucket is refreshed every minute and the farthest ones every
+/// 16 minutes.
+///
+/// Most lookups terminate in the buckets close to us, hence keeping them fresh is what matters the
+/// most. The tradeoff is that the entries in the distant, sparsely used, buckets are allowed to
+/// grow stale for longer; lookups for keys far away from us may then start from peers that have
+/// left the network and take a few more hops (or time out on those peers) to complete.
+#[derive(Debug, Clone)]
+pub struct BucketRefreshConfig {
+    /// How often the closest non-empty bucket is refreshed.
+    pub interval: Duration,
+    /// The maximum number of times the refresh interval is doubled for the distant buckets.
+    pub max_backoff: u32,
+}
+
+impl Default for BucketRefreshConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            max_backoff: 4,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LatencyBuckets, ProtocolVersion, IDENTIFY_PROTOCOL_VERSION};
+    use crate::network::error::Error;
+    use std::time::Duration;
+
+    #[test]
+    fn protocol_versions_are_parsed_and_ordered_by_major_minor_then_patch() {
+        let version = |major, minor, patch| ProtocolVersion {
+            major,
+            minor,
+            patch,
+        };
+        assert_eq!(
+            ProtocolVersion::parse(IDENTIFY_PROTOCOL_VERSION),
+            Some(version(1, 0, 0))
+        );
+        assert_eq!(
+            ProtocolVersion::parse("/safe/2.10.3"),
+            Some(version(2, 10, 3))
+        );
+        for invalid in [
+            "/safe/1.0",
+            "/safe/1.0.0.0",
+            "/safe/1.x.0",
+            "/other/1.0.0",
+            "1.0.0",
+        ] {
+            assert_eq!(ProtocolVersion::parse(invalid), None, "for {invalid}");
+        }
+
+        assert!(version(1, 0, 10) > version(1, 0, 9));
+        assert!(version(1, 10, 0) > version(1, 9, 99));
+        assert!(version(2, 0, 0) > version(1, 99, 99));
+    }
+
+    #[test]
+    fn latency_buckets_are_only_built_from_increasing_bounds_and_a_fraction() {
+        let ms = Duration::from_millis;
+        let buckets = LatencyBuckets::new(vec![ms(20), ms(200)], 0.25);
+        assert_eq!(
+            buckets.as_ref().map(LatencyBuckets::bounds).ok(),
+            Some(&[ms(20), ms(200)][..])
+        );
+        assert!(LatencyBuckets::new(Vec::new(), 0.0).is_ok());
+        assert!(LatencyBuckets::new(LatencyBuckets::default().bounds, 1.0).is_ok());
+
+        for bounds in [vec![ms(200), ms(20)], vec![ms(20), ms(20)]] {
+            assert!(matches!(
+                LatencyBuckets::new(bounds, 0.25),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+        for fraction in [-0.1, 1.5, f64::NAN] {
+            assert!(matches!(
+                LatencyBuckets::new(vec![ms(20)], fraction),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    config::DataClass,
+    error::{Error, Result},
+    NetworkSwarmLoop,
+};
+use crate::storage::chunks::ChunkAddress;
+use libp2p::kad::{store::RecordStore, RecordKey};
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+use tracing::{debug, trace, warn};
+use xor_name::XorName;
+
+/// The interval between two checks for the provided data due to be re-advertised.
+pub(super) const REPUBLISH_TICK: Duration = Duration::from_secs(60);
+
+/// A key provided by the local node, tracked to re-advertise it as per its `DataClass`.
+pub(super) struct ProvidedData {
+    class: DataClass,
+    /// When the key started being provided, or got touched, its TTL running from then.
+    provided_at: Instant,
+    published_at: Instant,
+}
+
+impl NetworkSwarmLoop {
+    /// Tracks the key as being advertised now, with the given class.
+    pub(super) fn track_provided(&mut self, key: RecordKey, class: DataClass) {
+        let now = Instant::now();
+        let _ = self.provided_data.insert(
+            key,
+            ProvidedData {
+                class,
+                provided_at: now,
+                published_at: now,
+            },
+        );
+    }
+
+    /// Tracks the key as being advertised again now, keeping its class, e.g. once touched.
+    pub(super) fn track_republished(&mut self, key: RecordKey) {
+        let class = self
+            .provided_data
+            .get(&key)
+            .map_or_else(DataClass::default, |data| data.class);
+        self.track_provided(key, class);
+    }
+
+    /// Advertises again the provided keys due as per the `DataClassSchedule` of their class, and
+    /// stops providing those past their TTL, or whose data is no longer held. The keys provided
+    /// otherwise than through `Network::store_data_with_class` are of the default class.
+    pub(super) async fn republish_provided_data(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let keys: HashSet<RecordKey> = self
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .store_mut()
+            .provided()
+            .map(|record| record.key.clone())
+            .collect();
+        // Forget about the keys no longer provided.
+        self.provided_data.retain(|key, _| keys.contains(key));
+
+        let mut expired = Vec::new();
+        let mut due = Vec::new();
+        for key in keys {
+            let data = self
+                .provided_data
+                .entry(key.clone())
+                .or_insert(ProvidedData {
+                    class: DataClass::default(),
+                    provided_at: now,
+                    published_at: now,
+                });
+            let schedule = self.data_classes.get(data.class);
+            if schedule
+                .ttl
+                .is_some_and(|ttl| now.duration_since(data.provided_at) >= ttl)
+            {
+                debug!("Stop providing {key:?}, past the TTL of {:?}", data.class);
+                expired.push(key);
+            } else if now.duration_since(data.published_at) >= schedule.republish_interval {
+                trace!("Republishing {key:?} of {:?}", data.class);
+                data.published_at = now;
+                due.push(key);
+            }
+        }
+        for key in due {
+            let held = match <[u8; 32]>::try_from(key.as_ref()) {
+                Ok(bytes) => self.verify_held(XorName(bytes)).await.is_ok(),
+                Err(_) => self.held_data.is_none(),
+            };
+            if held {
+                let query_id = self.swarm.behaviour_mut().kademlia.start_providing(key)?;
+                let _ = self.republish_queries.insert(query_id);
+            } else {
+                expired.push(key);
+            }
+        }
+        for key in expired {
+            self.swarm.behaviour_mut().kademlia.stop_providing(&key);
+            let _ = self.provided_data.remove(&key);
+            // Not to be advertised again after a restart either.
+            if let Ok(bytes) = <[u8; 32]>::try_from(key.as_ref()) {
+                self.unlog_provided(&XorName(bytes))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the data is in the `NetworkConfig::held_data` store, if any, hence can be
+    /// served once advertised. The store checks the content against its name as it reads it.
+    // Takes `&mut self` for the future to be `Send`, the loop not being `Sync`.
+    pub(super) async fn verify_held(&mut self, xor_name: XorName) -> Result<()> {
+        if let Some(storage) = &self.held_data {
+            if let Err(err) = storage.query(&ChunkAddress(xor_name)).await {
+                warn!("Refusing to provide {xor_name:?}, not held locally: {err}");
+                return Err(Error::DataNotHeld(xor_name));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        network::{
+            command::SwarmCmd,
+            config::{DataClass, DataClassSchedule, DataClassSchedules},
+            error::{Error, Result},
+            testing::isolated_config,
+            NetworkConfig, NetworkSwarmLoop,
+        },
+        storage::{chunks::Chunk, DataStorage},
+    };
+    use assert_fs::TempDir;
+    use bytes::Bytes;
+    use futures::channel::oneshot;
+    use libp2p::kad::{store::RecordStore, RecordKey};
+    use std::{fs, time::Duration};
+    use xor_name::XorName;
+
+    #[async_std::test]
+    async fn the_provided_data_is_republished_until_past_its_ttl() -> Result<()> {
+        let schedule = DataClassSchedule {
+            republish_interval: Duration::from_millis(10),
+            ttl: None,
+        };
+        let (_network, _events, mut event_loop) = NetworkSwarmLoop::with_config(NetworkConfig {
+            data_classes: DataClassSchedules {
+                critical: schedule,
+                standard: schedule,
+                cache: DataClassSchedule {
+                    ttl: Some(Duration::from_millis(50)),
+                    ..schedule
+                },
+            },
+            ..isolated_config()
+        })?;
+        let cached = RecordKey::new(&[1; 32]);
+        let standard = RecordKey::new(&[2; 32]);
+        for (key, class) in [
+            (cached.clone(), DataClass::Cache),
+            (standard.clone(), DataClass::Standard),
+        ] {
+            let _ = event_loop
+                .swarm
+                .behaviour_mut()
+                .kademlia
+                .start_providing(key.clone())?;
+            event_loop.track_provided(key, class);
+        }
+
+        async_std::task::sleep(Duration::from_millis(60)).await;
+        event_loop.republish_provided_data().await?;
+        let provided: Vec<RecordKey> = event_loop
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .store_mut()
+            .provided()
+            .map(|record| record.key.clone())
+            .collect();
+        assert_eq!(provided, vec![standard.clone()]);
+        assert!(!event_loop.provided_data.contains_key(&cached));
+        assert!(event_loop.provided_data.contains_key(&standard));
+        assert_eq!(event_loop.republish_queries.len(), 1);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn the_data_no_longer_held_is_no_longer_provided() -> Result<()> {
+        let dir = TempDir::new().map_err(|err| Error::Other(err.to_string()))?;
+        let storage = DataStorage::new(dir.path());
+        let chunk = Chunk::new(Bytes::from_static(b"held"));
+        storage
+            .store(&chunk)
+            .await
+            .map_err(|err| Error::Other(err.to_string()))?;
+        let schedule = DataClassSchedule {
+            republish_interval: Duration::from_millis(10),
+            ttl: None,
+        };
+        let (_network, _events, mut event_loop) = NetworkSwarmLoop::with_config(NetworkConfig {
+            data_classes: DataClassSchedules {
+                critical: schedule,
+                standard: schedule,
+                cache: schedule,
+            },
+            held_data: Some(storage),
+            ..isolated_config()
+        })?;
+        let held = *chunk.name();
+        let (sender, _receiver) = oneshot::channel();
+        event_loop
+            .handle_command(SwarmCmd::StoreData {
+                xor_name: held,
+                class: DataClass::Standard,
+                sender,
+            })
+            .await?;
+        let (sender, receiver) = oneshot::channel();
+        event_loop
+            .handle_command(SwarmCmd::StoreDataPersistent {
+                xor_name: XorName([1; 32]),
+                sender,
+            })
+            .await?;
+        assert!(matches!(receiver.await?, Err(Error::DataNotHeld(_))));
+
+        fs::remove_dir_all(dir.path())?;
+        let (sender, receiver) = oneshot::channel();
+        event_loop
+            .handle_command(SwarmCmd::TouchRecord { key: held, sender })
+            .await?;
+        assert!(matches!(receiver.await?, Err(Error::DataNotHeld(name)) if name == held));
+
+        async_std::task::sleep(Duration::from_millis(20)).await;
+        event_loop.republish_provided_data().await?;
+        let kademlia = &mut event_loop.swarm.behaviour_mut().kademlia;
+        assert_eq!(kademlia.store_mut().provided().count(), 0);
+        assert!(event_loop.republish_queries.is_empty());
+        Ok(())
+    }
+}
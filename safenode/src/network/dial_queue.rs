@@ -0,0 +1,248 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    command::SwarmCmd,
+    error::{Error, Result},
+    NetworkSwarmLoop,
+};
+use futures::channel::oneshot;
+use libp2p::{
+    core::Endpoint,
+    swarm::{
+        dummy, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, PollParameters,
+        THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    fmt,
+    task::{Context, Poll},
+};
+use tracing::trace;
+
+/// A `Network::dial` waiting for the number of pending connections to go below the
+/// `NetworkConfig::max_pending_dials`.
+pub(super) struct QueuedDial {
+    peer_id: PeerId,
+    peer_addr: Multiaddr,
+    sender: oneshot::Sender<Result<()>>,
+}
+
+impl NetworkSwarmLoop {
+    /// Whether the connections being established reached the `NetworkConfig::max_pending_dials`,
+    /// whoever dialed them, the dials then being queued.
+    pub(super) fn dial_limit_reached(&self) -> bool {
+        self.swarm.behaviour().dial_limit.limit_reached()
+    }
+
+    /// Queues the dial, until a pending connection settles.
+    pub(super) fn queue_dial(
+        &mut self,
+        peer_id: PeerId,
+        peer_addr: Multiaddr,
+        sender: oneshot::Sender<Result<()>>,
+    ) {
+        trace!("Queuing the dial to {peer_id:?}, too many connections pending");
+        self.queued_dials.push_back(QueuedDial {
+            peer_id,
+            peer_addr,
+            sender,
+        });
+    }
+
+    /// Dials the queued peers, in order, while below the `NetworkConfig::max_pending_dials`.
+    pub(super) async fn dequeue_dials(&mut self) -> Result<()> {
+        while !self.queued_dials.is_empty() && !self.dial_limit_reached() {
+            if let Some(dial) = self.queued_dials.pop_front() {
+                self.handle_command(SwarmCmd::Dial {
+                    peer_id: dial.peer_id,
+                    peer_addr: dial.peer_addr,
+                    sender: dial.sender,
+                })
+                .await?;
+            }
+        }
+        self.resume_bootstrap_dials().await
+    }
+
+    /// Fails the queued dials, once shutting down.
+    pub(super) fn abandon_queued_dials(&mut self) {
+        for dial in self.queued_dials.drain(..) {
+            let _ = dial.sender.send(Err(Error::Shutdown));
+        }
+    }
+}
+
+/// Refuses the connections beyond the `NetworkConfig::max_pending_dials`, for the dials of the
+/// protocols, e.g. those of Kademlia or of the request protocols to the peers not connected,
+/// not to exceed it. Those of `Network::dial` and of the bootstrap are queued instead.
+pub(crate) struct DialLimit {
+    max: Option<usize>,
+    pending: HashSet<ConnectionId>,
+}
+
+impl DialLimit {
+    pub(super) fn new(max: Option<usize>) -> Self {
+        Self {
+            max,
+            pending: HashSet::new(),
+        }
+    }
+
+    /// Whether as many connections as allowed are being established.
+    pub(super) fn limit_reached(&self) -> bool {
+        self.max.is_some_and(|max| self.pending.len() >= max)
+    }
+}
+
+/// The reason for refusing a connection beyond the `NetworkConfig::max_pending_dials`.
+#[derive(Debug)]
+struct DialLimitReached;
+
+impl fmt::Display for DialLimitReached {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the limit of connections being established is reached")
+    }
+}
+
+impl std::error::Error for DialLimitReached {}
+
+impl NetworkBehaviour for DialLimit {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type OutEvent = Infallible;
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        _maybe_peer: Option<PeerId>,
+        _addresses: &[Multiaddr],
+        _effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        if self.limit_reached() {
+            return Err(ConnectionDenied::new(DialLimitReached));
+        }
+        let _ = self.pending.insert(connection_id);
+        Ok(vec![])
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        match event {
+            FromSwarm::ConnectionEstablished(established) => {
+                let _ = self.pending.remove(&established.connection_id);
+            }
+            FromSwarm::DialFailure(failure) => {
+                let _ = self.pending.remove(&failure.connection_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer_id: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+        _params: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::OutEvent, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        error::{Error, Result},
+        testing::isolated_config,
+        NetworkConfig, NetworkSwarmLoop, Request,
+    };
+    use async_std::{future::timeout, task};
+    use futures::StreamExt;
+    use libp2p::{request_response::OutboundFailure, Multiaddr, PeerId};
+    use std::{net::TcpListener, time::Duration};
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    // Accepts the connections at the TCP level only, the dials hanging in the handshake.
+    fn hanging_listener() -> Result<(TcpListener, Multiaddr)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let addr = format!("/ip4/127.0.0.1/tcp/{port}").parse()?;
+        Ok((listener, addr))
+    }
+
+    #[async_std::test]
+    async fn the_dials_of_the_protocols_are_refused_beyond_the_limit() -> Result<()> {
+        let (_first_listener, first_addr) = hanging_listener()?;
+        let (_second_listener, second_addr) = hanging_listener()?;
+        let (first, second) = (PeerId::random(), PeerId::random());
+
+        let (network, events, mut event_loop) = NetworkSwarmLoop::with_config(NetworkConfig {
+            max_pending_dials: Some(1),
+            ..isolated_config()
+        })?;
+        let _routing_update = event_loop
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .add_address(&second, second_addr);
+        let _event_loop = task::spawn(event_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+
+        // Takes the only slot, until the handshake times out.
+        let mut dialer = network.clone();
+        let _dial = task::spawn(async move { dialer.dial(first, first_addr).await });
+        task::sleep(Duration::from_millis(200)).await;
+
+        // The request protocol dialing on its own is refused, instead of hanging as well.
+        let mut requester = network;
+        let result = timeout(
+            TEST_TIMEOUT,
+            requester.send_request(Request::GetDBC, second),
+        )
+        .await
+        .expect("the request to fail without waiting for the pending dial");
+        assert!(matches!(
+            result,
+            Err(Error::RequestFailed {
+                error: OutboundFailure::DialFailure,
+                ..
+            })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::metrics::Metrics;
+use std::{
+    sync::{atomic::Ordering, Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Accounts for the bytes written by the request-response codecs and, if a rate limit is
+/// configured, delays the writes exceeding the budget. Shared by all the codecs.
+#[derive(Clone, Default)]
+pub(crate) struct Egress {
+    metrics: Arc<Metrics>,
+    budget: Option<Arc<Mutex<Budget>>>,
+}
+
+// A token bucket refilled at `rate` bytes per second, holding at most one second worth of bytes.
+struct Budget {
+    rate: f64,
+    available: f64,
+    refilled_at: Instant,
+}
+
+impl Egress {
+    pub(super) fn new(metrics: Arc<Metrics>, rate_limit: Option<u64>) -> Self {
+        let budget = rate_limit.map(|rate| {
+            let rate = rate.max(1) as f64;
+            Arc::new(Mutex::new(Budget {
+                rate,
+                available: rate,
+                refilled_at: Instant::now(),
+            }))
+        });
+        Self { metrics, budget }
+    }
+
+    /// Waits until `bytes` can be written within the budget, then accounts for them.
+    ///
+    /// A message is never split: one larger than the available budget is let through once the
+    /// budget has been refilled by its size, the budget going into debt to be paid back by the
+    /// following writes. Concurrent writers pay the debt back in turn, i.e. they are queued.
+    pub(crate) async fn reserve(&self, bytes: usize) {
+        if let Some(budget) = &self.budget {
+            let wait = budget
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take(bytes as f64, Instant::now());
+            if !wait.is_zero() {
+                Metrics::inc(&self.metrics.egress_throttled);
+                async_std::task::sleep(wait).await;
+            }
+        }
+        let _ = self
+            .metrics
+            .egress_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+impl Budget {
+    // Takes `bytes` out of the budget at `now`, returning how long to wait for it to be paid back.
+    fn take(&mut self, bytes: f64, now: Instant) -> Duration {
+        let refill = now.duration_since(self.refilled_at).as_secs_f64() * self.rate;
+        self.available = (self.available + refill).min(self.rate) - bytes;
+        self.refilled_at = now;
+        if self.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.available / self.rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Budget;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn the_budget_allows_a_second_worth_of_burst_then_queues_the_writes() {
+        let start = Instant::now();
+        let mut budget = Budget {
+            rate: 1000.0,
+            available: 1000.0,
+            refilled_at: start,
+        };
+        assert_eq!(budget.take(600.0, start), Duration::ZERO);
+        assert_eq!(budget.take(400.0, start), Duration::ZERO);
+        // Into debt, paid back at the rate: the writes are queued one after the other.
+        assert_eq!(budget.take(500.0, start), Duration::from_millis(500));
+        assert_eq!(budget.take(500.0, start), Duration::from_secs(1));
+
+        // Refilled as time goes by, but never beyond a second worth of bytes.
+        let later = start + Duration::from_secs(1);
+        assert_eq!(budget.take(0.0, later), Duration::ZERO);
+        let much_later = later + Duration::from_secs(10);
+        assert_eq!(budget.take(1000.0, much_later), Duration::ZERO);
+        assert_eq!(budget.take(100.0, much_later), Duration::from_millis(100));
+    }
+}
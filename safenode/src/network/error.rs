@@ -6,32 +6,45 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use super::msg::{FrameTooLarge, Response, TransferProgress};
 use futures::channel::{mpsc, oneshot};
-use libp2p::{kad, request_response::OutboundFailure, swarm::DialError, TransportError};
-use std::io;
+use libp2p::{
+    gossipsub, kad, request_response::OutboundFailure, swarm::DialError, PeerId, TransportError,
+};
+use std::{io, path::PathBuf, time::Duration};
 use thiserror::Error;
+use xor_name::XorName;
 
 /// The type returned by the `sn_routing` message handling methods.
 pub(super) type Result<T, E = Error> = std::result::Result<T, E>;
 
 /// Internal error.
 #[derive(Debug, Error)]
-#[allow(missing_docs)]
+#[allow(missing_docs, clippy::enum_variant_names)]
 pub enum Error {
     #[error("Othe error: {0}")]
     Other(String),
 
     #[error("I/O error: {0}")]
-    Io(#[from] io::Error),
+    Io(#[source] io::Error),
+
+    #[error("Noise configuration error: {0}")]
+    Noise(#[from] libp2p::noise::Error),
 
     #[error("Transport Error")]
-    TransportError(#[from] TransportError<std::io::Error>),
+    TransportError(#[from] TransportError<io::Error>),
 
     #[error("Dial Error")]
     DialError(#[from] DialError),
 
-    #[error("Outbound Error")]
-    OutboundError(#[from] OutboundFailure),
+    #[error("Raw request failed: {0}")]
+    OutboundError(OutboundFailure),
+
+    #[error("Request failed after {progress:?}: {error}")]
+    RequestFailed {
+        error: OutboundFailure,
+        progress: TransferProgress,
+    },
 
     #[error("Kademlia Store error: {0}")]
     KademliaStoreError(#[from] kad::store::Error),
@@ -41,4 +54,146 @@ pub enum Error {
 
     #[error("The oneshot::sender has been dropped")]
     SenderDropped(#[from] oneshot::Canceled),
+
+    #[error("The oneshot::receiver has been dropped")]
+    OneshotReceiverDropped,
+
+    #[error("The swarm event stream ended")]
+    SwarmStreamEnded,
+
+    #[error("Invalid multiaddr: {0}")]
+    InvalidMultiaddr(#[from] libp2p::multiaddr::Error),
+
+    #[error("Start providing error: {0}")]
+    AddProviderError(#[from] kad::AddProviderError),
+
+    #[error("Bootstrap query failed: {0}")]
+    BootstrapError(#[from] kad::BootstrapError),
+
+    #[error("No peer subscribed to topic {0}")]
+    NoTopicPeers(String),
+
+    #[error("Publish error: {0}")]
+    PublishError(#[from] gossipsub::PublishError),
+
+    #[error("Subscription error: {0}")]
+    SubscriptionError(#[from] gossipsub::SubscriptionError),
+
+    #[error("None of the {0} bootstrap peers could be reached")]
+    BootstrapPeersUnreachable(usize),
+
+    #[error("No known peers to bootstrap from")]
+    NoKnownPeers(#[from] kad::NoKnownPeers),
+
+    #[error("Put record error: {0}")]
+    PutRecordError(#[from] kad::PutRecordError),
+
+    #[error("Get record error: {0}")]
+    GetRecordError(#[from] kad::GetRecordError),
+
+    #[error("Get providers error: {0}")]
+    GetProvidersError(#[from] kad::GetProvidersError),
+
+    #[error("The local node is not providing: {0:?}")]
+    RecordNotProvided(XorName),
+
+    #[error("A different record already exists under {0:?}")]
+    RecordExists(XorName),
+
+    #[error("Manifest not found: {0:?}")]
+    ManifestNotFound(XorName),
+
+    #[error("Unsupported manifest version: {0}")]
+    UnsupportedManifestVersion(u8),
+
+    #[error("Failed to encode the manifest: {0}")]
+    ManifestEncoding(#[from] rmp_serde::encode::Error),
+
+    #[error("Failed to decode the manifest: {0}")]
+    ManifestDecoding(#[from] rmp_serde::decode::Error),
+
+    #[error("Data {0:?} is not held locally, hence can't be provided")]
+    DataNotHeld(XorName),
+
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("Dialed {expected:?}, but the remote identified as {actual:?}")]
+    // Boxed for the `Error` to stay small, a `PeerId` taking 80 bytes.
+    PeerIdMismatch {
+        expected: Box<PeerId>,
+        actual: Box<PeerId>,
+    },
+
+    #[error("Unknown protocol: {0}")]
+    UnknownProtocol(String),
+
+    #[error("Protocol {0} is disabled")]
+    ProtocolDisabled(&'static str),
+
+    #[error("Invalid identity bundle: {0}")]
+    InvalidIdentityBundle(String),
+
+    #[error("Invalid keypair file {path:?}: {reason}")]
+    InvalidKeypairFile { path: PathBuf, reason: String },
+
+    #[error("Unsupported identity bundle version: {0}")]
+    UnsupportedIdentityBundleVersion(u8),
+
+    #[error("Node {node} could not find key {key:?} before the network converged")]
+    ConvergenceTimeout { node: usize, key: XorName },
+
+    #[error("Message of {size} bytes exceeds the maximum of {max} bytes for protocol {protocol}")]
+    MessageTooLarge {
+        protocol: &'static str,
+        size: usize,
+        max: usize,
+    },
+
+    #[error("Quorum of {quorum} matching responses not reached, got {responses:?}")]
+    QuorumNotReached {
+        quorum: usize,
+        responses: Vec<Response>,
+    },
+
+    #[error("None of the {tried} peers tried responded to the request")]
+    NoPeerResponded { tried: usize },
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Node metadata of {len} bytes exceeds the maximum of {max} bytes")]
+    NodeMetadataTooLong { len: usize, max: usize },
+
+    #[error("Networking is paused")]
+    Paused,
+
+    #[error("The node is overloaded, shedding load")]
+    Overloaded,
+
+    #[error("Networking is shutting down")]
+    Shutdown,
+
+    #[error("The network event loop did not respond within {0:?}")]
+    Unresponsive(Duration),
+
+    #[error("Bootstrap did not complete in time, connected to {connected_peers} peers")]
+    BootstrapTimeout { connected_peers: usize },
+}
+
+// The codecs report the messages over their limits through an `io::Error`, see `FrameTooLarge`.
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        match err
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<FrameTooLarge>())
+        {
+            Some(too_large) => Error::MessageTooLarge {
+                protocol: too_large.protocol,
+                size: too_large.size,
+                max: too_large.max,
+            },
+            None => Error::Io(err),
+        }
+    }
 }
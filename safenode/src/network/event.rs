@@ -7,41 +7,142 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::{
+    address_book::AddressSource,
+    config::{self, ProtocolVersion},
+    dial_queue::DialLimit,
     error::{Error, Result},
-    msg::MsgCodec,
-    NetworkSwarmLoop, Request, Response,
+    metrics::Metrics,
+    msg::{MsgCodec, MsgRequest, RawCodec, RawResponse},
+    protocols::FilteredIdentify,
+    staggered_dial::StaggeredDialGuard,
+    Distance, NetworkSwarmLoop, Request, Response,
 };
-use futures::{channel::oneshot, SinkExt};
+use futures::channel::oneshot;
 use libp2p::{
-    kad::{store::MemoryStore, GetProvidersOk, Kademlia, KademliaEvent, QueryResult},
+    autonat::{self, NatStatus},
+    core::ConnectedPoint,
+    gossipsub, identify,
+    kad::{
+        store::{MemoryStore, RecordStore},
+        AddProviderResult, GetClosestPeersError, GetClosestPeersOk, GetProvidersOk, GetRecordError,
+        GetRecordOk, InboundRequest, KBucketKey, Kademlia, KademliaEvent, QueryId, QueryResult,
+    },
     mdns,
     multiaddr::Protocol,
     request_response::{self, ResponseChannel},
-    swarm::{NetworkBehaviour, SwarmEvent},
+    swarm::{behaviour::toggle::Toggle, DialError, NetworkBehaviour, SwarmEvent, THandlerErr},
+    Multiaddr, PeerId,
+};
+use std::{
+    collections::{hash_map, HashSet},
+    convert::Infallible,
+    fmt,
+    sync::{Arc, Mutex},
 };
-use tracing::{info, warn};
+use tracing::{debug, info, warn, Span};
+use xor_name::XorName;
+
+/// An untranslated event of the swarm, as seen by the `NetworkSwarmLoop::with_raw_event_tap`.
+///
+/// The events of the libp2p protocols are lent as they are; the others, e.g. on the connections
+/// and listeners, and those of the request protocols, are only shown through `Debug`. These are
+/// low-level libp2p types that change with the libp2p releases.
+pub struct RawSwarmEvent<'a>(pub(super) &'a SwarmEvent<NodeEvent, THandlerErr<NodeBehaviour>>);
+
+impl RawSwarmEvent<'_> {
+    /// Returns the event of the Kademlia DHT, if that's what it is.
+    pub fn kademlia(&self) -> Option<&KademliaEvent> {
+        match self.0 {
+            SwarmEvent::Behaviour(NodeEvent::Kademlia(event)) => Some(event),
+            _ => None,
+        }
+    }
+
+    /// Returns the event of the mDNS discovery, if that's what it is.
+    pub fn mdns(&self) -> Option<&mdns::Event> {
+        match self.0 {
+            SwarmEvent::Behaviour(NodeEvent::Mdns(event)) => Some(event),
+            _ => None,
+        }
+    }
+
+    /// Returns the event of the AutoNAT probing, if that's what it is.
+    pub fn autonat(&self) -> Option<&autonat::Event> {
+        match self.0 {
+            SwarmEvent::Behaviour(NodeEvent::Autonat(event)) => Some(event),
+            _ => None,
+        }
+    }
 
+    /// Returns the event of the identify protocol, if that's what it is.
+    pub fn identify(&self) -> Option<&identify::Event> {
+        match self.0 {
+            SwarmEvent::Behaviour(NodeEvent::Identify(event)) => Some(event),
+            _ => None,
+        }
+    }
+
+    /// Returns the event of the gossipsub broadcast, if that's what it is.
+    pub fn gossipsub(&self) -> Option<&gossipsub::Event> {
+        match self.0 {
+            SwarmEvent::Behaviour(NodeEvent::Gossipsub(event)) => Some(event),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for RawSwarmEvent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.0, f)
+    }
+}
+
+/// The protocols run by the node
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "NodeEvent")]
-pub(super) struct NodeBehaviour {
+pub(crate) struct NodeBehaviour {
     pub(super) request_response: request_response::Behaviour<MsgCodec>,
+    pub(super) raw_request_response: request_response::Behaviour<RawCodec>,
     pub(super) kademlia: Kademlia<MemoryStore>,
-    pub(super) mdns: mdns::async_io::Behaviour,
+    pub(super) mdns: Toggle<mdns::async_io::Behaviour>,
+    pub(super) autonat: autonat::Behaviour,
+    pub(super) identify: FilteredIdentify,
+    pub(super) gossipsub: gossipsub::Behaviour,
+    pub(super) staggered_dials: StaggeredDialGuard,
+    pub(super) dial_limit: DialLimit,
 }
 
+/// The events emitted by the protocols of the `NodeBehaviour`
 #[derive(Debug)]
-pub(super) enum NodeEvent {
-    RequestResponse(request_response::Event<Request, Response>),
+pub(crate) enum NodeEvent {
+    /// Event of the `Request`/`Response` protocol
+    RequestResponse(request_response::Event<MsgRequest, Response>),
+    /// Event of the raw bytes protocol
+    RawRequestResponse(request_response::Event<Vec<u8>, RawResponse>),
+    /// Event of the Kademlia DHT
     Kademlia(KademliaEvent),
+    /// Event of the mDNS discovery
     Mdns(Box<mdns::Event>),
+    /// Event of the AutoNAT probing
+    Autonat(autonat::Event),
+    /// Event of the identify protocol
+    Identify(Box<identify::Event>),
+    /// Event of the gossipsub broadcast
+    Gossipsub(Box<gossipsub::Event>),
 }
 
-impl From<request_response::Event<Request, Response>> for NodeEvent {
-    fn from(event: request_response::Event<Request, Response>) -> Self {
+impl From<request_response::Event<MsgRequest, Response>> for NodeEvent {
+    fn from(event: request_response::Event<MsgRequest, Response>) -> Self {
         NodeEvent::RequestResponse(event)
     }
 }
 
+impl From<request_response::Event<Vec<u8>, RawResponse>> for NodeEvent {
+    fn from(event: request_response::Event<Vec<u8>, RawResponse>) -> Self {
+        NodeEvent::RawRequestResponse(event)
+    }
+}
+
 impl From<KademliaEvent> for NodeEvent {
     fn from(event: KademliaEvent) -> Self {
         NodeEvent::Kademlia(event)
@@ -54,7 +155,68 @@ impl From<mdns::Event> for NodeEvent {
     }
 }
 
+impl From<identify::Event> for NodeEvent {
+    fn from(event: identify::Event) -> Self {
+        NodeEvent::Identify(Box::new(event))
+    }
+}
+
+impl From<gossipsub::Event> for NodeEvent {
+    fn from(event: gossipsub::Event) -> Self {
+        NodeEvent::Gossipsub(Box::new(event))
+    }
+}
+
+impl From<Infallible> for NodeEvent {
+    fn from(event: Infallible) -> Self {
+        match event {}
+    }
+}
+
+impl From<autonat::Event> for NodeEvent {
+    fn from(event: autonat::Event) -> Self {
+        NodeEvent::Autonat(event)
+    }
+}
+
+/// The channel to answer an inbound request through, see `Network::send_response`. The copies
+/// of a handle share the channel, the first response sent through any of them being the only
+/// one delivered.
 #[derive(Debug)]
+pub struct ResponseHandle<T> {
+    peer: PeerId,
+    channel: Arc<Mutex<Option<ResponseChannel<T>>>>,
+}
+
+impl<T> ResponseHandle<T> {
+    pub(super) fn new(peer: PeerId, channel: ResponseChannel<T>) -> Self {
+        Self {
+            peer,
+            channel: Arc::new(Mutex::new(Some(channel))),
+        }
+    }
+
+    /// The peer that sent the request, the response going to it.
+    pub fn peer(&self) -> PeerId {
+        self.peer
+    }
+
+    /// Takes the channel out, `None` once a response has been sent through a copy.
+    pub(super) fn take(&self) -> Option<ResponseChannel<T>> {
+        self.channel.lock().ok()?.take()
+    }
+}
+
+impl<T> Clone for ResponseHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            peer: self.peer,
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 /// Events forwarded by the underlying Network; to be used by the upper layers
 pub enum NetworkEvent {
     /// Incoming `Request` from a peer
@@ -62,11 +224,185 @@ pub enum NetworkEvent {
         /// Request
         req: Request,
         /// The channel to send the `Response` through
-        channel: ResponseChannel<Response>,
+        channel: ResponseHandle<Response>,
+        /// The endpoints of the connections currently open with the requesting peer. The
+        /// request-response protocol does not report which of the connections carried the
+        /// request, hence all of them are listed; there is usually only one.
+        endpoints: Vec<ConnectedPoint>,
+    },
+    /// Incoming raw request from a peer; the bytes have not been validated in any way
+    RawRequestReceived {
+        /// The raw bytes sent by the peer
+        bytes: Vec<u8>,
+        /// The channel to send the raw response through
+        channel: ResponseHandle<RawResponse>,
+    },
+    /// Emitted when AutoNAT first confirms that we are publicly reachable at `addr`
+    ExternalAddressConfirmed {
+        /// Our confirmed external address
+        addr: Multiaddr,
+    },
+    /// Emitted when the previously confirmed external address is no longer confirmed
+    ExternalAddressExpired {
+        /// The external address that is no longer confirmed
+        addr: Multiaddr,
+    },
+    /// Emitted when a peer has identified itself
+    PeerIdentified {
+        /// The identified peer
+        peer_id: PeerId,
+        /// The agent version advertised by the peer
+        agent_version: String,
+        /// The node metadata advertised by the peer, if any
+        node_metadata: Option<String>,
+    },
+    /// Emitted when the connections to a peer are closed for it advertising a protocol version
+    /// below `NetworkConfig::min_peer_version`
+    PeerRejectedVersion {
+        /// The rejected peer
+        peer: PeerId,
+        /// The protocol version advertised by the peer
+        version: String,
+    },
+    /// Emitted when a dialed address answered with another `PeerId` than the one dialed, e.g. a
+    /// man-in-the-middle or a stale address now used by another node. The connection is
+    /// refused and the address dropped from the routing table.
+    PeerIdMismatch {
+        /// The peer that was dialed
+        expected: PeerId,
+        /// The peer that answered
+        actual: PeerId,
+        /// The address dialed
+        addr: Multiaddr,
+    },
+    /// A message published on a topic the node subscribed to through `Network::subscribe`
+    GossipMessage {
+        /// The topic the message was published on
+        topic: String,
+        /// The peer that published the message, which may not be the one that relayed it
+        source: PeerId,
+        /// The published data
+        data: Vec<u8>,
+    },
+    /// Emitted during `Network::bootstrap` every time one of the bootstrap peers is dialed
+    BootstrapProgress {
+        /// The number of bootstrap peers we connected to so far
+        connected: usize,
+        /// The number of bootstrap peers we failed to connect to so far
+        failed: usize,
+        /// The number of bootstrap peers to dial
+        total: usize,
+    },
+    /// Emitted when a bootstrap completed, whether started through `Network::bootstrap` or
+    /// from the `NetworkConfig::bootstrap_peers`
+    BootstrapComplete {
+        /// Why the bootstrap failed, `None` if it succeeded
+        error: Option<String>,
     },
-    /// Emmited when we discover a peer.
-    /// might/might not be successfully added to the DHT; `RoutingUpdate` is private/no debug impl
-    PeerDiscovered,
+    /// Emitted when the data of a `Network::store_data_persistent` has been advertised
+    DataStored {
+        /// The name of the data
+        xor_name: XorName,
+        /// The number of attempts it took
+        attempts: u32,
+    },
+    /// Emitted when `Network::store_data_persistent` gave up, past its deadline
+    DataStoreFailed {
+        /// The name of the data
+        xor_name: XorName,
+        /// The number of attempts made
+        attempts: u32,
+    },
+    /// Emitted when the first connection to a peer is established, whoever dialed it
+    PeerConnected {
+        /// The peer we are now connected to
+        peer_id: PeerId,
+        /// The remote address of the connection
+        addr: Multiaddr,
+    },
+    /// Emitted when the last connection to a peer is closed
+    PeerDisconnected {
+        /// The peer we are no longer connected to
+        peer_id: PeerId,
+        /// The remote address of the last connection
+        addr: Multiaddr,
+    },
+    /// Emitted when `Network::dial` is called for a peer we are already connected to; the
+    /// existing connection is reused and the dial completes right away
+    PeerAlreadyConnected {
+        /// The peer we are connected to
+        peer_id: PeerId,
+    },
+    /// Emitted when the node starts shedding load, see `LoadSheddingConfig`
+    LoadSheddingActivated {
+        /// The number of requests and queries waiting for their outcome
+        in_flight: usize,
+        /// The number of records held by the local store
+        stored_records: usize,
+    },
+    /// Emitted when the node stops shedding load, the resource usage being back to normal
+    LoadSheddingDeactivated,
+    /// Emitted when a record is stored under a key already holding a different value; the
+    /// outcome depends on the `NetworkConfig::record_conflict_policy`
+    RecordConflict {
+        /// The key of the record, the bytes of an `XorName` for those put through `Network`
+        key: Vec<u8>,
+        /// The peer putting the record; `None` for the local node
+        source: Option<PeerId>,
+        /// Whether the existing record has been kept, i.e. the new one discarded
+        kept_existing: bool,
+    },
+    /// Emitted when a peer reaches the `NetworkConfig::max_inbound_streams_per_peer`; its
+    /// requests beyond the limit are dropped. Emitted again only once the peer got back under
+    /// the limit
+    InboundStreamLimitReached {
+        /// The peer at the limit
+        peer: PeerId,
+        /// The limit
+        limit: usize,
+    },
+    /// Emitted when the last peer is removed from the routing table, leaving the node isolated
+    /// until it bootstraps again
+    RoutingTableEmpty,
+    /// Emitted when a peer is added to the routing table after it had become empty
+    RoutingTableRecovered,
+    /// Emitted when the connections to a peer are closed for being idle for too long
+    PeerReaped {
+        /// The peer we disconnected from
+        peer: PeerId,
+    },
+    /// Emitted when a request protocol is enabled or disabled, see `Network::disable_protocol`
+    ProtocolsChanged {
+        /// The names of the request protocols now enabled
+        active: Vec<String>,
+    },
+    /// Emitted when mDNS discovers a peer on the local network, its addresses being added to
+    /// the routing table; those that expire later on are removed from it
+    PeerDiscovered {
+        /// The discovered peer
+        peer_id: PeerId,
+        /// The addresses the peer announced
+        addrs: Vec<Multiaddr>,
+    },
+}
+
+impl NetworkEvent {
+    /// Returns a copy of the event, unless it carries a response channel.
+    pub(super) fn try_clone(&self) -> Option<Self> {
+        if self.carries_channel() {
+            None
+        } else {
+            Some(self.clone())
+        }
+    }
+
+    /// Whether the event carries a response channel, to be answered once.
+    pub(super) fn carries_channel(&self) -> bool {
+        matches!(
+            self,
+            NetworkEvent::RequestReceived { .. } | NetworkEvent::RawRequestReceived { .. }
+        )
+    }
 }
 
 impl NetworkSwarmLoop {
@@ -75,6 +411,9 @@ impl NetworkSwarmLoop {
         &mut self,
         event: SwarmEvent<NodeEvent, EventError>,
     ) -> Result<()> {
+        if let SwarmEvent::Behaviour(behaviour_event) = &event {
+            self.on_behaviour_event(behaviour_event);
+        }
         match event {
             // handle RequestResponse events
             SwarmEvent::Behaviour(NodeEvent::RequestResponse(event)) => {
@@ -82,33 +421,37 @@ impl NetworkSwarmLoop {
                     warn!("RequestResponseError: {e:?}");
                 }
             }
+            SwarmEvent::Behaviour(NodeEvent::RawRequestResponse(event)) => {
+                if let Err(e) = self.handle_raw_msg(event).await {
+                    warn!("RawRequestResponseError: {e:?}");
+                }
+            }
             // handle Kademlia events
             SwarmEvent::Behaviour(NodeEvent::Kademlia(event)) => match event {
                 KademliaEvent::OutboundQueryProgressed {
                     id,
-                    result: QueryResult::StartProviding(_),
+                    result: QueryResult::StartProviding(result),
                     ..
-                } => {
-                    let sender: oneshot::Sender<Result<()>> = self
-                        .pending_start_providing
-                        .remove(&id)
-                        .ok_or(Error::Other(
-                            "Completed query to be previously pending.".to_string(),
-                        ))?;
-                    let _ = sender.send(Ok(()));
-                }
+                } => self.on_start_providing_completed(id, result).await?,
                 KademliaEvent::OutboundQueryProgressed {
                     id,
                     result:
                         QueryResult::GetProviders(Ok(GetProvidersOk::FoundProviders {
                             providers, ..
                         })),
+                    stats,
                     ..
                 } => {
                     if let Some(sender) = self.pending_get_providers.remove(&id) {
+                        let query = self.op_traces.query_completed(&id);
+                        let _entered = query.as_ref().map(Span::enter);
+                        // The first result of the lookup, the later ones being ignored below.
+                        if let Some(latency) = stats.duration() {
+                            self.metrics.time_to_first_provider.observe(latency);
+                        }
                         sender
-                            .send(providers)
-                            .map_err(|_| Error::Other("Receiver not to be dropped".to_string()))?;
+                            .send(Ok(providers))
+                            .map_err(|_| Error::OneshotReceiverDropped)?;
 
                         // Finish the query. We are only interested in the first result.
                         self.swarm
@@ -119,54 +462,542 @@ impl NetworkSwarmLoop {
                             .finish();
                     }
                 }
+                // The lookup ended without finding any provider, e.g. timing out.
+                KademliaEvent::OutboundQueryProgressed {
+                    id,
+                    result: QueryResult::GetProviders(result),
+                    step,
+                    ..
+                } if step.last => {
+                    if let Some(sender) = self.pending_get_providers.remove(&id) {
+                        let query = self.op_traces.query_completed(&id);
+                        let _entered = query.as_ref().map(Span::enter);
+                        debug!("No provider found by {id:?}: {result:?}");
+                        let _ = sender.send(
+                            result
+                                .map(|_| HashSet::new())
+                                .map_err(Error::GetProvidersError),
+                        );
+                    }
+                }
+                // The inbound records are filtered, for the `RecordConflictPolicy` to apply.
+                KademliaEvent::InboundRequest {
+                    request:
+                        InboundRequest::PutRecord {
+                            source,
+                            record: Some(record),
+                            ..
+                        },
+                } => self.store_inbound_record(record, source).await?,
+                KademliaEvent::InboundRequest {
+                    request:
+                        InboundRequest::AddProvider {
+                            record: Some(record),
+                        },
+                } => {
+                    let store = self.swarm.behaviour_mut().kademlia.store_mut();
+                    if let Err(err) = store.add_provider(record) {
+                        warn!("Failed to store a provider record: {err}");
+                    }
+                }
+                KademliaEvent::OutboundQueryProgressed {
+                    id,
+                    result: QueryResult::PutRecord(result),
+                    ..
+                } => {
+                    if result.is_ok() {
+                        Metrics::inc(&self.metrics.records_put);
+                    }
+                    if let Some(sender) = self.pending_put_record.remove(&id) {
+                        let _ = sender.send(result.map(|_| ()).map_err(Error::from));
+                    }
+                }
+                KademliaEvent::OutboundQueryProgressed {
+                    id,
+                    result: QueryResult::GetRecord(result),
+                    ..
+                } => {
+                    if let Some(sender) = self.pending_get_record.remove(&id) {
+                        let value = match result {
+                            Ok(GetRecordOk::FoundRecord(peer_record)) => {
+                                // Finish the query. We are only interested in the first result.
+                                if let Some(mut query) =
+                                    self.swarm.behaviour_mut().kademlia.query_mut(&id)
+                                {
+                                    query.finish();
+                                }
+                                peer_record.record.try_into().map(Some)
+                            }
+                            Ok(GetRecordOk::FinishedWithNoAdditionalRecord { .. })
+                            | Err(GetRecordError::NotFound { .. }) => Ok(None),
+                            Err(err) => Err(err.into()),
+                        };
+                        let _ = sender.send(value);
+                    }
+                }
+                KademliaEvent::OutboundQueryProgressed {
+                    id,
+                    result: QueryResult::GetClosestPeers(result),
+                    ..
+                } => {
+                    if let Some(sender) = self.pending_get_closest_peers.remove(&id) {
+                        let (key, peers) = match result {
+                            Ok(GetClosestPeersOk { key, peers })
+                            | Err(GetClosestPeersError::Timeout { key, peers }) => (key, peers),
+                        };
+                        let _ = sender.send(Ok(sort_by_distance(key, peers)));
+                    }
+                }
+                // A bootstrap is made of several queries (one per bucket refresh); it is
+                // complete once the last one has progressed.
+                KademliaEvent::OutboundQueryProgressed {
+                    id,
+                    result: QueryResult::Bootstrap(result),
+                    step,
+                    ..
+                } if step.last || result.is_err() => {
+                    if result.is_ok() {
+                        self.bootstrapped = true;
+                        self.resume_providing().await?;
+                    }
+                    if let Some(sender) = self.pending_bootstrap.remove(&id) {
+                        self.complete_bootstrap(sender, result.map(|_| ()).map_err(Error::from))
+                            .await?;
+                    }
+                }
+                // There is a peer to advertise the logged keys to, without waiting for a
+                // bootstrap that may never be run.
+                KademliaEvent::RoutingUpdated { .. } => self.resume_providing().await?,
                 _ => {}
             },
             SwarmEvent::Behaviour(NodeEvent::Mdns(mdns_event)) => match *mdns_event {
                 mdns::Event::Discovered(list) => {
+                    // mDNS lists each address on its own, hence grouped by peer, in order.
+                    let mut discovered: Vec<(PeerId, Vec<Multiaddr>)> = Vec::new();
                     for (peer_id, multiaddr) in list {
                         info!("Node discovered: {multiaddr:?}");
+                        self.address_book
+                            .record(peer_id, multiaddr.clone(), AddressSource::Mdns);
                         let _routing_update = self
                             .swarm
                             .behaviour_mut()
                             .kademlia
-                            .add_address(&peer_id, multiaddr);
+                            .add_address(&peer_id, multiaddr.clone());
+                        match discovered.iter_mut().find(|(peer, _)| *peer == peer_id) {
+                            Some((_, addrs)) => addrs.push(multiaddr),
+                            None => discovered.push((peer_id, vec![multiaddr])),
+                        }
+                    }
+                    for (peer_id, addrs) in discovered {
+                        self.send_event(NetworkEvent::PeerDiscovered { peer_id, addrs })
+                            .await?;
                     }
-                    self.event_sender.send(NetworkEvent::PeerDiscovered).await?;
                 }
-                mdns::Event::Expired(_) => {
-                    info!("mdns peer expired");
+                mdns::Event::Expired(list) => {
+                    for (peer_id, multiaddr) in list {
+                        info!("mdns peer expired: {peer_id:?} at {multiaddr:?}");
+                        let removed_peer = self
+                            .swarm
+                            .behaviour_mut()
+                            .kademlia
+                            .remove_address(&peer_id, &multiaddr)
+                            .is_some();
+                        // Only drop the peers we learnt about through mDNS alone; the ones we
+                        // explicitly dialed or still know other addresses of are kept.
+                        if removed_peer
+                            && self.disconnect_expired_mdns_peers
+                            && !self.explicit_peers.contains(&peer_id)
+                            && self.swarm.disconnect_peer_id(peer_id).is_ok()
+                        {
+                            info!("Disconnected from expired mdns peer {peer_id:?}");
+                        }
+                    }
                 }
             },
+            SwarmEvent::Behaviour(NodeEvent::Autonat(autonat_event)) => {
+                if let autonat::Event::StatusChanged { old, new } = autonat_event {
+                    info!("NAT status changed from {old:?} to {new:?}");
+                    // Only the transitions of the reachability status are reported, hence
+                    // nothing is emitted when a probe re-confirms the same address.
+                    if let NatStatus::Public(addr) = old {
+                        if new != NatStatus::Public(addr.clone()) {
+                            self.send_event(NetworkEvent::ExternalAddressExpired { addr })
+                                .await?;
+                        }
+                    }
+                    if let NatStatus::Public(addr) = new {
+                        self.send_event(NetworkEvent::ExternalAddressConfirmed { addr })
+                            .await?;
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(NodeEvent::Gossipsub(gossip_event)) => {
+                self.handle_gossip(*gossip_event).await?;
+            }
+            SwarmEvent::Behaviour(NodeEvent::Identify(identify_event)) => {
+                if let identify::Event::Received { peer_id, info } = *identify_event {
+                    info!("Identified {peer_id:?} as {:?}", info.agent_version);
+                    if !self.is_version_accepted(&info.protocol_version) {
+                        warn!(
+                            "Rejecting {peer_id:?} running protocol version {:?}",
+                            info.protocol_version
+                        );
+                        let _ = self.swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
+                        self.forget_explicit_peer(&peer_id);
+                        let _ = self.swarm.disconnect_peer_id(peer_id);
+                        return self
+                            .send_event(NetworkEvent::PeerRejectedVersion {
+                                peer: peer_id,
+                                version: info.protocol_version,
+                            })
+                            .await;
+                    }
+                    for addr in info.listen_addrs {
+                        self.address_book
+                            .record(peer_id, addr, AddressSource::Identify);
+                    }
+                    let node_metadata = config::node_metadata(&info.agent_version);
+                    self.send_event(NetworkEvent::PeerIdentified {
+                        peer_id,
+                        agent_version: info.agent_version,
+                        node_metadata,
+                    })
+                    .await?;
+                }
+            }
             SwarmEvent::NewListenAddr { address, .. } => {
+                if !self.listen_addrs.contains(&address) {
+                    self.listen_addrs.push(address.clone());
+                }
                 let local_peer_id = *self.swarm.local_peer_id();
                 info!(
                     "Local node is listening on {:?}",
                     address.with(Protocol::P2p(local_peer_id.into()))
                 );
             }
+            SwarmEvent::ListenerClosed {
+                listener_id,
+                addresses,
+                ..
+            } => {
+                let _ = self.listeners.remove(&listener_id);
+                self.listen_addrs.retain(|addr| !addresses.contains(addr));
+                info!("Listener {listener_id:?} closed, no longer listening on {addresses:?}");
+            }
+            SwarmEvent::ExpiredListenAddr { address, .. } => {
+                self.listen_addrs.retain(|addr| addr != &address);
+                info!("No longer listening on {address:?}");
+            }
             SwarmEvent::IncomingConnection { .. } => {}
             SwarmEvent::ConnectionEstablished {
-                peer_id, endpoint, ..
+                peer_id,
+                endpoint,
+                num_established,
+                ..
             } => {
+                self.op_traces.dial_completed(&peer_id);
+                let _ = self.dial_deadlines.remove(&peer_id);
+                self.peer_endpoints
+                    .entry(peer_id)
+                    .or_default()
+                    .push(endpoint.clone());
+                Metrics::inc(&self.metrics.connections_established);
+                self.record_peer_activity(peer_id);
                 if endpoint.is_dialer() {
                     info!("Connected with {peer_id:?}");
+                    self.address_book.record(
+                        peer_id,
+                        endpoint.get_remote_address().clone(),
+                        AddressSource::Dialed,
+                    );
+                    self.on_staggered_dial_connected(peer_id);
                     if let Some(sender) = self.pending_dial.remove(&peer_id) {
                         let _ = sender.send(Ok(()));
                     }
                 }
+                self.on_bootstrap_dial_completed(peer_id, true).await?;
+                if num_established.get() == 1 {
+                    self.send_event(NetworkEvent::PeerConnected {
+                        peer_id,
+                        addr: endpoint.get_remote_address().clone(),
+                    })
+                    .await?;
+                }
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                endpoint,
+                num_established,
+                ..
+            } => {
+                Metrics::inc(&self.metrics.connections_closed);
+                if let hash_map::Entry::Occupied(mut entry) = self.peer_endpoints.entry(peer_id) {
+                    let endpoints = entry.get_mut();
+                    if let Some(index) = endpoints.iter().position(|e| *e == endpoint) {
+                        let _ = endpoints.swap_remove(index);
+                    }
+                    if endpoints.is_empty() {
+                        let _ = entry.remove();
+                        self.forget_explicit_peer(&peer_id);
+                        let _ = self.peer_last_activity.remove(&peer_id);
+                        let _ = self.last_ping.remove(&peer_id);
+                        let _ = self.peer_latencies.remove(&peer_id);
+                    }
+                }
+                if num_established == 0 {
+                    self.send_event(NetworkEvent::PeerDisconnected {
+                        peer_id,
+                        addr: endpoint.get_remote_address().clone(),
+                    })
+                    .await?;
+                }
             }
-            SwarmEvent::ConnectionClosed { .. } => {}
             SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
                 if let Some(peer_id) = peer_id {
-                    if let Some(sender) = self.pending_dial.remove(&peer_id) {
-                        let _ = sender.send(Err(error.into()));
+                    self.op_traces.dial_completed(&peer_id);
+                    let error = match error {
+                        DialError::WrongPeerId { obtained, endpoint } => {
+                            self.on_peer_id_mismatch(peer_id, obtained, endpoint)
+                                .await?
+                        }
+                        error => error.into(),
+                    };
+                    // The failed dials of a staggered dial still in progress are not reported.
+                    if !self.on_staggered_dial_failed(peer_id) {
+                        let _ = self.dial_deadlines.remove(&peer_id);
+                        if let Some(sender) = self.pending_dial.remove(&peer_id) {
+                            let _ = sender.send(Err(error));
+                        }
+                        self.on_bootstrap_dial_completed(peer_id, false).await?;
                     }
                 }
             }
             SwarmEvent::IncomingConnectionError { .. } => {}
             SwarmEvent::Dialing(peer_id) => info!("Dialing {peer_id}"),
-            e => panic!("{e:?}"),
+            other => debug!("Ignoring swarm event: {other:?}"),
         }
         Ok(())
     }
 }
+
+impl NetworkSwarmLoop {
+    // Accounts for the outcome of a query advertising provided data: that of a persistent store
+    // is retried or reported as such, that of a republish only logged, and the others are
+    // returned to their caller.
+    async fn on_start_providing_completed(
+        &mut self,
+        id: QueryId,
+        result: AddProviderResult,
+    ) -> Result<()> {
+        if self.republish_queries.remove(&id) {
+            debug!("Advertised the provided data again: {result:?}");
+            return Ok(());
+        }
+        if self
+            .on_persistent_store_completed(id, result.is_ok())
+            .await?
+        {
+            return Ok(());
+        }
+        let sender: oneshot::Sender<Result<()>> =
+            self.pending_start_providing
+                .remove(&id)
+                .ok_or(Error::Other(
+                    "Completed query to be previously pending.".to_string(),
+                ))?;
+        if result.is_ok() {
+            Metrics::inc(&self.metrics.provider_records_published);
+        }
+        let _ = sender.send(result.map(|_| ()).map_err(Error::from));
+        Ok(())
+    }
+
+    // Drops the address that answered with another `PeerId` than the dialed one, reporting the
+    // attempt, and returns the error to fail the dial with.
+    async fn on_peer_id_mismatch(
+        &mut self,
+        expected: PeerId,
+        actual: PeerId,
+        endpoint: ConnectedPoint,
+    ) -> Result<Error> {
+        let addr = endpoint.get_remote_address().clone();
+        warn!("Dialed {expected:?} at {addr:?}, but the remote identified as {actual:?}");
+        let _ = self
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .remove_address(&expected, &addr);
+        self.send_event(NetworkEvent::PeerIdMismatch {
+            expected,
+            actual,
+            addr,
+        })
+        .await?;
+        Ok(Error::PeerIdMismatch {
+            expected: Box::new(expected),
+            actual: Box::new(actual),
+        })
+    }
+
+    // Whether the protocol version advertised by a peer is at least the configured minimum.
+    fn is_version_accepted(&self, protocol_version: &str) -> bool {
+        match self.min_peer_version {
+            Some(min) => ProtocolVersion::parse(protocol_version).is_some_and(|v| v >= min),
+            None => true,
+        }
+    }
+}
+
+// Pairs each peer with its XOR distance to `key`, closest first.
+fn sort_by_distance(key: Vec<u8>, peers: Vec<PeerId>) -> Vec<(PeerId, Distance)> {
+    let target = KBucketKey::new(key);
+    let mut peers: Vec<(PeerId, Distance)> = peers
+        .into_iter()
+        .map(|peer| (peer, target.distance(&KBucketKey::from(peer))))
+        .collect();
+    peers.sort_by_key(|(_, distance)| *distance);
+    peers
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        config::ProtocolVersion,
+        error::Result,
+        testing::{isolated_config, listen_addr},
+        NetworkConfig, NetworkEvent, NetworkSwarmLoop,
+    };
+    use async_std::{future::timeout, task};
+    use futures::{channel::mpsc, Stream, StreamExt};
+    use libp2p::{identify, PeerId};
+    use std::time::Duration;
+    use xor_name::XorName;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[async_std::test]
+    async fn get_data_providers_resolves_with_no_provider() -> Result<()> {
+        let (mut network, events, event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let _event_loop = task::spawn(event_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+
+        let xor_name = XorName::random(&mut rand::thread_rng());
+        let providers = timeout(TEST_TIMEOUT, network.get_data_providers(xor_name))
+            .await
+            .expect("the lookup to complete")?;
+        assert!(providers.is_empty());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn the_peers_below_the_minimum_version_are_rejected() -> Result<()> {
+        let (mut remote, remote_events, remote_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _remote_loop = task::spawn(remote_loop.run());
+        let _remote_events = task::spawn(remote_events.for_each(|_| async {}));
+        let remote_addr = listen_addr(&mut remote).await?;
+
+        let (mut network, mut events, event_loop) = NetworkSwarmLoop::with_config(NetworkConfig {
+            min_peer_version: Some(ProtocolVersion {
+                major: 2,
+                minor: 0,
+                patch: 0,
+            }),
+            ..isolated_config()
+        })?;
+        let _event_loop = task::spawn(event_loop.run());
+        timeout(
+            TEST_TIMEOUT,
+            network.dial(remote.local_peer_id(), remote_addr),
+        )
+        .await
+        .expect("the dial to complete")?;
+
+        let rejected = timeout(TEST_TIMEOUT, async {
+            while let Some(event) = events.next().await {
+                if let NetworkEvent::PeerRejectedVersion { peer, version } = event {
+                    return Some((peer, version));
+                }
+            }
+            None
+        })
+        .await
+        .expect("the remote to be rejected");
+        assert_eq!(
+            rejected,
+            Some((remote.local_peer_id(), "/safe/1.0.0".to_string()))
+        );
+        let _events = task::spawn(events.for_each(|_| async {}));
+        assert!(network.routing_table_peers().await?.is_empty());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn the_raw_event_tap_lends_the_protocol_events() -> Result<()> {
+        let (mut remote, remote_events, remote_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _remote_loop = task::spawn(remote_loop.run());
+        let _remote_events = task::spawn(remote_events.for_each(|_| async {}));
+        let remote_addr = listen_addr(&mut remote).await?;
+
+        let (identified, mut tapped) = mpsc::unbounded();
+        let (mut network, events, event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let event_loop = event_loop.with_raw_event_tap(move |event| {
+            if let Some(identify::Event::Received { peer_id, .. }) = event.identify() {
+                let _ = identified.unbounded_send(*peer_id);
+            }
+        });
+        let _event_loop = task::spawn(event_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+
+        timeout(
+            TEST_TIMEOUT,
+            network.dial(remote.local_peer_id(), remote_addr),
+        )
+        .await
+        .expect("the dial to complete")?;
+        let peer = timeout(TEST_TIMEOUT, tapped.next())
+            .await
+            .expect("the remote to identify");
+        assert_eq!(peer, Some(remote.local_peer_id()));
+        Ok(())
+    }
+
+    // Waits for `peer` to be discovered through mDNS, then keeps draining the events.
+    async fn discovered(
+        mut events: impl Stream<Item = NetworkEvent> + Unpin + Send + 'static,
+        peer: PeerId,
+    ) {
+        while let Some(event) = events.next().await {
+            if matches!(event, NetworkEvent::PeerDiscovered { peer_id, .. } if peer_id == peer) {
+                break;
+            }
+        }
+        let _events = task::spawn(events.for_each(|_| async {}));
+    }
+
+    #[async_std::test]
+    async fn mdns_discovered_peers_are_added_to_the_routing_table() -> Result<()> {
+        let (mut first, first_events, first_loop) = NetworkSwarmLoop::new()?;
+        let (mut second, second_events, second_loop) = NetworkSwarmLoop::new()?;
+        let _first_loop = task::spawn(first_loop.run());
+        let _second_loop = task::spawn(second_loop.run());
+        let first_id = first.local_peer_id();
+        let second_id = second.local_peer_id();
+
+        let ((), ()) = timeout(
+            TEST_TIMEOUT,
+            futures::future::join(
+                discovered(first_events, second_id),
+                discovered(second_events, first_id),
+            ),
+        )
+        .await
+        .expect("the nodes to discover each other");
+
+        assert!(first.routing_table_peers().await?.contains(&second_id));
+        assert!(second.routing_table_peers().await?.contains(&first_id));
+        Ok(())
+    }
+}
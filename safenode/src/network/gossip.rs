@@ -0,0 +1,176 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    error::{Error, Result},
+    NetworkEvent, NetworkSwarmLoop,
+};
+use libp2p::{
+    gossipsub::{self, IdentTopic, PublishError},
+    identity::Keypair,
+};
+use tracing::{debug, trace};
+
+/// The maximum size of a gossipsub RPC, hence of a published message once signed and framed.
+/// Gossipsub's own default, independent of the `ProtocolLimits` of the request protocols: the
+/// messages are relayed by every peer of the mesh, so they are kept small.
+pub(super) const MAX_GOSSIP_SIZE: usize = 64 * 1024;
+
+/// Builds the gossipsub behaviour, signing the published messages with the node's keypair so
+/// that the receivers can tell their source.
+pub(super) fn behaviour(keypair: &Keypair) -> Result<gossipsub::Behaviour> {
+    let config = gossipsub::ConfigBuilder::default()
+        .max_transmit_size(MAX_GOSSIP_SIZE)
+        .build()
+        .map_err(|err| Error::Other(format!("Invalid gossipsub config: {err}")))?;
+    gossipsub::Behaviour::new(
+        gossipsub::MessageAuthenticity::Signed(keypair.clone()),
+        config,
+    )
+    .map_err(|err| Error::Other(format!("Invalid gossipsub config: {err}")))
+}
+
+impl NetworkSwarmLoop {
+    /// Subscribes to the topic; gossipsub announces the subscription to every peer connected
+    /// from then on, so it holds across reconnects.
+    pub(super) fn subscribe_topic(&mut self, topic: &str) -> Result<()> {
+        let subscribed = self
+            .swarm
+            .behaviour_mut()
+            .gossipsub
+            .subscribe(&IdentTopic::new(topic))?;
+        if !subscribed {
+            trace!("Already subscribed to topic {topic}");
+        }
+        Ok(())
+    }
+
+    /// Publishes the data to the peers subscribed to the topic.
+    pub(super) fn publish_to_topic(&mut self, topic: String, data: Vec<u8>) -> Result<()> {
+        match self
+            .swarm
+            .behaviour_mut()
+            .gossipsub
+            .publish(IdentTopic::new(topic.clone()), data)
+        {
+            Ok(_message_id) => Ok(()),
+            Err(PublishError::InsufficientPeers) => Err(Error::NoTopicPeers(topic)),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Forwards the gossiped messages to the upper layers.
+    pub(super) async fn handle_gossip(&mut self, event: gossipsub::Event) -> Result<()> {
+        match event {
+            gossipsub::Event::Message {
+                propagation_source,
+                message,
+                ..
+            } => {
+                // The messages are signed, hence always carry their source; the peer that
+                // forwarded them is a mere fallback.
+                let source = message.source.unwrap_or(propagation_source);
+                self.send_event(NetworkEvent::GossipMessage {
+                    topic: message.topic.into_string(),
+                    source,
+                    data: message.data,
+                })
+                .await?;
+            }
+            other => debug!("Gossipsub event: {other:?}"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MAX_GOSSIP_SIZE;
+    use crate::network::{
+        error::{Error, Result},
+        testing::{isolated_config, listen_addr},
+        NetworkEvent, NetworkSwarmLoop,
+    };
+    use async_std::{future::timeout, task};
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+    const TOPIC: &str = "test-topic";
+
+    #[async_std::test]
+    async fn a_published_message_reaches_the_subscribers() -> Result<()> {
+        let (mut subscriber, mut subscriber_events, subscriber_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _subscriber_loop = task::spawn(subscriber_loop.run());
+        let subscriber_addr = listen_addr(&mut subscriber).await?;
+        subscriber.subscribe(TOPIC).await?;
+
+        let (mut publisher, events, publisher_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _publisher_loop = task::spawn(publisher_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+        publisher.subscribe(TOPIC).await?;
+        timeout(
+            TEST_TIMEOUT,
+            publisher.dial(subscriber.local_peer_id(), subscriber_addr),
+        )
+        .await
+        .expect("the dial to complete")?;
+
+        // Until the subscription of the peer has been exchanged.
+        timeout(TEST_TIMEOUT, async {
+            loop {
+                match publisher.publish(TOPIC, b"hello".to_vec()).await {
+                    Err(Error::NoTopicPeers(_)) => task::sleep(Duration::from_millis(50)).await,
+                    result => return result,
+                }
+            }
+        })
+        .await
+        .expect("the subscription to be known")?;
+
+        let (topic, source, data) = timeout(TEST_TIMEOUT, async {
+            while let Some(event) = subscriber_events.next().await {
+                if let NetworkEvent::GossipMessage {
+                    topic,
+                    source,
+                    data,
+                } = event
+                {
+                    return Some((topic, source, data));
+                }
+            }
+            None
+        })
+        .await
+        .expect("the message to be received")
+        .ok_or_else(|| Error::Other("The subscriber's events ended".to_string()))?;
+        assert_eq!(topic, TOPIC);
+        assert_eq!(source, publisher.local_peer_id());
+        assert_eq!(data, b"hello");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn a_message_without_subscribers_or_too_large_is_refused() -> Result<()> {
+        let (mut network, events, event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let _event_loop = task::spawn(event_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+
+        assert!(matches!(
+            network.publish(TOPIC, b"hello".to_vec()).await,
+            Err(Error::NoTopicPeers(topic)) if topic == TOPIC
+        ));
+        assert!(matches!(
+            network.publish(TOPIC, vec![0; MAX_GOSSIP_SIZE]).await,
+            Err(Error::PublishError(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    error::{Error, Result},
+    NetworkSwarmLoop,
+};
+use libp2p::{identity::Keypair, kad::store::RecordStore, Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use xor_name::XorName;
+
+/// The current version of the `IdentityBundle` encoding.
+const IDENTITY_BUNDLE_VERSION: u8 = 1;
+
+/// Everything needed to resume a node's identity and connectivity elsewhere, e.g. on new
+/// hardware: its keypair, the peers in its routing table and the keys it provides.
+///
+/// Exported by `Network::export_identity_bundle` and restored by
+/// `NetworkSwarmLoop::from_identity_bundle`. It is encoded with MessagePack (`rmp_serde`) as
+/// `[version, keypair, [[peer_id, [address, ..]], ..], [key, ..]]`, where the keypair is in the
+/// libp2p protobuf encoding and the peer ids and addresses are in their binary form.
+///
+/// The bundle holds the node's private key in the clear, hence has to be stored and moved
+/// around as securely as the key itself.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityBundle {
+    version: u8,
+    keypair: Vec<u8>,
+    peers: Vec<(Vec<u8>, Vec<Vec<u8>>)>,
+    provided_keys: Vec<XorName>,
+}
+
+// Not derived, so the private key never ends up in the logs.
+impl std::fmt::Debug for IdentityBundle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IdentityBundle")
+            .field("version", &self.version)
+            .field("peers", &self.peers.len())
+            .field("provided_keys", &self.provided_keys.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl IdentityBundle {
+    /// Encodes the bundle, to be written to disk or sent over to the new host.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        rmp_serde::to_vec(self)
+            .map_err(|err| Error::InvalidIdentityBundle(format!("failed to encode: {err}")))
+    }
+
+    /// Decodes a bundle encoded by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let bundle: Self = rmp_serde::from_slice(bytes)
+            .map_err(|err| Error::InvalidIdentityBundle(format!("failed to decode: {err}")))?;
+        if bundle.version != IDENTITY_BUNDLE_VERSION {
+            return Err(Error::UnsupportedIdentityBundleVersion(bundle.version));
+        }
+        Ok(bundle)
+    }
+
+    /// Returns the `PeerId` the node restored from the bundle will have.
+    pub fn peer_id(&self) -> Result<PeerId> {
+        Ok(PeerId::from(self.keypair()?.public()))
+    }
+
+    /// Returns the keys provided by the node, to be advertised again once restored.
+    pub fn provided_keys(&self) -> &[XorName] {
+        &self.provided_keys
+    }
+
+    pub(super) fn keypair(&self) -> Result<Keypair> {
+        Keypair::from_protobuf_encoding(&self.keypair)
+            .map_err(|err| Error::InvalidIdentityBundle(format!("invalid keypair: {err}")))
+    }
+
+    pub(super) fn peers(&self) -> Result<Vec<(PeerId, Vec<Multiaddr>)>> {
+        self.peers
+            .iter()
+            .map(|(peer_id, addrs)| {
+                let peer_id = PeerId::from_bytes(peer_id)
+                    .map_err(|err| Error::InvalidIdentityBundle(format!("invalid peer: {err}")))?;
+                let addrs = addrs
+                    .iter()
+                    .map(|addr| {
+                        Multiaddr::try_from(addr.clone()).map_err(|err| {
+                            Error::InvalidIdentityBundle(format!("invalid address: {err}"))
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                Ok((peer_id, addrs))
+            })
+            .collect()
+    }
+}
+
+impl NetworkSwarmLoop {
+    /// Snapshots the keypair, the routing table and the provided keys in one go, so they are
+    /// consistent with each other.
+    pub(super) fn export_identity_bundle(&mut self) -> Result<IdentityBundle> {
+        let keypair = self
+            .keypair
+            .to_protobuf_encoding()
+            .map_err(|err| Error::InvalidIdentityBundle(format!("invalid keypair: {err}")))?;
+        let kademlia = &mut self.swarm.behaviour_mut().kademlia;
+        let peers = kademlia
+            .kbuckets()
+            .flat_map(|bucket| {
+                bucket
+                    .iter()
+                    .map(|entry| {
+                        let addrs = entry.node.value.iter().map(|addr| addr.to_vec()).collect();
+                        (entry.node.key.preimage().to_bytes(), addrs)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let provided_keys = kademlia
+            .store_mut()
+            .provided()
+            // Only the keys that are `XorName`s are provided through the `Network` API.
+            .filter_map(|record| <[u8; 32]>::try_from(record.key.as_ref()).ok())
+            .map(XorName)
+            .collect();
+        Ok(IdentityBundle {
+            version: IDENTITY_BUNDLE_VERSION,
+            keypair,
+            peers,
+            provided_keys,
+        })
+    }
+
+    /// Restores the routing table and the provided keys of the bundle onto a loop built with
+    /// the bundle's keypair. The keys are advertised again once bootstrapped.
+    pub(super) fn restore_identity_bundle(&mut self, bundle: &IdentityBundle) -> Result<()> {
+        for (peer_id, addrs) in bundle.peers()? {
+            for addr in addrs {
+                let _routing_update = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .add_address(&peer_id, addr);
+            }
+        }
+        for key in bundle.provided_keys() {
+            self.log_provided(*key)?;
+            if !self.resumed_provided_keys.contains(key) {
+                self.resumed_provided_keys.push(*key);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdentityBundle;
+    use crate::network::{
+        error::{Error, Result},
+        testing::isolated_config,
+        NetworkSwarmLoop,
+    };
+    use libp2p::{Multiaddr, PeerId};
+    use xor_name::XorName;
+
+    #[test]
+    fn a_bundle_restores_the_identity_peers_and_provided_keys() -> Result<()> {
+        let (network, _events, mut event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let peer = PeerId::random();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/12000".parse()?;
+        let key = XorName([1; 32]);
+        let kademlia = &mut event_loop.swarm.behaviour_mut().kademlia;
+        let _ = kademlia.add_address(&peer, addr.clone());
+        let _ = kademlia.start_providing(key.0.to_vec().into())?;
+
+        let bundle = IdentityBundle::from_bytes(&event_loop.export_identity_bundle()?.to_bytes()?)?;
+        assert_eq!(bundle.peer_id()?, network.local_peer_id());
+        assert_eq!(bundle.peers()?, vec![(peer, vec![addr])]);
+        assert_eq!(bundle.provided_keys(), [key]);
+
+        let (restored, _events, mut restored_loop) =
+            NetworkSwarmLoop::from_identity_bundle(isolated_config(), &bundle)?;
+        assert_eq!(restored.local_peer_id(), network.local_peer_id());
+        assert_eq!(restored_loop.resumed_provided_keys, vec![key]);
+        let peers: Vec<PeerId> = restored_loop
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .kbuckets()
+            .flat_map(|bucket| {
+                bucket
+                    .iter()
+                    .map(|entry| *entry.node.key.preimage())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(peers, vec![peer]);
+        Ok(())
+    }
+
+    #[test]
+    fn a_bundle_of_another_version_or_corrupted_is_refused() -> Result<()> {
+        let (_network, _events, mut event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let mut bundle = event_loop.export_identity_bundle()?;
+        bundle.version += 1;
+        assert!(matches!(
+            IdentityBundle::from_bytes(&bundle.to_bytes()?),
+            Err(Error::UnsupportedIdentityBundleVersion(2))
+        ));
+        assert!(matches!(
+            IdentityBundle::from_bytes(b"not a bundle"),
+            Err(Error::InvalidIdentityBundle(_))
+        ));
+        Ok(())
+    }
+}
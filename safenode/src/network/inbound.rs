@@ -0,0 +1,123 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{error::Result, metrics::Metrics, NetworkEvent, NetworkSwarmLoop};
+use libp2p::{request_response::RequestId, PeerId};
+use tracing::warn;
+
+/// An inbound request waiting for its response. The typed and raw protocols number their
+/// requests independently, hence the protocol is part of the identity of the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) enum InboundStream {
+    Msg(RequestId),
+    Raw(RequestId),
+}
+
+impl NetworkSwarmLoop {
+    /// Accounts for a new inbound stream from `peer`, unless the peer is already at the
+    /// `NetworkConfig::max_inbound_streams_per_peer`. Returns whether the stream is accepted;
+    /// a rejected request is to be dropped, which fails it on both ends.
+    pub(super) async fn open_inbound_stream(
+        &mut self,
+        peer: PeerId,
+        stream: InboundStream,
+    ) -> Result<bool> {
+        let streams = self.inbound_streams.entry(peer).or_default();
+        if let Some(limit) = self.max_inbound_streams_per_peer {
+            if streams.len() >= limit {
+                Metrics::inc(&self.metrics.inbound_streams_rejected);
+                // Only reported once, until the peer gets back under the limit.
+                if self.capped_peers.insert(peer) {
+                    warn!("{peer:?} reached the limit of {limit} concurrent inbound streams");
+                    self.send_event(NetworkEvent::InboundStreamLimitReached { peer, limit })
+                        .await?;
+                }
+                return Ok(false);
+            }
+        }
+        let _ = streams.insert(stream);
+        Ok(true)
+    }
+
+    /// Accounts for an inbound stream having been answered or having failed.
+    pub(super) fn close_inbound_stream(&mut self, peer: PeerId, stream: InboundStream) {
+        // The rejected streams, never accounted for, fail too.
+        let streams = match self.inbound_streams.get_mut(&peer) {
+            Some(streams) => streams,
+            None => return,
+        };
+        if !streams.remove(&stream) {
+            return;
+        }
+        if streams.is_empty() {
+            let _ = self.inbound_streams.remove(&peer);
+        }
+        let _ = self.capped_peers.remove(&peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        error::Result,
+        testing::{isolated_config, listen_addr},
+        NetworkConfig, NetworkEvent, NetworkSwarmLoop, Request,
+    };
+    use async_std::{future::timeout, task};
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[async_std::test]
+    async fn the_requests_beyond_the_limit_of_a_peer_are_refused() -> Result<()> {
+        let (mut responder, mut responder_events, responder_loop) =
+            NetworkSwarmLoop::with_config(NetworkConfig {
+                max_inbound_streams_per_peer: Some(1),
+                ..isolated_config()
+            })?;
+        let _responder_loop = task::spawn(responder_loop.run());
+        let responder_id = responder.local_peer_id();
+        let responder_addr = listen_addr(&mut responder).await?;
+
+        let (network, events, event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let _event_loop = task::spawn(event_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+        let mut dialer = network.clone();
+        timeout(TEST_TIMEOUT, dialer.dial(responder_id, responder_addr))
+            .await
+            .expect("the dial to complete")?;
+        for _ in 0..2 {
+            let mut requester = network.clone();
+            let _request =
+                task::spawn(
+                    async move { requester.send_request(Request::GetDBC, responder_id).await },
+                );
+        }
+
+        // The first request is left unanswered, holding the only stream allowed.
+        let mut received = Vec::new();
+        let limit = timeout(TEST_TIMEOUT, async {
+            while let Some(event) = responder_events.next().await {
+                match event {
+                    NetworkEvent::RequestReceived { channel, .. } => received.push(channel),
+                    NetworkEvent::InboundStreamLimitReached { peer, limit } => {
+                        return Some((peer, limit))
+                    }
+                    _ => {}
+                }
+            }
+            None
+        })
+        .await
+        .expect("the limit to be reached");
+        assert_eq!(limit, Some((network.local_peer_id(), 1)));
+        assert_eq!(received.len(), 1);
+        Ok(())
+    }
+}
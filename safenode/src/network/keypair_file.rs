@@ -0,0 +1,115 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::error::{Error, Result};
+use libp2p::identity::Keypair;
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::Path,
+};
+use tracing::info;
+
+/// Loads the keypair saved at `path`, see `NetworkConfig::keypair_path`, or generates a new
+/// ed25519 keypair and saves it there if the file doesn't exist yet.
+pub(super) fn load_or_generate(path: &Path) -> Result<Keypair> {
+    match fs::read(path) {
+        Ok(bytes) => {
+            let keypair = Keypair::from_protobuf_encoding(&bytes).map_err(|err| {
+                Error::InvalidKeypairFile {
+                    path: path.to_path_buf(),
+                    reason: err.to_string(),
+                }
+            })?;
+            info!("Loaded the keypair from {path:?}");
+            Ok(keypair)
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            let keypair = Keypair::generate_ed25519();
+            let bytes =
+                keypair
+                    .to_protobuf_encoding()
+                    .map_err(|err| Error::InvalidKeypairFile {
+                        path: path.to_path_buf(),
+                        reason: err.to_string(),
+                    })?;
+            write_atomically(path, &bytes)?;
+            info!("Saved a new keypair to {path:?}");
+            Ok(keypair)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Writes the file readable by its owner only, through a temporary file renamed into place once
+// synced, so that a crash never leaves a truncated keypair behind.
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".tmp");
+    let temp_path = dir.join(file_name);
+
+    let mut options = OpenOptions::new();
+    let _ = options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        let _ = options.mode(0o600);
+    }
+    let mut file = options.open(&temp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::load_or_generate;
+    use crate::network::error::{Error, Result};
+    use assert_fs::TempDir;
+    use std::fs;
+
+    #[test]
+    fn a_generated_keypair_is_loaded_back() -> Result<()> {
+        let dir = TempDir::new().map_err(|err| Error::Other(err.to_string()))?;
+        let path = dir.path().join("keys").join("keypair");
+
+        let generated = load_or_generate(&path)?;
+        let loaded = load_or_generate(&path)?;
+        assert_eq!(generated.public(), loaded.public());
+        assert!(!dir.path().join("keys").join("keypair.tmp").exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn a_corrupt_keypair_file_is_an_error() -> Result<()> {
+        let dir = TempDir::new().map_err(|err| Error::Other(err.to_string()))?;
+        let path = dir.path().join("keypair");
+        fs::write(&path, b"not a keypair")?;
+
+        match load_or_generate(&path) {
+            Err(Error::InvalidKeypairFile { path: err_path, .. }) => assert_eq!(err_path, path),
+            other => panic!("expected an invalid keypair file error, got {other:?}"),
+        }
+        // The corrupt file is left untouched, not replaced by a new keypair.
+        assert_eq!(fs::read(&path)?, b"not a keypair");
+        Ok(())
+    }
+}
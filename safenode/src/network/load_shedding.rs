@@ -0,0 +1,176 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{error::Result, NetworkEvent, NetworkSwarmLoop};
+use libp2p::kad::store::RecordStore;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How often the resource usage is checked against the `LoadSheddingConfig` thresholds.
+pub(super) const LOAD_CHECK_TICK: Duration = Duration::from_secs(1);
+
+impl NetworkSwarmLoop {
+    /// Starts or stops shedding load as per the `NetworkConfig::load_shedding` thresholds,
+    /// reporting the transitions.
+    pub(super) async fn check_load(&mut self) -> Result<()> {
+        let config = match self.load_shedding {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+        let in_flight = self.in_flight_count();
+        let stored_records = self
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .store_mut()
+            .records()
+            .count();
+        let overloaded =
+            in_flight >= config.max_in_flight || stored_records >= config.max_stored_records;
+        if overloaded == self.shedding_load {
+            return Ok(());
+        }
+        self.shedding_load = overloaded;
+        if overloaded {
+            warn!("Shedding load: {in_flight} operations in flight, {stored_records} records");
+            self.send_event(NetworkEvent::LoadSheddingActivated {
+                in_flight,
+                stored_records,
+            })
+            .await
+        } else {
+            info!(
+                "Stopped shedding load: {in_flight} operations in flight, {stored_records} records"
+            );
+            self.send_event(NetworkEvent::LoadSheddingDeactivated).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        config::LoadSheddingConfig,
+        error::{Error, Result},
+        testing::{isolated_config, listen_addr},
+        NetworkConfig, NetworkEvent, NetworkSwarmLoop, Request, Response,
+    };
+    use async_std::{future::timeout, task};
+    use futures::StreamExt;
+    use libp2p::kad::{store::RecordStore, Record, RecordKey};
+    use std::time::Duration;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[async_std::test]
+    async fn load_is_shed_from_the_threshold_until_back_below_it() -> Result<()> {
+        let (_network, events, mut event_loop) = NetworkSwarmLoop::with_config(NetworkConfig {
+            load_shedding: Some(LoadSheddingConfig {
+                max_stored_records: 2,
+                ..Default::default()
+            }),
+            ..isolated_config()
+        })?;
+        let mut events = Box::pin(events);
+        let key = |byte: u8| RecordKey::new(&[byte; 32]);
+        for byte in [1, 2] {
+            event_loop.check_load().await?;
+            assert!(!event_loop.shedding_load);
+            event_loop
+                .swarm
+                .behaviour_mut()
+                .kademlia
+                .store_mut()
+                .put(Record::new(key(byte), vec![byte]))?;
+        }
+        event_loop.check_load().await?;
+        assert!(event_loop.shedding_load);
+        assert!(matches!(
+            events.next().await,
+            Some(NetworkEvent::LoadSheddingActivated {
+                stored_records: 2,
+                ..
+            })
+        ));
+        // Reported once per transition.
+        event_loop.check_load().await?;
+
+        event_loop
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .store_mut()
+            .remove(&key(1));
+        event_loop.check_load().await?;
+        assert!(!event_loop.shedding_load);
+        assert!(matches!(
+            events.next().await,
+            Some(NetworkEvent::LoadSheddingDeactivated)
+        ));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn the_requests_to_a_node_shedding_load_are_answered_overloaded() -> Result<()> {
+        let (mut shedding, mut shedding_events, shedding_loop) =
+            NetworkSwarmLoop::with_config(NetworkConfig {
+                // Reached from the first check on.
+                load_shedding: Some(LoadSheddingConfig {
+                    max_in_flight: 0,
+                    ..Default::default()
+                }),
+                ..isolated_config()
+            })?;
+        let _shedding_loop = task::spawn(shedding_loop.run());
+        let shedding_addr = listen_addr(&mut shedding).await?;
+        let activated = timeout(TEST_TIMEOUT, async {
+            while let Some(event) = shedding_events.next().await {
+                if matches!(event, NetworkEvent::LoadSheddingActivated { .. }) {
+                    return true;
+                }
+            }
+            false
+        })
+        .await
+        .expect("the node to start shedding load");
+        assert!(activated);
+        let _shedding_events = task::spawn(shedding_events.for_each(|_| async {}));
+
+        // Its own outbound work is refused, its connectivity kept up.
+        assert!(matches!(
+            shedding.put_record(Default::default(), vec![1]).await,
+            Err(Error::Overloaded)
+        ));
+
+        let (mut network, events, event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let _event_loop = task::spawn(event_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+        timeout(
+            TEST_TIMEOUT,
+            network.dial(shedding.local_peer_id(), shedding_addr),
+        )
+        .await
+        .expect("the dial to complete")?;
+
+        let response = timeout(
+            TEST_TIMEOUT,
+            network.send_request(Request::GetDBC, shedding.local_peer_id()),
+        )
+        .await
+        .expect("the request to complete")?;
+        assert_eq!(response, Response::Overloaded);
+        let raw = timeout(
+            TEST_TIMEOUT,
+            network.send_raw(vec![1], shedding.local_peer_id()),
+        )
+        .await
+        .expect("the raw request to complete");
+        assert!(matches!(raw, Err(Error::Overloaded)));
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use xor_name::XorName;
+
+/// The current version of the `Manifest` encoding.
+const MANIFEST_VERSION: u8 = 1;
+
+/// Links a multi-chunk piece of content to the chunks it has been split into.
+///
+/// The manifest is stored on the DHT as a Kademlia record keyed by the manifest's `XorName`.
+/// The record value is the MessagePack (`rmp_serde`) encoding of this struct, i.e. the array
+/// `[version, [chunk_name, ..]]`, where each chunk name is the 32 byte `XorName` of the chunk
+/// and the chunks are listed in the order they have to be joined back together.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    version: u8,
+    chunk_names: Vec<XorName>,
+}
+
+impl Manifest {
+    /// Creates a new `Manifest` listing the provided chunks.
+    pub fn new(chunk_names: Vec<XorName>) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            chunk_names,
+        }
+    }
+
+    /// Returns the names of the chunks, in order.
+    pub fn chunk_names(&self) -> &[XorName] {
+        &self.chunk_names
+    }
+
+    /// Consumes the manifest, returning the names of the chunks.
+    pub fn into_chunk_names(self) -> Vec<XorName> {
+        self.chunk_names
+    }
+
+    pub(super) fn encode(&self) -> Result<Vec<u8>> {
+        Ok(rmp_serde::to_vec(self)?)
+    }
+
+    pub(super) fn decode(bytes: &[u8]) -> Result<Self> {
+        let manifest: Self = rmp_serde::from_slice(bytes)?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(Error::UnsupportedManifestVersion(manifest.version));
+        }
+        Ok(manifest)
+    }
+}
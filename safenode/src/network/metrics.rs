@@ -0,0 +1,162 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// The upper bounds, in milliseconds, of the buckets of the latency histograms.
+const LATENCY_BOUNDS_MS: [u64; 10] = [10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+/// The counters of the network activity, updated by the `NetworkSwarmLoop` and read through
+/// the `Network` handles. Any export format is to be derived from these counters.
+#[derive(Debug, Default)]
+pub(super) struct Metrics {
+    pub(super) connections_established: AtomicU64,
+    pub(super) connections_closed: AtomicU64,
+    pub(super) requests_sent: AtomicU64,
+    pub(super) requests_received: AtomicU64,
+    pub(super) responses_received: AtomicU64,
+    pub(super) request_failures: AtomicU64,
+    pub(super) provider_records_published: AtomicU64,
+    pub(super) records_put: AtomicU64,
+    pub(super) peers_reaped: AtomicU64,
+    pub(super) egress_bytes: AtomicU64,
+    pub(super) egress_throttled: AtomicU64,
+    pub(super) inbound_streams_rejected: AtomicU64,
+    pub(super) lagging_subscribers_dropped: AtomicU64,
+    pub(super) time_to_first_provider: Histogram,
+}
+
+/// A histogram of latencies over the `LATENCY_BOUNDS_MS` buckets, plus one for the latencies
+/// beyond the last bound.
+#[derive(Debug, Default)]
+pub(super) struct Histogram {
+    counts: [AtomicU64; LATENCY_BOUNDS_MS.len() + 1],
+    sum_ms: AtomicU64,
+}
+
+impl Histogram {
+    /// Records one observation of `latency`.
+    pub(super) fn observe(&self, latency: Duration) {
+        let ms = u64::try_from(latency.as_millis()).unwrap_or(u64::MAX);
+        let bucket = LATENCY_BOUNDS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(LATENCY_BOUNDS_MS.len());
+        Metrics::inc(&self.counts[bucket]);
+        let _ = self.sum_ms.fetch_add(ms, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let counts: Vec<u64> = self
+            .counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect();
+        HistogramSnapshot {
+            bounds_ms: LATENCY_BOUNDS_MS.to_vec(),
+            count: counts.iter().sum(),
+            counts,
+            sum_ms: self.sum_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Metrics {
+    /// Increments the given counter by one.
+    pub(super) fn inc(counter: &AtomicU64) {
+        let _ = counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn snapshot(&self) -> MetricsSnapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let connections_established = load(&self.connections_established);
+        let connections_closed = load(&self.connections_closed);
+        MetricsSnapshot {
+            connections_established,
+            connections_closed,
+            open_connections: connections_established.saturating_sub(connections_closed),
+            requests_sent: load(&self.requests_sent),
+            requests_received: load(&self.requests_received),
+            responses_received: load(&self.responses_received),
+            request_failures: load(&self.request_failures),
+            provider_records_published: load(&self.provider_records_published),
+            records_put: load(&self.records_put),
+            peers_reaped: load(&self.peers_reaped),
+            egress_bytes: load(&self.egress_bytes),
+            egress_throttled: load(&self.egress_throttled),
+            inbound_streams_rejected: load(&self.inbound_streams_rejected),
+            lagging_subscribers_dropped: load(&self.lagging_subscribers_dropped),
+            time_to_first_provider: self.time_to_first_provider.snapshot(),
+        }
+    }
+}
+
+/// A point in time copy of the network counters, see `Network::metrics_snapshot`.
+///
+/// The counters are cumulative since the node started, both the typed and the raw protocols
+/// being accounted for in the request counters.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// The number of connections established, inbound and outbound.
+    pub connections_established: u64,
+    /// The number of connections closed.
+    pub connections_closed: u64,
+    /// The number of connections currently open.
+    pub open_connections: u64,
+    /// The number of requests sent to peers.
+    pub requests_sent: u64,
+    /// The number of requests received from peers.
+    pub requests_received: u64,
+    /// The number of responses received to our requests.
+    pub responses_received: u64,
+    /// The number of requests, inbound or outbound, that failed.
+    pub request_failures: u64,
+    /// The number of provider records successfully published.
+    pub provider_records_published: u64,
+    /// The number of records successfully put on the DHT.
+    pub records_put: u64,
+    /// The number of peers disconnected for being idle.
+    pub peers_reaped: u64,
+    /// The number of bytes of requests and responses sent to peers.
+    pub egress_bytes: u64,
+    /// The number of messages delayed by the `NetworkConfig::egress_rate_limit`.
+    pub egress_throttled: u64,
+    /// The number of inbound requests dropped for exceeding the
+    /// `NetworkConfig::max_inbound_streams_per_peer`.
+    pub inbound_streams_rejected: u64,
+    /// The number of `Network::subscribe_events` streams disconnected for falling behind.
+    pub lagging_subscribers_dropped: u64,
+    /// The time `Network::get_data_providers` took to find the first provider, from the start
+    /// of the lookup. The lookups finding no provider are not accounted for.
+    pub time_to_first_provider: HistogramSnapshot,
+}
+
+/// A point in time copy of a latency histogram.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistogramSnapshot {
+    /// The upper bounds, in milliseconds, of the buckets.
+    pub bounds_ms: Vec<u64>,
+    /// The number of observations in each bucket, i.e. no larger than its bound and larger than
+    /// the previous one. The last count is of the observations beyond the last bound.
+    pub counts: Vec<u64>,
+    /// The total number of observations.
+    pub count: u64,
+    /// The sum of the observations, in milliseconds.
+    pub sum_ms: u64,
+}
+
+impl MetricsSnapshot {
+    /// Returns the snapshot as a JSON object.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+}
@@ -6,42 +6,138 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+mod address_book;
+mod bootstrap;
+mod bucket_refresh;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod coalescing;
 mod command;
+mod command_channel;
+mod config;
+mod data_class;
+mod dial_queue;
+mod egress;
 mod error;
 mod event;
+mod gossip;
+mod identity_bundle;
+mod inbound;
+mod keypair_file;
+mod load_shedding;
+mod manifest;
+mod metrics;
 mod msg;
+mod op_trace;
+mod peer_latency;
+mod protocols;
+mod provider_log;
+mod queries;
+mod reaper;
+mod record;
+mod record_policy;
+mod request_pool;
+mod routing_table;
+mod shutdown;
+mod staggered_dial;
+mod store_retry;
+mod subscription;
+/// Helpers for tests that spin up several nodes
+pub mod testing;
+mod ticks;
+mod timeouts;
+mod transport;
 
+#[cfg(feature = "chaos")]
+pub use self::chaos::ChaosConfig;
 pub use self::{
-    event::NetworkEvent,
-    msg::{Request, Response},
+    address_book::{AddressBook, AddressEntry, AddressSource, MemoryAddressBook},
+    config::{
+        BucketRefreshConfig, DataClass, DataClassSchedule, DataClassSchedules, LatencyBuckets,
+        LoadSheddingConfig, NetworkConfig, ProtocolLimits, ProtocolVersion,
+        ProviderSelectionStrategy, RecordConflictPolicy, RequestPoolConfig, StoreRetryConfig,
+        TransportKind, DEFAULT_BOOTSTRAP_TIMEOUT, DEFAULT_COMMAND_CHANNEL_CAPACITY,
+        DEFAULT_DIAL_STAGGER, DEFAULT_EVENT_CHANNEL_CAPACITY, HEALTH_CHECK_TIMEOUT,
+        MAX_NODE_METADATA_LEN,
+    },
+    error::Error,
+    event::{NetworkEvent, RawSwarmEvent, ResponseHandle},
+    identity_bundle::IdentityBundle,
+    manifest::Manifest,
+    metrics::{HistogramSnapshot, MetricsSnapshot},
+    msg::{MsgFormat, RawResponse, Request, Response, TransferProgress},
+    queries::{QueryInfo, QueryKind},
+    record::Record,
+    shutdown::ShutdownSummary,
+    subscription::EventFilter,
 };
+// `Distance` is only exported through the deprecated `kbucket` module in this libp2p release.
+#[allow(deprecated)]
+pub use libp2p::kad::kbucket::Distance;
+pub use libp2p::kad::Quorum;
 
 use self::{
+    bootstrap::BootstrapDials,
+    coalescing::{CoalescedRequest, CoalescedWaiters},
     command::SwarmCmd,
+    command_channel::{command_channel, CommandReceiver, CommandSender},
+    data_class::{ProvidedData, REPUBLISH_TICK},
+    dial_queue::{DialLimit, QueuedDial},
+    egress::Egress,
     error::Result,
     event::NodeBehaviour,
-    msg::{MsgCodec, MsgProtocol},
+    inbound::InboundStream,
+    load_shedding::LOAD_CHECK_TICK,
+    metrics::Metrics,
+    msg::{
+        encoded_len, MsgCodec, MsgProtocol, RawCodec, RawProtocol, TransferTracker,
+        MSG_PROTOCOL_NAME, RAW_PROTOCOL_NAME,
+    },
+    op_trace::{OpSpan, OpTraces},
+    protocols::FilteredIdentify,
+    provider_log::ProviderLog,
+    routing_table::ROUTING_CHECK_TICK,
+    shutdown::Shutdown,
+    staggered_dial::StaggeredDial,
+    store_retry::{PersistentStore, STORE_RETRY_TICK},
+    subscription::Subscriber,
+    testing::MessageRecorder,
+    ticks::Tick,
+    timeouts::RequestDeadline,
 };
+use crate::storage::DataStorage;
 use futures::{
     channel::{mpsc, oneshot},
     prelude::*,
+    stream::FuturesUnordered,
 };
 use libp2p::{
-    core::muxing::StreamMuxerBox,
-    identity,
-    kad::{record::store::MemoryStore, Kademlia, KademliaConfig, QueryId},
+    autonat,
+    core::{transport::ListenerId, ConnectedPoint},
+    identify, identity,
+    kad::{
+        record::store::MemoryStore, KBucketKey, Kademlia, KademliaConfig, KademliaStoreInserts,
+        QueryId, RecordKey,
+    },
     mdns,
-    request_response::{self, ProtocolSupport, RequestId, ResponseChannel},
+    request_response::{self, ProtocolSupport, RequestId},
     swarm::{Swarm, SwarmBuilder},
-    Multiaddr, PeerId, Transport,
+    Multiaddr, PeerId,
 };
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     iter,
-    time::Duration,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use tracing::warn;
-use xor_name::XorName;
+use tracing::{info_span, warn};
+use xor_name::{Prefix, XorName};
+
+// See `NetworkSwarmLoop::with_raw_event_tap`.
+type RawEventTap = Box<dyn FnMut(RawSwarmEvent<'_>) + Send>;
+
+/// Peers paired with their distance to a key, closest first.
+type ClosestPeers = Vec<(PeerId, Distance)>;
 
 /// The main event loop recieves `SwarmEvents` from the network, `SwarmCmd` from the upper layers and
 /// emmits back `NetworkEvent` to the upper layers.
@@ -49,12 +145,103 @@ use xor_name::XorName;
 /// that is the outcome of a previously executed cmd, send a response to them via the stored channel.
 pub struct NetworkSwarmLoop {
     swarm: Swarm<NodeBehaviour>,
-    cmd_receiver: mpsc::Receiver<SwarmCmd>,
+    keypair: identity::Keypair,
+    cmd_receiver: CommandReceiver,
     event_sender: mpsc::Sender<NetworkEvent>,
+    subscribers: Vec<Subscriber>,
     pending_dial: HashMap<PeerId, oneshot::Sender<Result<()>>>,
     pending_start_providing: HashMap<QueryId, oneshot::Sender<Result<()>>>,
-    pending_get_providers: HashMap<QueryId, oneshot::Sender<HashSet<PeerId>>>,
+    pending_get_providers: HashMap<QueryId, oneshot::Sender<Result<HashSet<PeerId>>>>,
     pending_requests: HashMap<RequestId, oneshot::Sender<Result<Response>>>,
+    /// The transfer progress of the `pending_requests`.
+    request_transfers: HashMap<RequestId, TransferTracker>,
+    pending_raw_requests: HashMap<RequestId, oneshot::Sender<Result<Vec<u8>>>>,
+    /// The bootstrap queries, along with the caller waiting for them, `None` for the startup
+    /// bootstrap.
+    pending_bootstrap: HashMap<QueryId, Option<oneshot::Sender<Result<()>>>>,
+    bootstrap_dials: Option<BootstrapDials>,
+    bootstrap_dial_concurrency: usize,
+    /// The `NetworkConfig::bootstrap_peers`, taken once the loop runs.
+    startup_bootstrap_peers: Vec<(PeerId, Multiaddr)>,
+    dial_stagger: Option<Duration>,
+    staggered_dials: HashMap<PeerId, StaggeredDial>,
+    queued_dials: VecDeque<QueuedDial>,
+    request_coalescing_window: Option<Duration>,
+    request_timeout: Option<Duration>,
+    request_retries: u32,
+    /// The deadlines of the `pending_requests`, when timed out.
+    request_deadlines: HashMap<RequestId, RequestDeadline>,
+    dial_timeout: Option<Duration>,
+    /// The deadlines of the `pending_dial`s, when timed out.
+    dial_deadlines: HashMap<PeerId, Instant>,
+    /// The latest coalescable request sent for each (peer, key).
+    coalesced_requests: HashMap<(PeerId, XorName), CoalescedRequest>,
+    /// The requests joined by others, with the senders of the latter.
+    coalesced_waiters: HashMap<RequestId, CoalescedWaiters>,
+    op_traces: OpTraces,
+    /// Whether a bootstrap completed successfully at least once.
+    bootstrapped: bool,
+    min_ready_peers: usize,
+    min_peer_version: Option<ProtocolVersion>,
+    metrics: Arc<Metrics>,
+    shutdown: Option<Shutdown>,
+    raw_event_tap: Option<RawEventTap>,
+    message_recorder: Option<MessageRecorder>,
+    address_book: Box<dyn AddressBook>,
+    pending_put_record: HashMap<QueryId, oneshot::Sender<Result<()>>>,
+    pending_get_record: HashMap<QueryId, oneshot::Sender<Result<Option<Record>>>>,
+    pending_get_closest_peers: HashMap<QueryId, oneshot::Sender<Result<ClosestPeers>>>,
+    persistent_stores: HashMap<QueryId, PersistentStore>,
+    /// The persistent stores waiting for their backoff to elapse, with the time to retry at.
+    store_retry_queue: Vec<(Instant, PersistentStore)>,
+    store_retry: StoreRetryConfig,
+    data_classes: DataClassSchedules,
+    /// The keys provided by the local node, as advertised as per their `DataClass`.
+    provided_data: HashMap<RecordKey, ProvidedData>,
+    /// The queries advertising the `provided_data` again, whose outcome is only logged.
+    republish_queries: HashSet<QueryId>,
+    load_shedding: Option<LoadSheddingConfig>,
+    /// Whether the `load_shedding` thresholds have been reached.
+    shedding_load: bool,
+    record_conflict_policy: RecordConflictPolicy,
+    /// Whether the routing table is empty; `None` until it gets its first entry, so that a
+    /// starting node isn't reported as isolated.
+    routing_table_empty: Option<bool>,
+    provider_log: Option<ProviderLog>,
+    /// The keys read back from the `provider_log`, to advertise again once bootstrapped.
+    resumed_provided_keys: Vec<XorName>,
+    /// The data checked before being advertised, see `NetworkConfig::held_data`.
+    held_data: Option<DataStorage>,
+    bucket_refresh: Option<BucketRefreshConfig>,
+    bucket_refresh_round: u64,
+    /// The addresses we have been asked to listen on, by listener.
+    listeners: HashMap<ListenerId, Multiaddr>,
+    /// The addresses the listeners actually bound to, as reported by the swarm.
+    listen_addrs: Vec<Multiaddr>,
+    /// The addresses to listen on once resumed; `Some` while networking is paused.
+    paused_listen_addrs: Option<Vec<Multiaddr>>,
+    /// Peers that have been explicitly dialed or bootstrapped from, i.e. not only discovered.
+    explicit_peers: HashSet<PeerId>,
+    disconnect_expired_mdns_peers: bool,
+    /// The endpoints of the connections currently open, by peer.
+    peer_endpoints: HashMap<PeerId, Vec<ConnectedPoint>>,
+    /// The last time we exchanged messages with each of the connected peers.
+    peer_last_activity: HashMap<PeerId, Instant>,
+    /// When we last answered a `Request::Ping`, by peer.
+    last_ping: HashMap<PeerId, Instant>,
+    /// The inbound requests waiting for a response, by peer.
+    inbound_streams: HashMap<PeerId, HashSet<InboundStream>>,
+    max_inbound_streams_per_peer: Option<usize>,
+    /// The peers at the `max_inbound_streams_per_peer` limit, already reported as such.
+    capped_peers: HashSet<PeerId>,
+    idle_connection_timeout: Option<Duration>,
+    /// The request protocols disabled through `Network::disable_protocol`.
+    disabled_protocols: HashSet<&'static str>,
+    provider_selection: ProviderSelectionStrategy,
+    /// The peer and send time of the requests timed for the `peer_latencies`.
+    request_timings: HashMap<RequestId, (PeerId, Instant)>,
+    /// The smoothed request round trip of each of the connected peers.
+    peer_latencies: HashMap<PeerId, Duration>,
 }
 
 impl NetworkSwarmLoop {
@@ -66,94 +253,447 @@ impl NetworkSwarmLoop {
     ///
     /// - The `NetworkSwarmLoop` that drives the network.
     pub fn new() -> Result<(Network, impl Stream<Item = NetworkEvent>, NetworkSwarmLoop)> {
-        // Create a random key for ourselves.
-        let keypair = identity::Keypair::generate_ed25519();
+        Self::with_config(NetworkConfig::default())
+    }
+
+    /// Creates the network components using the provided `NetworkConfig`.
+    /// See [`NetworkSwarmLoop::new`] for the components returned.
+    pub fn with_config(
+        config: NetworkConfig,
+    ) -> Result<(Network, impl Stream<Item = NetworkEvent>, NetworkSwarmLoop)> {
+        let keypair = match &config.keypair_path {
+            Some(path) => keypair_file::load_or_generate(path)?,
+            // Create a random key for ourselves.
+            None => identity::Keypair::generate_ed25519(),
+        };
+        Self::build(config, keypair)
+    }
+
+    /// Creates the network components using the provided keypair, hence `PeerId`, instead of
+    /// the one from `NetworkConfig::keypair_path` or a random one.
+    /// See [`NetworkSwarmLoop::new`] for the components returned.
+    pub fn with_keypair(
+        config: NetworkConfig,
+        keypair: identity::Keypair,
+    ) -> Result<(Network, impl Stream<Item = NetworkEvent>, NetworkSwarmLoop)> {
+        Self::build(config, keypair)
+    }
+
+    /// Creates the network components resuming the identity and connectivity exported by
+    /// `Network::export_identity_bundle`, e.g. on another host: the node keeps its keypair,
+    /// hence its `PeerId`, starts from the exported routing table, and advertises the exported
+    /// keys again once bootstrapped.
+    /// See [`NetworkSwarmLoop::new`] for the components returned.
+    pub fn from_identity_bundle(
+        config: NetworkConfig,
+        bundle: &IdentityBundle,
+    ) -> Result<(Network, impl Stream<Item = NetworkEvent>, NetworkSwarmLoop)> {
+        let (network, events, mut event_loop) = Self::build(config, bundle.keypair()?)?;
+        event_loop.restore_identity_bundle(bundle)?;
+        Ok((network, events, event_loop))
+    }
+
+    fn build(
+        config: NetworkConfig,
+        keypair: identity::Keypair,
+    ) -> Result<(Network, mpsc::Receiver<NetworkEvent>, NetworkSwarmLoop)> {
+        if config.node_metadata.len() > MAX_NODE_METADATA_LEN {
+            return Err(Error::NodeMetadataTooLong {
+                len: config.node_metadata.len(),
+                max: MAX_NODE_METADATA_LEN,
+            });
+        }
+
+        // A zero capacity channel only holds a slot per sender, the event loop then waiting on
+        // every single event.
+        if config.command_channel_capacity == 0 || config.event_channel_capacity == 0 {
+            return Err(Error::InvalidConfig(
+                "the command and event channel capacities must not be zero".to_string(),
+            ));
+        }
+
         let local_peer_id = PeerId::from(keypair.public());
 
-        // QUIC configuration
-        let quic_config = libp2p_quic::Config::new(&keypair);
-        let transport = libp2p_quic::async_std::Transport::new(quic_config);
-        let transport = transport
-            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
-            .boxed();
+        let transport = transport::build(&keypair, &config)?;
         // Create a Kademlia instance and connect to the network address.
         // Create a swarm to manage peers and events.
+        let metrics = Arc::new(Metrics::default());
+        let egress = Egress::new(metrics.clone(), config.egress_rate_limit);
+        let mut listeners = HashMap::new();
         let swarm = {
             // Create a Kademlia behaviour.
             let mut cfg = KademliaConfig::default();
             let _ = cfg.set_query_timeout(Duration::from_secs(5 * 60));
+            // The provided data is advertised again as per its `DataClass` instead.
+            let _ = cfg.set_provider_publication_interval(None);
+            // The inbound records go through the `RecordConflictPolicy` before being stored.
+            let _ = cfg.set_record_filtering(KademliaStoreInserts::FilterBoth);
+            let _ = cfg.disjoint_query_paths(config.disjoint_query_paths);
             let kademlia =
                 Kademlia::with_config(local_peer_id, MemoryStore::new(local_peer_id), cfg);
-            let mdns = mdns::async_io::Behaviour::new(mdns::Config::default(), local_peer_id)?;
+            let mdns = if config.mdns {
+                Some(mdns::async_io::Behaviour::new(
+                    mdns::Config::default(),
+                    local_peer_id,
+                )?)
+            } else {
+                None
+            };
             let behaviour = NodeBehaviour {
                 request_response: request_response::Behaviour::new(
-                    MsgCodec(),
+                    MsgCodec::new(config.msg_limits, config.msg_format, egress.clone()),
                     iter::once((MsgProtocol(), ProtocolSupport::Full)),
                     Default::default(),
                 ),
+                raw_request_response: request_response::Behaviour::new(
+                    RawCodec::new(config.raw_limits, egress),
+                    iter::once((RawProtocol(), ProtocolSupport::Full)),
+                    Default::default(),
+                ),
                 kademlia,
-                mdns,
+                mdns: mdns.into(),
+                autonat: autonat::Behaviour::new(local_peer_id, Default::default()),
+                identify: FilteredIdentify::new(identify::Behaviour::new(
+                    identify::Config::new(
+                        config::IDENTIFY_PROTOCOL_VERSION.to_string(),
+                        keypair.public(),
+                    )
+                    .with_agent_version(config::agent_version(&config.node_metadata)),
+                )),
+                gossipsub: gossip::behaviour(&keypair)?,
+                staggered_dials: Default::default(),
+                dial_limit: DialLimit::new(config.max_pending_dials),
             };
 
             let mut swarm =
                 SwarmBuilder::with_async_std_executor(transport, behaviour, local_peer_id).build();
 
-            // Listen on all interfaces and whatever port the OS assigns.
-            let addr = "/ip4/0.0.0.0/udp/0/quic-v1"
-                .parse()
-                .expect("Failed to parse the address");
-            let _listener_id = swarm
-                .listen_on(addr)
-                .expect("Failed to listen on the provided address");
+            let addrs = match &config.listen_addr {
+                Some(addr) => vec![addr.clone()],
+                None => config::default_listen_addrs(config.transport),
+            };
+            for addr in addrs {
+                let listener_id = swarm.listen_on(addr.clone())?;
+                let _ = listeners.insert(listener_id, addr);
+            }
 
             swarm
         };
 
-        let (swarm_cmd_sender, swarm_cmd_receiver) = mpsc::channel(0);
-        let (event_sender, event_receiver) = mpsc::channel(0);
+        let (provider_log, resumed_provided_keys) = match &config.provider_log {
+            Some(path) => {
+                let (log, keys) = ProviderLog::open(path)?;
+                (Some(log), keys)
+            }
+            None => (None, Vec::new()),
+        };
+
+        let (swarm_cmd_sender, swarm_cmd_receiver) =
+            command_channel(config.command_channel_capacity);
+        let (event_sender, event_receiver) = mpsc::channel(config.event_channel_capacity);
         let event_loop = Self {
             swarm,
+            keypair,
             cmd_receiver: swarm_cmd_receiver,
             event_sender,
+            subscribers: Vec::new(),
             pending_dial: Default::default(),
             pending_start_providing: Default::default(),
             pending_get_providers: Default::default(),
             pending_requests: Default::default(),
+            request_transfers: Default::default(),
+            pending_raw_requests: Default::default(),
+            pending_bootstrap: Default::default(),
+            bootstrap_dials: None,
+            bootstrap_dial_concurrency: config.bootstrap_dial_concurrency,
+            startup_bootstrap_peers: config.bootstrap_peers.clone(),
+            dial_stagger: config.dial_stagger,
+            staggered_dials: Default::default(),
+            queued_dials: VecDeque::new(),
+            request_coalescing_window: config.request_coalescing_window,
+            request_timeout: config.request_timeout,
+            request_retries: config.request_retries,
+            request_deadlines: Default::default(),
+            dial_timeout: config.dial_timeout,
+            dial_deadlines: Default::default(),
+            coalesced_requests: Default::default(),
+            coalesced_waiters: Default::default(),
+            op_traces: Default::default(),
+            bootstrapped: false,
+            min_ready_peers: config.min_ready_peers,
+            min_peer_version: config.min_peer_version,
+            metrics: metrics.clone(),
+            shutdown: None,
+            raw_event_tap: None,
+            message_recorder: None,
+            address_book: Box::<MemoryAddressBook>::default(),
+            pending_put_record: Default::default(),
+            pending_get_record: Default::default(),
+            pending_get_closest_peers: Default::default(),
+            persistent_stores: Default::default(),
+            store_retry_queue: Vec::new(),
+            store_retry: config.store_retry,
+            data_classes: config.data_classes,
+            provided_data: Default::default(),
+            republish_queries: Default::default(),
+            load_shedding: config.load_shedding,
+            shedding_load: false,
+            record_conflict_policy: config.record_conflict_policy,
+            routing_table_empty: None,
+            provider_log,
+            resumed_provided_keys,
+            held_data: config.held_data.clone(),
+            bucket_refresh: config.bucket_refresh,
+            bucket_refresh_round: 0,
+            listeners,
+            listen_addrs: Vec::new(),
+            paused_listen_addrs: None,
+            explicit_peers: Default::default(),
+            disconnect_expired_mdns_peers: config.disconnect_expired_mdns_peers,
+            peer_endpoints: Default::default(),
+            peer_last_activity: Default::default(),
+            last_ping: Default::default(),
+            inbound_streams: Default::default(),
+            max_inbound_streams_per_peer: config.max_inbound_streams_per_peer,
+            capped_peers: Default::default(),
+            idle_connection_timeout: config.idle_connection_timeout,
+            disabled_protocols: HashSet::new(),
+            provider_selection: config.provider_selection,
+            request_timings: Default::default(),
+            peer_latencies: Default::default(),
         };
 
-        Ok((Network { swarm_cmd_sender }, event_receiver, event_loop))
+        let network = Network {
+            swarm_cmd_sender,
+            local_peer_id,
+            bootstrap_timeout: config.bootstrap_timeout,
+            msg_limits: config.msg_limits,
+            msg_format: config.msg_format,
+            raw_limits: config.raw_limits,
+            event_channel_capacity: config.event_channel_capacity,
+            metrics,
+        };
+
+        Ok((network, event_receiver, event_loop))
+    }
+
+    /// Hands every swarm event to `tap` before it is handled, as an escape hatch to build on the
+    /// behaviour not exposed through the `NetworkEvent`s.
+    ///
+    /// The events can't be cloned, hence `tap` only gets to borrow them; it is up to it to
+    /// forward what it needs, e.g. to a channel. Being called from within the event loop, `tap`
+    /// must not block. This is a low-level API exposing libp2p types, which are not stable.
+    pub fn with_raw_event_tap(
+        mut self,
+        tap: impl FnMut(RawSwarmEvent<'_>) + Send + 'static,
+    ) -> Self {
+        self.raw_event_tap = Some(Box::new(tap));
+        self
     }
 
-    /// Drive the network
-    pub async fn run(mut self) {
+    /// Records the `Request`s and `Response`s exchanged with the peers into `recorder`, for the
+    /// tests to assert on what went over the wire.
+    pub fn with_message_recorder(mut self, recorder: MessageRecorder) -> Self {
+        self.message_recorder = Some(recorder);
+        self
+    }
+
+    /// Drive the network, until all the `Network` handles are dropped or the network is shut
+    /// down. Fails with `Error::SwarmStreamEnded` should the swarm stop producing events.
+    pub async fn run(mut self) -> Result<()> {
+        let mut bucket_refresh_tick = match &self.bucket_refresh {
+            Some(config) => interval(config.interval).boxed(),
+            None => stream::pending().boxed(),
+        }
+        .fuse();
+        // Check for idle peers a few times per timeout, so they are reaped close to the deadline.
+        let mut reaper_tick = match self.idle_connection_timeout {
+            Some(timeout) => interval((timeout / 4).max(Duration::from_secs(1))).boxed(),
+            None => stream::pending().boxed(),
+        }
+        .fuse();
+        let mut dial_stagger_tick = match self.dial_stagger {
+            Some(stagger) if !stagger.is_zero() => interval(stagger).boxed(),
+            _ => stream::pending().boxed(),
+        }
+        .fuse();
+        let mut timeout_tick = match self.timeout_tick() {
+            Some(tick) => interval(tick).boxed(),
+            None => stream::pending().boxed(),
+        }
+        .fuse();
+        let mut store_retry_tick = interval(STORE_RETRY_TICK).boxed().fuse();
+        let mut republish_tick = interval(REPUBLISH_TICK).boxed().fuse();
+        let mut routing_check_tick = interval(ROUTING_CHECK_TICK).boxed().fuse();
+        let mut load_check_tick = match self.load_shedding {
+            Some(_) => interval(LOAD_CHECK_TICK).boxed(),
+            None => stream::pending().boxed(),
+        }
+        .fuse();
+        // Armed once a graceful shutdown starts, to stop draining at its deadline.
+        let mut drain_deadline = future::pending().boxed().fuse();
+        let mut drain_deadline_armed = false;
+
+        if let Err(err) = self.start_startup_bootstrap().await {
+            warn!("Error while bootstrapping from the configured peers: {err}");
+        }
+
         loop {
             futures::select! {
                 event = self.swarm.next() => {
-                    if let Err(err) = self.handle_event(event.expect("Swarm stream to be infinite!")).await {
+                    let event = match event {
+                        Some(event) => event,
+                        None => return Err(Error::SwarmStreamEnded),
+                    };
+                    if let Some(tap) = &mut self.raw_event_tap {
+                        tap(RawSwarmEvent(&event));
+                    }
+                    if let Err(err) = self.handle_event(event).await {
                         warn!("Error while handling event: {err}");
                     }
                 }  ,
-                command = self.cmd_receiver.next() => match command {
+                command = self.cmd_receiver.receiver.next() => match command {
                     Some(cmd) => {
-                        if let Err(err) = self.handle_command(cmd) {
+                        self.cmd_receiver.received();
+                        if let Err(err) = self.handle_command(cmd).await {
                             warn!("Error while handling cmd: {err}");
                         }
                     },
                     // Command channel closed, thus shutting down the network event loop.
-                    None=>  return,
+                    None => return Ok(()),
                 },
+                _ = bucket_refresh_tick.next() => self.run_tick(Tick::BucketRefresh).await,
+                _ = reaper_tick.next() => self.run_tick(Tick::Reaper).await,
+                _ = dial_stagger_tick.next() => self.run_tick(Tick::DialStagger).await,
+                _ = timeout_tick.next() => self.run_tick(Tick::Timeout).await,
+                _ = republish_tick.next() => self.run_tick(Tick::Republish).await,
+                _ = store_retry_tick.next() => self.run_tick(Tick::StoreRetry).await,
+                _ = load_check_tick.next() => self.run_tick(Tick::LoadCheck).await,
+                _ = routing_check_tick.next() => self.run_tick(Tick::RoutingCheck).await,
+                () = drain_deadline => {},
+            }
+
+            if let Err(err) = self.dequeue_dials().await {
+                warn!("Error while dialing the queued peers: {err}");
+            }
+
+            if !drain_deadline_armed {
+                if let Some(deadline) = self.shutdown_deadline() {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    drain_deadline = async_std::task::sleep(remaining).boxed().fuse();
+                    drain_deadline_armed = true;
+                }
+            }
+            if self.try_complete_shutdown() {
+                return Ok(());
             }
         }
     }
 }
 
+// Returns the size of the largest group of equal responses.
+fn most_matching(responses: &[Response]) -> usize {
+    responses
+        .iter()
+        .map(|response| responses.iter().filter(|r| *r == response).count())
+        .max()
+        .unwrap_or(0)
+}
+
+// Refuses the outbound messages of the given protocol that exceed its size limit.
+fn check_size(protocol: &'static str, size: usize, max: usize) -> Result<()> {
+    if size > max {
+        return Err(Error::MessageTooLarge {
+            protocol,
+            size,
+            max,
+        });
+    }
+    Ok(())
+}
+
+// The error of a response sent through a `ResponseHandle` already answered by one of its copies.
+fn already_responded() -> Error {
+    Error::Other("A response was already sent for this request.".to_string())
+}
+
+// A stream that yields every `period`, starting `period` from now.
+fn interval(period: Duration) -> impl Stream<Item = ()> {
+    stream::unfold((), move |()| async move {
+        async_std::task::sleep(period).await;
+        Some(((), ()))
+    })
+}
+
 #[derive(Clone)]
 /// API to interact with the underlying Swarm
 pub struct Network {
-    pub(super) swarm_cmd_sender: mpsc::Sender<SwarmCmd>,
+    pub(super) swarm_cmd_sender: CommandSender,
+    local_peer_id: PeerId,
+    bootstrap_timeout: Duration,
+    msg_limits: ProtocolLimits,
+    msg_format: MsgFormat,
+    raw_limits: ProtocolLimits,
+    event_channel_capacity: usize,
+    metrics: Arc<Metrics>,
 }
 
 impl Network {
+    /// Returns the `PeerId` of the local node, which is stable across restarts when the node is
+    /// given the same keypair, see `NetworkConfig::keypair_path`.
+    pub fn local_peer_id(&self) -> PeerId {
+        self.local_peer_id
+    }
+
+    /// Stops accepting new work and waits, up to `drain_timeout`, for the in-flight requests and
+    /// queries to complete, before closing all the connections and stopping the
+    /// `NetworkSwarmLoop`. The work still in flight at the deadline fails with
+    /// `Error::Shutdown`, as does any new outbound command sent while draining; responses
+    /// to the requests already received can still be sent.
+    pub async fn shutdown_graceful(&mut self, drain_timeout: Duration) -> Result<ShutdownSummary> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::Shutdown {
+                drain_timeout,
+                sender,
+            })
+            .await?;
+        receiver.await?
+    }
+
+    /// Stops the `NetworkSwarmLoop` right away: the in-flight requests, dials and queries fail
+    /// with `Error::Shutdown`, the connections are closed, and this returns once the loop
+    /// stopped, e.g. for a deterministic teardown. See `Network::shutdown_graceful` to let the
+    /// in-flight work complete first.
+    pub async fn shutdown(&mut self) -> Result<ShutdownSummary> {
+        self.shutdown_graceful(Duration::ZERO).await
+    }
+
+    /// Returns how full the channel carrying the commands to the `NetworkSwarmLoop` is, from 0.0
+    /// for empty to 1.0 for full, all the `Network` handles sharing the channel. Once full, the
+    /// calls wait for the loop to catch up, hence producers can use this to slow down first.
+    pub fn command_channel_pressure(&self) -> f32 {
+        self.swarm_cmd_sender.pressure()
+    }
+
+    /// Returns a copy of the current network counters, e.g. to be exported as JSON.
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Returns the XOR distance between the local node and the given key, as used by Kademlia to
+    /// decide which nodes are the closest to, hence responsible for, the data stored under `key`.
+    ///
+    /// Both ends are hashed into the keyspace before being compared: the local node's position is
+    /// the SHA-256 digest of its `PeerId` bytes (i.e. the multihash of its public key), while the
+    /// key's position is the SHA-256 digest of the 32 bytes of the `XorName`, as for the record
+    /// and provider keys. Hence the raw bytes of the `XorName` and of the `PeerId` can't be
+    /// compared directly.
+    pub fn key_distance(&self, key: XorName) -> Distance {
+        let record_key: RecordKey = key.0.to_vec().into();
+        KBucketKey::from(self.local_peer_id).distance(&KBucketKey::from(record_key))
+    }
+
     ///  Listen for incoming connections on the given address.
     pub async fn start_listening(&mut self, addr: Multiaddr) -> Result<()> {
         let (sender, receiver) = oneshot::channel();
@@ -176,46 +716,820 @@ impl Network {
         receiver.await?
     }
 
+    /// Dials all the given peers concurrently, e.g. right before a burst of requests to them, so
+    /// that the requests don't wait on the connection handshakes. Returns the outcome of each
+    /// dial, in the order of `peers`. The peers already connected to succeed right away.
+    ///
+    /// As with `dial`, the peers are considered explicitly dialed and are never reaped.
+    pub async fn warm_connections(
+        &mut self,
+        peers: Vec<(PeerId, Multiaddr)>,
+    ) -> Vec<(PeerId, Result<()>)> {
+        let dials = peers.into_iter().map(|(peer_id, peer_addr)| {
+            let mut network = self.clone();
+            async move { (peer_id, network.dial(peer_id, peer_addr).await) }
+        });
+        future::join_all(dials).await
+    }
+
+    /// Join the network through the given peers; they are added to the routing table before
+    /// a Kademlia bootstrap is performed to populate it. The peers are dialed at most
+    /// `NetworkConfig::bootstrap_dial_concurrency` at a time, each dial being reported by a
+    /// `NetworkEvent::BootstrapProgress`. The bootstrap goes on as long as one of them could be
+    /// reached, failing with `Error::BootstrapPeersUnreachable` otherwise; its outcome is also
+    /// reported by a `NetworkEvent::BootstrapComplete`.
+    ///
+    /// Returns `Error::BootstrapTimeout` if the bootstrap has not completed within the
+    /// configured `NetworkConfig::bootstrap_timeout`.
+    pub async fn bootstrap(&mut self, peers: Vec<(PeerId, Multiaddr)>) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::Bootstrap { peers, sender })
+            .await?;
+        match async_std::future::timeout(self.bootstrap_timeout, receiver).await {
+            Ok(result) => result?,
+            Err(_elapsed) => {
+                // No one waits for the outcome any longer, nor for the progress.
+                self.swarm_cmd_sender
+                    .send(SwarmCmd::AbandonBootstrap)
+                    .await?;
+                let connected_peers = self.connected_peers_count().await?;
+                Err(Error::BootstrapTimeout { connected_peers })
+            }
+        }
+    }
+
+    /// Returns the addresses the node is actually listening on, e.g. with the port the OS
+    /// assigned in place of port 0. Append the `local_peer_id` to get an address other nodes
+    /// can dial.
+    pub async fn listeners(&mut self) -> Result<Vec<Multiaddr>> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::Listeners { sender })
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Returns the number of peers we are currently connected to.
+    async fn connected_peers_count(&mut self) -> Result<usize> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::ConnectedPeersCount { sender })
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Checks that the `NetworkSwarmLoop` is alive and processing the commands, returning
+    /// `Error::Unresponsive` if it doesn't respond within `HEALTH_CHECK_TIMEOUT`.
+    ///
+    /// The remote peers can probe the node's responsiveness with a `Request::Ping`, which is
+    /// answered by the network layer itself.
+    pub async fn health(&mut self) -> Result<()> {
+        let check = async {
+            let (sender, receiver) = oneshot::channel();
+            self.swarm_cmd_sender
+                .send(SwarmCmd::Health { sender })
+                .await?;
+            Ok(receiver.await?)
+        };
+        match async_std::future::timeout(HEALTH_CHECK_TIMEOUT, check).await {
+            Ok(result) => result,
+            Err(_elapsed) => Err(Error::Unresponsive(HEALTH_CHECK_TIMEOUT)),
+        }
+    }
+
+    /// Checks whether the node is ready to serve traffic, i.e. sufficiently connected to the
+    /// network, as a readiness counterpart to the `health` liveness check.
+    ///
+    /// The node is ready once both:
+    /// - a `Network::bootstrap` completed successfully at least once since the node started,
+    /// - the routing table holds at least `NetworkConfig::min_ready_peers` peers.
+    ///
+    /// A node may become unready again if its routing table shrinks below the threshold.
+    pub async fn is_ready(&mut self) -> Result<bool> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::IsReady { sender })
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Subscribe to the `NetworkEvent`s matching `filter`, so focused consumers only get the
+    /// events they care about.
+    ///
+    /// Every matching event is delivered to each subscriber, as well as to the event stream
+    /// returned by `NetworkSwarmLoop::new`, except for the events carrying a response channel:
+    /// those go to the first matching subscriber only, and to the main stream if none matches.
+    /// Each stream buffers up to `NetworkConfig::event_channel_capacity` events: the main one has
+    /// to be drained for the network to progress, while a subscriber falling further behind is
+    /// disconnected, its stream ending, and counted in the
+    /// `MetricsSnapshot::lagging_subscribers_dropped`.
+    pub async fn subscribe_events(
+        &mut self,
+        filter: EventFilter,
+    ) -> Result<impl Stream<Item = NetworkEvent>> {
+        let (sender, receiver) = mpsc::channel(self.event_channel_capacity);
+        self.swarm_cmd_sender
+            .send(SwarmCmd::Subscribe {
+                subscriber: Subscriber { filter, sender },
+            })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Lists the Kademlia queries currently in flight, with how long they have been running,
+    /// e.g. to diagnose lookups that seem to hang.
+    pub async fn active_queries(&mut self) -> Result<Vec<QueryInfo>> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::ActiveQueries { sender })
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Exports the node's keypair, the peers in its routing table and the keys it provides as a
+    /// single, consistent, `IdentityBundle`, to migrate the node with
+    /// `NetworkSwarmLoop::from_identity_bundle`.
+    pub async fn export_identity_bundle(&mut self) -> Result<IdentityBundle> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::ExportIdentityBundle { sender })
+            .await?;
+        receiver.await?
+    }
+
+    /// Returns the addresses of `peer` known to the `AddressBook`.
+    pub async fn known_addresses(&mut self, peer: PeerId) -> Result<Vec<AddressEntry>> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::KnownAddresses { peer, sender })
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Returns the number of records held by the local store whose key starts with the first
+    /// `bits` bits of `prefix`, e.g. to tell whether a part of the keyspace is overloaded.
+    ///
+    /// This walks the whole store on the event loop, which doesn't process any other event in
+    /// the meantime; the cost grows linearly with the number of records, so it is meant for the
+    /// occasional rebalancing decision rather than to be polled with large stores.
+    pub async fn count_records_in_range(&mut self, prefix: XorName, bits: u8) -> Result<usize> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::CountRecordsInRange {
+                prefix: Prefix::new(usize::from(bits), prefix),
+                sender,
+            })
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Temporarily suspend networking: the listeners are closed and the connections to the peers
+    /// are dropped, while the routing table and the keypair are preserved. Until
+    /// [`Network::resume`] is called, outbound operations fail with `Error::Paused`.
+    /// Queries that were already in flight are left to complete or time out.
+    pub async fn pause(&mut self) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::Pause { sender })
+            .await?;
+        receiver.await?
+    }
+
+    /// Resume networking after a [`Network::pause`], listening again on the addresses that were
+    /// closed. An OS-assigned port (port `0`) may be different once resumed.
+    pub async fn resume(&mut self) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::Resume { sender })
+            .await?;
+        receiver.await?
+    }
+
     /// Advertise the local node as the provider of a given piece of data; The XorName of the data
-    /// is advertised to the nodes on the DHT
-    /// todo: do not use the provider api to store stuff
+    /// is advertised to the nodes on the DHT. Only the node is advertised, not the data itself,
+    /// see `Network::put_record` to store the data on the DHT.
     pub async fn store_data(&mut self, xor_name: XorName) -> Result<()> {
+        self.store_data_with_class(xor_name, DataClass::default())
+            .await
+    }
+
+    /// Store the given data like [`Network::store_data`], advertising it as per the
+    /// `NetworkConfig::data_classes` schedule of the given class.
+    pub async fn store_data_with_class(
+        &mut self,
+        xor_name: XorName,
+        class: DataClass,
+    ) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::StoreData {
+                xor_name,
+                class,
+                sender,
+            })
+            .await?;
+        receiver.await?
+    }
+
+    /// Refresh the lifetime of the provider record of a piece of data previously stored through
+    /// [`Network::store_data`], without involving the data itself.
+    ///
+    /// The provider record is re-published to the closest peers, which resets its expiry to
+    /// now plus the Kademlia provider record TTL (24h by default). The records are also
+    /// re-published automatically as per the `NetworkConfig::data_classes` schedule of their
+    /// class (every 12h by default), so touching is only needed to extend the lifetime sooner,
+    /// e.g. after re-validating the data. Touching also restarts the TTL of the class.
+    /// Returns `Error::RecordNotProvided` if the local node is not providing `key`.
+    pub async fn touch_record(&mut self, key: XorName) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::TouchRecord { key, sender })
+            .await?;
+        receiver.await?
+    }
+
+    /// Store the given data like [`Network::store_data`], but in the background, retrying with
+    /// backoff as per the `NetworkConfig::store_retry` until it succeeds. E.g. at startup, when
+    /// the routing table is still empty. The outcome is reported by `NetworkEvent::DataStored`
+    /// or `NetworkEvent::DataStoreFailed`; this returns as soon as the store is scheduled.
+    pub async fn store_data_persistent(&mut self, xor_name: XorName) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::StoreDataPersistent { xor_name, sender })
+            .await?;
+        receiver.await?
+    }
+
+    /// Looks up the peers closest to `key`, paired with their XOR distance to it and sorted
+    /// closest first. The distances are computed as in `Network::key_distance`, i.e. with
+    /// Kademlia's own metric. If the lookup times out, the closest peers found so far are
+    /// returned.
+    pub async fn get_closest_peers(&mut self, key: XorName) -> Result<Vec<(PeerId, Distance)>> {
         let (sender, receiver) = oneshot::channel();
         self.swarm_cmd_sender
-            .send(SwarmCmd::StoreData { xor_name, sender })
+            .send(SwarmCmd::GetClosestPeers { key, sender })
             .await?;
         receiver.await?
     }
 
+    /// Looks up the peers closest to `rounds` random keys, one after the other, to learn about
+    /// peers all across the keyspace rather than only those near the local node. The keys being
+    /// uniformly distributed, most lookups target the distant buckets, which hold most of the
+    /// network. Returns the number of peers added to the routing table meanwhile.
+    pub async fn discover_peers(&mut self, rounds: usize) -> Result<usize> {
+        let known_peers = self.routing_table_peers().await?;
+        for _ in 0..rounds {
+            let key = XorName::random(&mut rand::thread_rng());
+            let _closest_peers = self.get_closest_peers(key).await?;
+        }
+        let peers = self.routing_table_peers().await?;
+        Ok(peers.difference(&known_peers).count())
+    }
+
+    /// Seeds the routing table with the peers of a trusted snapshot, e.g. the member list of a
+    /// private network, for a cold start without any lookup. Unlike `Network::bootstrap`, no
+    /// peer is dialed: Kademlia connects to them as it needs. The entries of the local peer,
+    /// and the addresses meant for another peer, are skipped, and the duplicates merged.
+    /// Returns the number of peers added to the routing table.
+    pub async fn import_routing_snapshot(
+        &mut self,
+        peers: Vec<(PeerId, Vec<Multiaddr>)>,
+    ) -> Result<usize> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::ImportRoutingSnapshot { peers, sender })
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Returns the peers in the routing table.
+    async fn routing_table_peers(&mut self) -> Result<HashSet<PeerId>> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::RoutingTablePeers { sender })
+            .await?;
+        Ok(receiver.await?)
+    }
+
     /// Find the providers for the given piece of data; The XorName is used to locate the nodes
-    /// that hold the data
-    /// todo: do not use the provider api to store stuff
+    /// that hold the data. See `Network::get_record_value` to fetch data stored on the DHT.
+    /// Resolves with no provider if the lookup completes without finding any, and fails with
+    /// `Error::GetProvidersError` if it times out.
     pub async fn get_data_providers(&mut self, xor_name: XorName) -> Result<HashSet<PeerId>> {
         let (sender, receiver) = oneshot::channel();
         self.swarm_cmd_sender
-            .send(SwarmCmd::GetDataProviders { xor_name, sender })
+            .send(SwarmCmd::GetDataProviders {
+                xor_name,
+                sender,
+                op: OpSpan::new(|| info_span!("get_data_providers", key = ?xor_name)),
+            })
+            .await?;
+        receiver.await?
+    }
+
+    /// Finds the providers for the given piece of data, like `Network::get_data_providers`,
+    /// returning up to `count` of them as picked by the `NetworkConfig::provider_selection`,
+    /// e.g. the nearest ones first.
+    pub async fn select_providers(
+        &mut self,
+        xor_name: XorName,
+        count: usize,
+    ) -> Result<Vec<PeerId>> {
+        let peers = self
+            .get_data_providers(xor_name)
+            .await?
+            .into_iter()
+            .collect();
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::SelectPeers {
+                peers,
+                count,
+                sender,
+            })
             .await?;
         Ok(receiver.await?)
     }
 
+    /// Store the `Manifest` listing the given chunks on the DHT, under `manifest_name`.
+    /// If `advertise_chunks` is set, the local node is also advertised as the provider of each
+    /// of the chunks, see [`Network::store_data`].
+    pub async fn store_manifest(
+        &mut self,
+        manifest_name: XorName,
+        chunk_names: Vec<XorName>,
+        advertise_chunks: bool,
+    ) -> Result<()> {
+        let value = Manifest::new(chunk_names.clone()).encode()?;
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::PutRecord {
+                key: manifest_name,
+                value,
+                quorum: Quorum::One,
+                sender,
+            })
+            .await?;
+        receiver.await??;
+
+        if advertise_chunks {
+            for chunk_name in chunk_names {
+                self.store_data(chunk_name).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores `value` on the DHT, as a Kademlia record under `key`, completing once one of the
+    /// closest peers to the key stored it. See `Network::get_record_value` to fetch it back.
+    pub async fn put_record(&mut self, key: XorName, value: Vec<u8>) -> Result<()> {
+        self.put_record_with_quorum(key, value, Quorum::One).await
+    }
+
+    /// Stores `value` on the DHT like `Network::put_record`, completing once `quorum` of the
+    /// closest peers to the key stored it.
+    pub async fn put_record_with_quorum(
+        &mut self,
+        key: XorName,
+        value: Vec<u8>,
+        quorum: Quorum,
+    ) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::PutRecord {
+                key,
+                value,
+                quorum,
+                sender,
+            })
+            .await?;
+        receiver.await?
+    }
+
+    /// Fetches the record stored under `key` on the DHT, with its publisher and expiry as known
+    /// to Kademlia, to verify its provenance and freshness. Returns `None` if no peer holds it.
+    pub async fn get_record(&mut self, key: XorName) -> Result<Option<Record>> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::GetRecord { key, sender })
+            .await?;
+        receiver.await?
+    }
+
+    /// Fetches the value of the record stored under `key` on the DHT, see `get_record`.
+    pub async fn get_record_value(&mut self, key: XorName) -> Result<Option<Vec<u8>>> {
+        Ok(self.get_record(key).await?.map(|record| record.value))
+    }
+
+    /// Resolve the list of chunks of the `Manifest` stored under `manifest_name`.
+    pub async fn get_manifest(&mut self, manifest_name: XorName) -> Result<Vec<XorName>> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::GetRecord {
+                key: manifest_name,
+                sender,
+            })
+            .await?;
+        let record = receiver
+            .await??
+            .ok_or(Error::ManifestNotFound(manifest_name))?;
+        Ok(Manifest::decode(&record.value)?.into_chunk_names())
+    }
+
+    /// Put `new` under `key` on the DHT, only if the value currently found there is `expected`;
+    /// `None` expecting no record at all. Returns whether the swap happened.
+    ///
+    /// This is a best-effort conditional write: the current value is fetched, compared, and the
+    /// new one put with a majority quorum of the closest peers. The DHT being eventually
+    /// consistent it is not linearizable. Concurrent swaps can both succeed, the last write
+    /// winning on each replica, and the value read may be stale or differ between replicas.
+    /// It is suited to advisory coordination; callers that need stronger guarantees should
+    /// embed a version or a writer id in the value and read it back to confirm their write won.
+    pub async fn compare_and_swap(
+        &mut self,
+        key: XorName,
+        expected: Option<Vec<u8>>,
+        new: Vec<u8>,
+    ) -> Result<bool> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::GetRecord { key, sender })
+            .await?;
+        if receiver.await??.map(|record| record.value) != expected {
+            return Ok(false);
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::PutRecord {
+                key,
+                value: new,
+                quorum: Quorum::Majority,
+                sender,
+            })
+            .await?;
+        receiver.await??;
+        Ok(true)
+    }
+
     /// Send `Request` to the the given `PeerId`
+    ///
+    /// A request failing once sent returns `Error::RequestFailed`, telling how far its transfer
+    /// got, i.e. whether retrying it is safe, see `TransferProgress::nothing_sent`.
     pub async fn send_request(&mut self, req: Request, peer: PeerId) -> Result<Response> {
+        self.send_request_with_key(req, peer, None).await
+    }
+
+    /// Send `Request` to the the given `PeerId`, unless a request with the same `key`, i.e. for
+    /// the same logical item, was sent to it within the `NetworkConfig::request_coalescing_window`
+    /// and is still in flight: the response to the latter, or its failure, is then returned
+    /// instead. The requests sharing a key are expected to be identical, only the first one
+    /// being sent.
+    pub async fn send_coalesced_request(
+        &mut self,
+        req: Request,
+        peer: PeerId,
+        key: XorName,
+    ) -> Result<Response> {
+        self.send_request_with_key(req, peer, Some(key)).await
+    }
+
+    async fn send_request_with_key(
+        &mut self,
+        req: Request,
+        peer: PeerId,
+        coalesce_key: Option<XorName>,
+    ) -> Result<Response> {
+        check_size(
+            MSG_PROTOCOL_NAME,
+            encoded_len(self.msg_format, &req),
+            self.msg_limits.max_request_size,
+        )?;
         let (sender, receiver) = oneshot::channel();
         self.swarm_cmd_sender
-            .send(SwarmCmd::SendRequest { req, peer, sender })
+            .send(SwarmCmd::SendRequest {
+                req,
+                peer,
+                coalesce_key,
+                sender,
+                op: OpSpan::new(|| info_span!("send_request", %peer)),
+            })
             .await?;
         receiver.await?
     }
 
+    /// Send the `Request` to all the given peers and return as soon as `quorum` of them replied
+    /// with a matching `Response`; the requests still in flight are then ignored.
+    ///
+    /// Responses match when they are equal, hence the peers act as replicas that have to agree
+    /// on the content. Failed requests never count towards the quorum.
+    /// Returns `Error::QuorumNotReached`, with all the responses gathered, once there are not
+    /// enough outstanding requests left to reach the quorum.
+    pub async fn quorum_request(
+        &mut self,
+        req: Request,
+        peers: Vec<PeerId>,
+        quorum: usize,
+    ) -> Result<Vec<Response>> {
+        if quorum == 0 {
+            return Ok(Vec::new());
+        }
+        let mut outstanding = peers.len();
+        let mut requests: FuturesUnordered<_> = peers
+            .into_iter()
+            .map(|peer| {
+                let mut network = self.clone();
+                let req = req.clone();
+                async move { network.send_request(req, peer).await }
+            })
+            .collect();
+
+        let mut responses: Vec<Response> = Vec::new();
+        while let Some(result) = requests.next().await {
+            outstanding -= 1;
+            match result {
+                Ok(response) => {
+                    let matching = responses.iter().filter(|r| **r == response).count() + 1;
+                    if matching >= quorum {
+                        return Ok(vec![response; quorum]);
+                    }
+                    responses.push(response);
+                }
+                Err(err) => warn!("Request failed while gathering a quorum: {err}"),
+            }
+            // Bail out early if even the remaining requests can't make up the quorum.
+            let best = most_matching(&responses);
+            if best + outstanding < quorum {
+                break;
+            }
+        }
+
+        Err(Error::QuorumNotReached { quorum, responses })
+    }
+
+    /// Send the `Request` to the peers closest to `target`, as found by
+    /// `Network::get_closest_peers`, and return the first successful `Response`.
+    ///
+    /// Up to `parallelism` requests are in flight at once, the closest peers first; each failed
+    /// request, or `Response::Overloaded`, has the next closest peer tried in its place. The
+    /// requests still in flight once a response is returned are ignored.
+    /// Returns `Error::NoPeerResponded`, with the number of peers tried, if none of them replied.
+    pub async fn request_from_closest(
+        &mut self,
+        target: XorName,
+        req: Request,
+        parallelism: usize,
+    ) -> Result<Response> {
+        let mut candidates = self
+            .get_closest_peers(target)
+            .await?
+            .into_iter()
+            .map(|(peer, _distance)| peer);
+        let mut requests = FuturesUnordered::new();
+        let mut tried = 0;
+        loop {
+            while requests.len() < parallelism.max(1) {
+                let peer = match candidates.next() {
+                    Some(peer) => peer,
+                    None => break,
+                };
+                tried += 1;
+                let mut network = self.clone();
+                let req = req.clone();
+                requests.push(async move { (peer, network.send_request(req, peer).await) });
+            }
+            match requests.next().await {
+                Some((_peer, Ok(response))) if response != Response::Overloaded => {
+                    return Ok(response)
+                }
+                Some((peer, Ok(_overloaded))) => warn!("The closest peer {peer:?} is overloaded"),
+                Some((peer, Err(err))) => {
+                    warn!("Request to the closest peer {peer:?} failed: {err}")
+                }
+                None => return Err(Error::NoPeerResponded { tried }),
+            }
+        }
+    }
+
     /// Send a `Response` through the channel opened by the requester.
     pub async fn send_response(
         &mut self,
         resp: Response,
-        channel: ResponseChannel<Response>,
+        channel: ResponseHandle<Response>,
     ) -> Result<()> {
+        check_size(
+            MSG_PROTOCOL_NAME,
+            encoded_len(self.msg_format, &resp),
+            self.msg_limits.max_response_size,
+        )?;
+        let peer = channel.peer();
+        let channel = channel.take().ok_or_else(already_responded)?;
         Ok(self
             .swarm_cmd_sender
-            .send(SwarmCmd::SendResponse { resp, channel })
+            .send(SwarmCmd::SendResponse {
+                resp,
+                peer,
+                channel,
+            })
             .await?)
     }
+
+    /// Send raw bytes to the given `PeerId` over a dedicated protocol, returning the raw
+    /// response. Raw messages bypass the `Request`/`Response` encoding and any typed validation;
+    /// only the `NetworkConfig::raw_limits` are enforced. Fails with `Error::Overloaded` if the
+    /// peer is shedding load, and with `Error::OutboundError` if the request fails.
+    pub async fn send_raw(&mut self, bytes: Vec<u8>, peer: PeerId) -> Result<Vec<u8>> {
+        check_size(
+            RAW_PROTOCOL_NAME,
+            bytes.len(),
+            self.raw_limits.max_request_size,
+        )?;
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::SendRawRequest {
+                bytes,
+                peer,
+                sender,
+            })
+            .await?;
+        receiver.await?
+    }
+
+    /// Enables the request protocol called `name`, i.e. `/msg/2` or `/raw/2`, after it was
+    /// disabled through `Network::disable_protocol`. Returns `Error::UnknownProtocol` for any
+    /// other name.
+    pub async fn enable_protocol(&mut self, name: &str) -> Result<()> {
+        self.set_protocol_enabled(name, true).await
+    }
+
+    /// Disables the request protocol called `name`, i.e. `/msg/2` or `/raw/2`, e.g. to turn off
+    /// the raw protocol under load: the outbound requests then fail with
+    /// `Error::ProtocolDisabled` and the inbound ones are dropped, the peers getting a failure.
+    /// The disabled protocols are no longer listed through identify, the connected peers being
+    /// pushed the update, and a `NetworkEvent::ProtocolsChanged` is emitted.
+    pub async fn disable_protocol(&mut self, name: &str) -> Result<()> {
+        self.set_protocol_enabled(name, false).await
+    }
+
+    /// Returns the names of the request protocols currently enabled.
+    pub async fn active_protocols(&mut self) -> Result<Vec<String>> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::ActiveProtocols { sender })
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    async fn set_protocol_enabled(&mut self, name: &str, enabled: bool) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::SetProtocolEnabled {
+                name: name.to_string(),
+                enabled,
+                sender,
+            })
+            .await?;
+        receiver.await?
+    }
+
+    /// Subscribes to the topic, for the messages published on it to be delivered as
+    /// `NetworkEvent::GossipMessage`s. The subscription is announced to the peers as they
+    /// connect, hence holds across reconnects; subscribing twice is a no-op.
+    pub async fn subscribe(&mut self, topic: &str) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::GossipSubscribe {
+                topic: topic.to_string(),
+                sender,
+            })
+            .await?;
+        receiver.await?
+    }
+
+    /// Broadcasts the data to the peers subscribed to the topic, relayed through the gossipsub
+    /// mesh to those we are not connected to. Fails with `Error::NoTopicPeers` if none of the
+    /// connected peers is known to be subscribed, the message being dropped otherwise. The
+    /// local node doesn't get its own messages back. The message, once signed and framed, must
+    /// fit within 64 KiB, failing with `Error::PublishError` otherwise.
+    pub async fn publish(&mut self, topic: &str, data: Vec<u8>) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.swarm_cmd_sender
+            .send(SwarmCmd::GossipPublish {
+                topic: topic.to_string(),
+                data,
+                sender,
+            })
+            .await?;
+        receiver.await?
+    }
+
+    /// Send raw response bytes through the channel opened by the requester.
+    pub async fn send_raw_response(
+        &mut self,
+        bytes: Vec<u8>,
+        channel: ResponseHandle<RawResponse>,
+    ) -> Result<()> {
+        check_size(
+            RAW_PROTOCOL_NAME,
+            bytes.len(),
+            self.raw_limits.max_response_size,
+        )?;
+        let channel = channel.take().ok_or_else(already_responded)?;
+        Ok(self
+            .swarm_cmd_sender
+            .send(SwarmCmd::SendRawResponse { bytes, channel })
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        error::{Error, Result},
+        testing::{isolated_config, listen_addr},
+        Network, NetworkConfig, NetworkEvent, NetworkSwarmLoop, Request, Response,
+    };
+    use async_std::{future::timeout, task};
+    use futures::StreamExt;
+    use libp2p::{Multiaddr, PeerId};
+    use std::time::Duration;
+    use xor_name::XorName;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    // Spawns a node answering every request with `response`, returning its id and address.
+    async fn spawn_responder(response: Response) -> Result<(PeerId, Multiaddr)> {
+        let (mut responder, mut events, swarm_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _swarm_loop = task::spawn(swarm_loop.run());
+        let peer_id = responder.local_peer_id();
+        let addr = listen_addr(&mut responder).await?;
+        let _responder = task::spawn(async move {
+            while let Some(event) = events.next().await {
+                if let NetworkEvent::RequestReceived { channel, .. } = event {
+                    let _ = responder.send_response(response.clone(), channel).await;
+                }
+            }
+        });
+        Ok((peer_id, addr))
+    }
+
+    async fn request_from_closest(requester: &mut Network) -> Result<Response> {
+        let target = XorName::random(&mut rand::thread_rng());
+        timeout(
+            TEST_TIMEOUT,
+            requester.request_from_closest(target, Request::GetDBC, 2),
+        )
+        .await
+        .expect("the request to complete")
+    }
+
+    #[async_std::test]
+    async fn invalid_bind_addresses_are_refused_with_an_error() -> Result<()> {
+        // A well-formed address, but over TCP while only QUIC is enabled.
+        let config = NetworkConfig {
+            listen_addr: Some("/ip4/127.0.0.1/tcp/0".parse()?),
+            ..Default::default()
+        };
+        assert!(matches!(
+            NetworkSwarmLoop::with_config(config),
+            Err(Error::TransportError(_))
+        ));
+
+        // And the other way round, once running.
+        let (mut network, events, event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let _event_loop = task::spawn(event_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+        let quic: Multiaddr = "/ip4/127.0.0.1/udp/0/quic-v1".parse()?;
+        let result = timeout(TEST_TIMEOUT, network.start_listening(quic))
+            .await
+            .expect("the loop to answer");
+        assert!(matches!(result, Err(Error::TransportError(_))));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn request_from_closest_returns_the_first_successful_response() -> Result<()> {
+        let (mut requester, events, swarm_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let _swarm_loop = task::spawn(swarm_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+
+        let (overloaded_id, overloaded_addr) = spawn_responder(Response::Overloaded).await?;
+        timeout(TEST_TIMEOUT, requester.dial(overloaded_id, overloaded_addr))
+            .await
+            .expect("the dial to complete")?;
+        assert!(matches!(
+            request_from_closest(&mut requester).await,
+            Err(Error::NoPeerResponded { tried: 1 })
+        ));
+
+        let (responder_id, responder_addr) = spawn_responder(Response::Pong).await?;
+        timeout(TEST_TIMEOUT, requester.dial(responder_id, responder_addr))
+            .await
+            .expect("the dial to complete")?;
+        assert_eq!(request_from_closest(&mut requester).await?, Response::Pong);
+
+        Ok(())
+    }
 }
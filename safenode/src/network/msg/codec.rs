@@ -6,11 +6,15 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::storage::chunks::Chunk;
+use super::{progress::TransferTracker, MsgFormat};
+use crate::{
+    network::{config::ProtocolLimits, egress::Egress},
+    storage::chunks::Chunk,
+};
 use async_trait::async_trait;
-use futures::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use libp2p::{
-    core::upgrade::{read_length_prefixed, write_length_prefixed},
+    core::upgrade::{read_varint, write_length_prefixed},
     request_response::{self, ProtocolName},
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -24,6 +28,38 @@ pub enum Request {
     GetChunk(XorName),
     /// todo: impl entire DataStorage struct
     GetDBC,
+    /// Liveness probe, answered by the network layer itself with `Response::Pong`
+    Ping,
+}
+
+impl Request {
+    /// Whether handling the request more than once has the same effect as handling it once,
+    /// hence whether it can be sent again after some of it reached the peer.
+    pub fn is_idempotent(&self) -> bool {
+        match self {
+            Request::GetChunk(_) | Request::GetDBC | Request::Ping => true,
+        }
+    }
+}
+
+/// A `Request` as carried by the `MsgCodec`. The outbound ones come with the tracking of how far
+/// their transfer got, for a failure to tell whether the peer may have acted on them.
+#[derive(Debug, Clone)]
+pub(crate) struct MsgRequest {
+    pub(crate) request: Request,
+    tracker: TransferTracker,
+}
+
+impl MsgRequest {
+    pub(crate) fn new(request: Request, tracker: TransferTracker) -> Self {
+        Self { request, tracker }
+    }
+}
+
+impl From<Request> for MsgRequest {
+    fn from(request: Request) -> Self {
+        Self::new(request, TransferTracker::default())
+    }
 }
 
 /// Respond to other peers in the network
@@ -33,30 +69,192 @@ pub enum Response {
     Chunk(Chunk),
     /// todo: impl entire DataStorage struct
     DBC,
+    /// Answer to a `Request::Ping`
+    Pong,
+    /// The node is too busy to handle the request, which is to be retried later
+    Overloaded,
+}
+
+/// The default maximum size of a message, typed or raw, read from or written to a stream.
+pub(crate) const MAX_MSG_SIZE: usize = 2 * 1024 * 1024;
+
+/// The name of the `Request`/`Response` protocol. Version 2 tags each message with its
+/// `MsgFormat`, unreadable by the nodes speaking `/msg/1`, which wrote bare MessagePack.
+pub(crate) const MSG_PROTOCOL_NAME: &str = "/msg/2";
+/// The name of the raw bytes protocol. Version 2 tags each response with its `RawResponse`
+/// kind, unreadable by the nodes speaking `/raw/1`, which wrote the bare bytes.
+pub(crate) const RAW_PROTOCOL_NAME: &str = "/raw/2";
+
+/// The response to a raw request, see `Network::send_raw`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawResponse {
+    /// The bytes answered by the peer
+    Bytes(Vec<u8>),
+    /// The node is too busy to handle the request, which is to be retried later
+    Overloaded,
+}
+
+// The tags of the `RawResponse` kinds on the wire, ahead of the bytes of a `RawResponse::Bytes`.
+const RAW_BYTES_TAG: u8 = 0;
+const RAW_OVERLOADED_TAG: u8 = 1;
+
+/// The error carried by the `io::Error` of a codec refusing a message over its `ProtocolLimits`,
+/// surfaced as `Error::MessageTooLarge`.
+#[derive(Debug, thiserror::Error)]
+#[error("Message of {size} bytes exceeds the maximum of {max} bytes for protocol {protocol}")]
+pub(crate) struct FrameTooLarge {
+    pub(crate) protocol: &'static str,
+    pub(crate) size: usize,
+    pub(crate) max: usize,
 }
 
+impl FrameTooLarge {
+    fn into_io_error(self, kind: io::ErrorKind) -> io::Error {
+        io::Error::new(kind, self)
+    }
+}
+
+/// Protocol used to exchange the `Request`/`Response` messages, see `MsgCodec`.
 #[derive(Debug, Clone)]
 pub(crate) struct MsgProtocol();
+/// Codec for the `Request`/`Response` messages, enforcing the encoded size limits.
+/// The messages are written in its `MsgFormat`, and read in any of them.
 #[derive(Clone)]
-pub(crate) struct MsgCodec();
+pub(crate) struct MsgCodec {
+    limits: ProtocolLimits,
+    format: MsgFormat,
+    egress: Egress,
+    // The tracker of the request written, for the same codec to read its response: the
+    // codec is cloned for every outbound request.
+    tracker: Option<TransferTracker>,
+}
+
+impl MsgCodec {
+    pub(crate) fn new(limits: ProtocolLimits, format: MsgFormat, egress: Egress) -> Self {
+        Self {
+            limits,
+            format,
+            egress,
+            tracker: None,
+        }
+    }
+}
 
 impl ProtocolName for MsgProtocol {
     fn protocol_name(&self) -> &[u8] {
-        "/msg/1".as_bytes()
+        MSG_PROTOCOL_NAME.as_bytes()
+    }
+}
+
+/// Protocol used to exchange opaque bytes, see `RawCodec`.
+#[derive(Debug, Clone)]
+pub(crate) struct RawProtocol();
+/// Codec that passes the bytes through as they are, only enforcing the size limits.
+/// The content of raw messages is not validated in any way.
+#[derive(Clone)]
+pub(crate) struct RawCodec {
+    limits: ProtocolLimits,
+    egress: Egress,
+}
+
+impl RawCodec {
+    pub(crate) fn new(limits: ProtocolLimits, egress: Egress) -> Self {
+        Self { limits, egress }
+    }
+}
+
+impl ProtocolName for RawProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        RAW_PROTOCOL_NAME.as_bytes()
     }
 }
 
 #[async_trait]
 impl request_response::Codec for MsgCodec {
     type Protocol = MsgProtocol;
-    type Request = Request;
+    type Request = MsgRequest;
     type Response = Response;
 
     async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
     where
         T: AsyncRead + Unpin + Send,
     {
-        read_and_decode(io).await
+        let request: Request =
+            read_and_decode(io, MSG_PROTOCOL_NAME, self.limits.max_request_size).await?;
+        Ok(request.into())
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let max_size = self.limits.max_response_size;
+        match &self.tracker {
+            Some(tracker) => {
+                let mut io = tracker.response_stream(io);
+                read_and_decode(&mut io, MSG_PROTOCOL_NAME, max_size).await
+            }
+            None => read_and_decode(io, MSG_PROTOCOL_NAME, max_size).await,
+        }
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        req: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let tracker = self.tracker.insert(req.tracker);
+        encode_and_write(
+            &mut tracker.request_stream(io),
+            &req.request,
+            MSG_PROTOCOL_NAME,
+            self.format,
+            self.limits.max_request_size,
+            &self.egress,
+        )
+        .await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        res: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        encode_and_write(
+            io,
+            &res,
+            MSG_PROTOCOL_NAME,
+            self.format,
+            self.limits.max_response_size,
+            &self.egress,
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl request_response::Codec for RawCodec {
+    type Protocol = RawProtocol;
+    type Request = Vec<u8>;
+    type Response = RawResponse;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_limited(io, RAW_PROTOCOL_NAME, self.limits.max_request_size).await
     }
 
     async fn read_response<T>(
@@ -67,7 +265,18 @@ impl request_response::Codec for MsgCodec {
     where
         T: AsyncRead + Unpin + Send,
     {
-        read_and_decode(io).await
+        let mut tag = [0; 1];
+        io.read_exact(&mut tag).await?;
+        match tag[0] {
+            RAW_BYTES_TAG => read_limited(io, RAW_PROTOCOL_NAME, self.limits.max_response_size)
+                .await
+                .map(RawResponse::Bytes),
+            RAW_OVERLOADED_TAG => Ok(RawResponse::Overloaded),
+            tag => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown raw response tag {tag}"),
+            )),
+        }
     }
 
     async fn write_request<T>(
@@ -79,7 +288,14 @@ impl request_response::Codec for MsgCodec {
     where
         T: AsyncWrite + Unpin + Send,
     {
-        encode_and_write(io, req).await
+        write_limited(
+            io,
+            req,
+            RAW_PROTOCOL_NAME,
+            self.limits.max_request_size,
+            &self.egress,
+        )
+        .await
     }
 
     async fn write_response<T>(
@@ -91,33 +307,485 @@ impl request_response::Codec for MsgCodec {
     where
         T: AsyncWrite + Unpin + Send,
     {
-        encode_and_write(io, res).await
+        match res {
+            RawResponse::Bytes(bytes) => {
+                io.write_all(&[RAW_BYTES_TAG]).await?;
+                write_limited(
+                    io,
+                    bytes,
+                    RAW_PROTOCOL_NAME,
+                    self.limits.max_response_size,
+                    &self.egress,
+                )
+                .await
+            }
+            RawResponse::Overloaded => {
+                io.write_all(&[RAW_OVERLOADED_TAG]).await?;
+                io.close().await
+            }
+        }
+    }
+}
+
+// Reads the length prefixed bytes, refusing the messages longer than `max_size` from their
+// prefix alone, before anything gets allocated for them
+async fn read_limited<IO>(
+    io: &mut IO,
+    protocol: &'static str,
+    max_size: usize,
+) -> io::Result<Vec<u8>>
+where
+    IO: AsyncRead + Unpin,
+{
+    let size = read_varint(io).await?;
+    if size > max_size {
+        return Err(FrameTooLarge {
+            protocol,
+            size,
+            max: max_size,
+        }
+        .into_io_error(io::ErrorKind::InvalidData));
     }
+    let mut bytes = vec![0; size];
+    io.read_exact(&mut bytes).await?;
+    Ok(bytes)
 }
 
-// Encodes the Response/Response using rmp_serde
-async fn encode_and_write<IO, T>(io: &mut IO, data: T) -> io::Result<()>
+// Writes the bytes, refusing to send more than what the remote is willing to read
+async fn write_limited<IO>(
+    io: &mut IO,
+    bytes: Vec<u8>,
+    protocol: &'static str,
+    max_size: usize,
+    egress: &Egress,
+) -> io::Result<()>
 where
     IO: AsyncWrite + Unpin,
-    T: Serialize,
 {
-    let bytes = rmp_serde::to_vec(&data)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+    if bytes.len() > max_size {
+        return Err(FrameTooLarge {
+            protocol,
+            size: bytes.len(),
+            max: max_size,
+        }
+        .into_io_error(io::ErrorKind::InvalidInput));
+    }
+    egress.reserve(bytes.len()).await;
     write_length_prefixed(io, bytes).await?;
     io.close().await?;
     Ok(())
 }
 
-// Decodes the Response/Response using rmp_serde
-async fn read_and_decode<IO, T>(io: &mut IO) -> io::Result<T>
+// Encodes the Request/Response in the given format
+async fn encode_and_write<IO, T>(
+    io: &mut IO,
+    data: &T,
+    protocol: &'static str,
+    format: MsgFormat,
+    max_size: usize,
+    egress: &Egress,
+) -> io::Result<()>
+where
+    IO: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let bytes = format.encode(data)?;
+    write_limited(io, bytes, protocol, max_size, egress).await
+}
+
+// Returns the size of the encoded Request/Response, zero if it can't be encoded; the
+// codec then reports the encoding error itself.
+pub(crate) fn encoded_len<T: Serialize>(format: MsgFormat, data: &T) -> usize {
+    format.encode(data).map_or(0, |bytes| bytes.len())
+}
+
+// Decodes the Request/Response, in whichever format it has been encoded
+async fn read_and_decode<IO, T>(
+    io: &mut IO,
+    protocol: &'static str,
+    max_size: usize,
+) -> io::Result<T>
 where
     IO: AsyncRead + Unpin,
     T: DeserializeOwned,
 {
-    let vec = read_length_prefixed(io, 500_000_000).await?; // update transfer maximum
+    let vec = read_limited(io, protocol, max_size).await?;
     if vec.is_empty() {
         return Err(io::ErrorKind::UnexpectedEof.into());
     }
-    rmp_serde::from_slice::<T>(vec.as_slice())
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    MsgFormat::decode(&vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{error::Error, msg::TransferProgress};
+    use bytes::Bytes;
+    use futures::io::Cursor;
+    use libp2p::{core::upgrade::write_varint, request_response::Codec};
+
+    const LIMITS: ProtocolLimits = ProtocolLimits {
+        max_request_size: 64,
+        max_response_size: 1024,
+    };
+
+    fn chunk_response(size: usize) -> Response {
+        Response::Chunk(Chunk::new(Bytes::from(vec![0; size])))
+    }
+
+    #[async_std::test]
+    async fn msg_codec_enforces_request_and_response_limits_independently() -> io::Result<()> {
+        let mut codec = MsgCodec::new(LIMITS, MsgFormat::default(), Egress::default());
+
+        let mut io = Cursor::new(Vec::new());
+        codec
+            .write_request(
+                &MsgProtocol(),
+                &mut io,
+                Request::GetChunk(XorName::default()).into(),
+            )
+            .await?;
+        // Responses are allowed to be larger than the request limit.
+        let mut io = Cursor::new(Vec::new());
+        codec
+            .write_response(&MsgProtocol(), &mut io, chunk_response(512))
+            .await?;
+
+        let mut io = Cursor::new(Vec::new());
+        let err = codec
+            .write_response(&MsgProtocol(), &mut io, chunk_response(2048))
+            .await
+            .expect_err("oversized response to be refused");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(io.into_inner().is_empty());
+
+        let mut codec = MsgCodec::new(
+            ProtocolLimits {
+                max_request_size: 8,
+                ..LIMITS
+            },
+            MsgFormat::default(),
+            Egress::default(),
+        );
+        let mut io = Cursor::new(Vec::new());
+        let err = codec
+            .write_request(
+                &MsgProtocol(),
+                &mut io,
+                Request::GetChunk(XorName::default()).into(),
+            )
+            .await
+            .expect_err("oversized request to be refused");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn msg_codec_refuses_to_read_oversized_messages() -> io::Result<()> {
+        let mut io = Cursor::new(Vec::new());
+        MsgCodec::new(
+            ProtocolLimits::default(),
+            MsgFormat::default(),
+            Egress::default(),
+        )
+        .write_response(&MsgProtocol(), &mut io, chunk_response(2048))
+        .await?;
+
+        let mut codec = MsgCodec::new(LIMITS, MsgFormat::default(), Egress::default());
+        let mut io = Cursor::new(io.into_inner());
+        assert!(codec.read_response(&MsgProtocol(), &mut io).await.is_err());
+
+        let mut io = Cursor::new(Vec::new());
+        MsgCodec::new(
+            ProtocolLimits::default(),
+            MsgFormat::default(),
+            Egress::default(),
+        )
+        .write_response(&MsgProtocol(), &mut io, chunk_response(512))
+        .await?;
+        let mut io = Cursor::new(io.into_inner());
+        let response = codec.read_response(&MsgProtocol(), &mut io).await?;
+        assert_eq!(response, chunk_response(512));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn msg_codec_limit_is_exact_and_reported_as_message_too_large() -> io::Result<()> {
+        let response = chunk_response(512);
+        let size = encoded_len(MsgFormat::default(), &response);
+        let at_limit = ProtocolLimits {
+            max_response_size: size,
+            ..LIMITS
+        };
+        let under_limit = ProtocolLimits {
+            max_response_size: size - 1,
+            ..LIMITS
+        };
+
+        let mut io = Cursor::new(Vec::new());
+        MsgCodec::new(at_limit, MsgFormat::default(), Egress::default())
+            .write_response(&MsgProtocol(), &mut io, response.clone())
+            .await?;
+        let bytes = io.into_inner();
+        let mut codec = MsgCodec::new(at_limit, MsgFormat::default(), Egress::default());
+        let mut io = Cursor::new(bytes.clone());
+        assert_eq!(
+            codec.read_response(&MsgProtocol(), &mut io).await?,
+            response
+        );
+
+        // A single byte over, either way.
+        let mut codec = MsgCodec::new(under_limit, MsgFormat::default(), Egress::default());
+        let mut io = Cursor::new(Vec::new());
+        let err = codec
+            .write_response(&MsgProtocol(), &mut io, response)
+            .await
+            .expect_err("oversized response to be refused");
+        assert!(matches!(
+            Error::from(err),
+            Error::MessageTooLarge { protocol: MSG_PROTOCOL_NAME, size: s, max } if s == size && max == size - 1
+        ));
+        let mut io = Cursor::new(bytes);
+        let err = codec
+            .read_response(&MsgProtocol(), &mut io)
+            .await
+            .expect_err("oversized response to be refused");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(
+            Error::from(err),
+            Error::MessageTooLarge { size: s, .. } if s == size
+        ));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn codecs_refuse_forged_lengths_before_allocating() -> io::Result<()> {
+        // A terabyte long message would abort the test if it were allocated.
+        let forged_len = 1 << 40;
+        let mut forged = Cursor::new(Vec::new());
+        write_varint(&mut forged, forged_len).await?;
+        let forged = forged.into_inner();
+
+        let mut codec = MsgCodec::new(LIMITS, MsgFormat::default(), Egress::default());
+        let err = codec
+            .read_request(&MsgProtocol(), &mut Cursor::new(forged.clone()))
+            .await
+            .expect_err("forged request to be refused");
+        assert!(matches!(
+            Error::from(err),
+            Error::MessageTooLarge { size, max: 64, .. } if size == forged_len
+        ));
+
+        let mut codec = RawCodec::new(LIMITS, Egress::default());
+        let forged_response = [&[RAW_BYTES_TAG][..], &forged].concat();
+        let err = codec
+            .read_response(&RawProtocol(), &mut Cursor::new(forged_response))
+            .await
+            .expect_err("forged response to be refused");
+        assert!(matches!(
+            Error::from(err),
+            Error::MessageTooLarge { protocol: RAW_PROTOCOL_NAME, size, max: 1024 } if size == forged_len
+        ));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn raw_codec_enforces_request_and_response_limits_independently() -> io::Result<()> {
+        let mut codec = RawCodec::new(LIMITS, Egress::default());
+
+        let mut io = Cursor::new(Vec::new());
+        codec
+            .write_request(&RawProtocol(), &mut io, vec![0; 64])
+            .await?;
+        let mut io = Cursor::new(Vec::new());
+        let err = codec
+            .write_request(&RawProtocol(), &mut io, vec![0; 65])
+            .await
+            .expect_err("oversized request to be refused");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let mut io = Cursor::new(Vec::new());
+        codec
+            .write_response(&RawProtocol(), &mut io, RawResponse::Bytes(vec![0; 1024]))
+            .await?;
+        let mut io = Cursor::new(Vec::new());
+        let err = codec
+            .write_response(&RawProtocol(), &mut io, RawResponse::Bytes(vec![0; 1025]))
+            .await
+            .expect_err("oversized response to be refused");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn raw_codec_refuses_to_read_oversized_messages() -> io::Result<()> {
+        let mut io = Cursor::new(Vec::new());
+        RawCodec::new(ProtocolLimits::default(), Egress::default())
+            .write_request(&RawProtocol(), &mut io, vec![1; 65])
+            .await?;
+        let bytes = io.into_inner();
+
+        let mut codec = RawCodec::new(LIMITS, Egress::default());
+        let mut io = Cursor::new(bytes.clone());
+        assert!(codec.read_request(&RawProtocol(), &mut io).await.is_err());
+        // The same message is within the response limit.
+        let mut io = Cursor::new([&[RAW_BYTES_TAG][..], &bytes].concat());
+        let response = codec.read_response(&RawProtocol(), &mut io).await?;
+        assert_eq!(response, RawResponse::Bytes(vec![1; 65]));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn raw_codec_round_trips_every_response() -> io::Result<()> {
+        let mut codec = RawCodec::new(LIMITS, Egress::default());
+        for response in [
+            RawResponse::Bytes(Vec::new()),
+            RawResponse::Bytes(vec![1; 1024]),
+            RawResponse::Overloaded,
+        ] {
+            let mut io = Cursor::new(Vec::new());
+            codec
+                .write_response(&RawProtocol(), &mut io, response.clone())
+                .await?;
+            let mut io = Cursor::new(io.into_inner());
+            assert_eq!(
+                codec.read_response(&RawProtocol(), &mut io).await?,
+                response
+            );
+        }
+
+        let mut io = Cursor::new(vec![2]);
+        let err = codec
+            .read_response(&RawProtocol(), &mut io)
+            .await
+            .expect_err("unknown tag to be refused");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn msg_codec_round_trips_in_every_format() -> io::Result<()> {
+        for format in MsgFormat::ALL {
+            let mut codec = MsgCodec::new(ProtocolLimits::default(), format, Egress::default());
+
+            let request = Request::GetChunk(XorName::from_content(b"chunk"));
+            let mut io = Cursor::new(Vec::new());
+            codec
+                .write_request(&MsgProtocol(), &mut io, request.clone().into())
+                .await?;
+            let mut io = Cursor::new(io.into_inner());
+            assert_eq!(
+                codec.read_request(&MsgProtocol(), &mut io).await?.request,
+                request
+            );
+
+            for response in [chunk_response(512), Response::Pong, Response::Overloaded] {
+                let mut io = Cursor::new(Vec::new());
+                codec
+                    .write_response(&MsgProtocol(), &mut io, response.clone())
+                    .await?;
+                let mut io = Cursor::new(io.into_inner());
+                assert_eq!(
+                    codec.read_response(&MsgProtocol(), &mut io).await?,
+                    response,
+                    "{format:?}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn msg_codec_reads_the_messages_of_every_format() -> io::Result<()> {
+        for written_in in MsgFormat::ALL {
+            let mut io = Cursor::new(Vec::new());
+            MsgCodec::new(ProtocolLimits::default(), written_in, Egress::default())
+                .write_response(&MsgProtocol(), &mut io, chunk_response(64))
+                .await?;
+            let bytes = io.into_inner();
+
+            for read_in in MsgFormat::ALL {
+                let mut codec =
+                    MsgCodec::new(ProtocolLimits::default(), read_in, Egress::default());
+                let mut io = Cursor::new(bytes.clone());
+                assert_eq!(
+                    codec.read_response(&MsgProtocol(), &mut io).await?,
+                    chunk_response(64),
+                    "written in {written_in:?}, read in {read_in:?}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn msg_codec_writes_bincode_by_default() -> io::Result<()> {
+        assert_eq!(MsgFormat::default(), MsgFormat::Bincode);
+        let mut io = Cursor::new(Vec::new());
+        MsgCodec::new(LIMITS, MsgFormat::default(), Egress::default())
+            .write_response(&MsgProtocol(), &mut io, Response::Pong)
+            .await?;
+        let mut io = Cursor::new(io.into_inner());
+        let bytes = read_limited(&mut io, MSG_PROTOCOL_NAME, LIMITS.max_response_size).await?;
+        assert_eq!(bytes, MsgFormat::Bincode.encode(&Response::Pong)?);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn msg_codec_refuses_unknown_formats() -> io::Result<()> {
+        let mut io = Cursor::new(Vec::new());
+        write_length_prefixed(&mut io, [u8::MAX, 0, 0]).await?;
+
+        let mut codec = MsgCodec::new(LIMITS, MsgFormat::default(), Egress::default());
+        let mut io = Cursor::new(io.into_inner());
+        let err = codec
+            .read_request(&MsgProtocol(), &mut io)
+            .await
+            .expect_err("unknown format to be refused");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn msg_codec_tracks_the_transfer_of_requests() -> io::Result<()> {
+        let tracker = TransferTracker::default();
+        let mut codec = MsgCodec::new(LIMITS, MsgFormat::default(), Egress::default());
+        assert!(tracker.progress().nothing_sent());
+
+        let mut io = Cursor::new(Vec::new());
+        codec
+            .write_request(
+                &MsgProtocol(),
+                &mut io,
+                MsgRequest::new(Request::GetChunk(XorName::default()), tracker.clone()),
+            )
+            .await?;
+        let request_len = io.into_inner().len();
+
+        let mut io = Cursor::new(Vec::new());
+        MsgCodec::new(LIMITS, MsgFormat::default(), Egress::default())
+            .write_response(&MsgProtocol(), &mut io, Response::Pong)
+            .await?;
+        let response_len = io.get_ref().len();
+        io.set_position(0);
+        let _ = codec.read_response(&MsgProtocol(), &mut io).await?;
+
+        assert_eq!(
+            tracker.progress(),
+            TransferProgress {
+                request_bytes_sent: request_len,
+                response_bytes_received: response_len,
+            }
+        );
+        assert!(!tracker.progress().nothing_sent());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,83 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use serde::{de::DeserializeOwned, Serialize};
+use std::io;
+
+/// The serialization format of the `Request`/`Response` messages, see
+/// `NetworkConfig::msg_format`.
+///
+/// Every message starts with a byte tagging its format, followed by the encoded message, so
+/// that a node reads the messages in all the formats whatever the one it writes. Nodes
+/// configured with different formats thus interoperate, each of them answering the requests
+/// in its own format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MsgFormat {
+    /// Bincode, the most compact and the fastest to encode, hence the default.
+    #[default]
+    Bincode,
+    /// JSON, readable when debugging.
+    Json,
+    /// CBOR, for interoperability with other implementations.
+    Cbor,
+    /// MessagePack, compact while self-describing.
+    MessagePack,
+}
+
+impl MsgFormat {
+    /// All the formats, which can all be read.
+    pub const ALL: [Self; 4] = [Self::Bincode, Self::Json, Self::Cbor, Self::MessagePack];
+
+    // The byte tagging the messages of this format.
+    fn tag(self) -> u8 {
+        match self {
+            Self::Bincode => 0,
+            Self::Json => 1,
+            Self::Cbor => 2,
+            Self::MessagePack => 3,
+        }
+    }
+
+    fn from_tag(tag: u8) -> io::Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.tag() == tag)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unknown message format {tag}"),
+                )
+            })
+    }
+
+    /// Encodes the message in this format, behind the byte tagging the format.
+    pub(crate) fn encode<T: Serialize>(self, data: &T) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![self.tag()];
+        match self {
+            Self::Bincode => bincode::serialize_into(&mut bytes, data).map_err(io::Error::other)?,
+            Self::Json => serde_json::to_writer(&mut bytes, data)?,
+            Self::Cbor => ciborium::ser::into_writer(data, &mut bytes)
+                .map_err(|e| io::Error::other(e.to_string()))?,
+            Self::MessagePack => rmp_serde::encode::write(&mut bytes, data)
+                .map_err(|e| io::Error::other(e.to_string()))?,
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes a message encoded by `MsgFormat::encode`, in whichever format it is tagged with.
+    pub(crate) fn decode<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+        let (tag, encoded) = bytes.split_first().ok_or(io::ErrorKind::UnexpectedEof)?;
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        match Self::from_tag(*tag)? {
+            Self::Bincode => bincode::deserialize(encoded).map_err(|e| invalid(e.to_string())),
+            Self::Json => serde_json::from_slice(encoded).map_err(|e| invalid(e.to_string())),
+            Self::Cbor => ciborium::de::from_reader(encoded).map_err(|e| invalid(e.to_string())),
+            Self::MessagePack => rmp_serde::from_slice(encoded).map_err(|e| invalid(e.to_string())),
+        }
+    }
+}
@@ -7,68 +7,270 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 mod codec;
-pub(crate) use codec::{MsgCodec, MsgProtocol};
-pub use codec::{Request, Response};
+mod format;
+mod progress;
+pub(crate) use codec::{
+    encoded_len, FrameTooLarge, MAX_MSG_SIZE, MSG_PROTOCOL_NAME, RAW_PROTOCOL_NAME,
+};
+pub(crate) use codec::{MsgCodec, MsgProtocol, MsgRequest, RawCodec, RawProtocol};
+pub use codec::{RawResponse, Request, Response};
+pub use format::MsgFormat;
+pub use progress::TransferProgress;
+pub(crate) use progress::TransferTracker;
 
-use crate::network::{error::Error, NetworkEvent, NetworkSwarmLoop};
-use futures::prelude::*;
-use libp2p::request_response::{self, Message};
-use tracing::{trace, warn};
+use crate::network::{
+    error::Error,
+    inbound::InboundStream,
+    metrics::Metrics,
+    testing::{Direction, WireMessage},
+    NetworkEvent, NetworkSwarmLoop, ResponseHandle,
+};
+use libp2p::{
+    request_response::{self, Message, ResponseChannel},
+    PeerId,
+};
+use std::time::{Duration, Instant};
+use tracing::{trace, warn, Span};
+
+/// The minimum interval between two `Request::Ping` answered for the same peer.
+const PING_MIN_INTERVAL: Duration = Duration::from_secs(1);
 
 impl NetworkSwarmLoop {
     /// Forwards `Request` to the upper layers using `Sender<NetworkEvent>`. Sends `Response` to the peers
-    pub async fn handle_msg(
+    pub(crate) async fn handle_msg(
         &mut self,
-        event: request_response::Event<Request, Response>,
+        event: request_response::Event<MsgRequest, Response>,
     ) -> Result<(), Error> {
+        if let request_response::Event::Message { peer, message } = &event {
+            self.record_message(*peer, Direction::Inbound, || match message {
+                Message::Request { request, .. } => WireMessage::Request(request.request.clone()),
+                Message::Response { response, .. } => WireMessage::Response(response.clone()),
+            });
+        }
         match event {
-            request_response::Event::Message { message, .. } => match message {
+            request_response::Event::Message {
+                peer,
+                message: Message::Request { request_id, .. },
+            } if !self.protocol_enabled(MSG_PROTOCOL_NAME) => {
+                trace!("Dropping request {request_id:?} from {peer:?}, {MSG_PROTOCOL_NAME} being disabled");
+            }
+            request_response::Event::Message { peer, message } => match message {
                 Message::Request {
-                    request,
+                    request:
+                        MsgRequest {
+                            request: Request::Ping,
+                            ..
+                        },
+                    channel,
+                    ..
+                } => self.answer_ping(peer, channel),
+                Message::Request {
+                    request: MsgRequest { request, .. },
                     channel,
                     request_id,
                     ..
                 } => {
                     trace!("Received request with id: {request_id:?}, req: {request:?}");
-                    self.event_sender
-                        .send(NetworkEvent::RequestReceived {
-                            req: request,
-                            channel,
-                        })
+                    Metrics::inc(&self.metrics.requests_received);
+                    if self.shedding_load {
+                        self.record_message(peer, Direction::Outbound, || {
+                            WireMessage::Response(Response::Overloaded)
+                        });
+                        let _ = self
+                            .swarm
+                            .behaviour_mut()
+                            .request_response
+                            .send_response(channel, Response::Overloaded);
+                        return Ok(());
+                    }
+                    if !self
+                        .open_inbound_stream(peer, InboundStream::Msg(request_id))
                         .await?
+                    {
+                        return Ok(());
+                    }
+                    let endpoints = self.peer_endpoints.get(&peer).cloned().unwrap_or_default();
+                    self.send_event(NetworkEvent::RequestReceived {
+                        req: request,
+                        channel: ResponseHandle::new(peer, channel),
+                        endpoints,
+                    })
+                    .await?
                 }
                 Message::Response {
                     request_id,
                     response,
                 } => {
+                    let step = self.op_traces.request_completed(&request_id);
+                    let _entered = step.as_ref().map(Span::enter);
                     trace!("Got response for id: {request_id:?}, res: {response:?} ");
+                    Metrics::inc(&self.metrics.responses_received);
+                    self.record_request_latency(&request_id);
+                    let _ = self.request_transfers.remove(&request_id);
+                    let _ = self.request_deadlines.remove(&request_id);
+                    for waiter in self.take_coalesced_waiters(&request_id) {
+                        let _ = waiter.send(Ok(response.clone()));
+                    }
+                    match self.pending_requests.remove(&request_id) {
+                        Some(sender) => {
+                            let _ = sender.send(Ok(response));
+                        }
+                        // The request is no longer waited for once timed out.
+                        None => trace!("Dropping the late response to {request_id:?}"),
+                    }
+                }
+            },
+            request_response::Event::OutboundFailure {
+                request_id, error, ..
+            } => {
+                Metrics::inc(&self.metrics.request_failures);
+                let step = self.op_traces.request_completed(&request_id);
+                let _entered = step.as_ref().map(Span::enter);
+                let _ = self.request_timings.remove(&request_id);
+                let progress = self
+                    .request_transfers
+                    .remove(&request_id)
+                    .map(|tracker| tracker.progress())
+                    .unwrap_or_default();
+                let _ = self.request_deadlines.remove(&request_id);
+                for waiter in self.take_coalesced_waiters(&request_id) {
+                    let _ = waiter.send(Err(Error::RequestFailed {
+                        error: error.clone(),
+                        progress,
+                    }));
+                }
+                match self.pending_requests.remove(&request_id) {
+                    Some(sender) => {
+                        let _ = sender.send(Err(Error::RequestFailed { error, progress }));
+                    }
+                    None => trace!("Dropping the late failure of {request_id:?}: {error:?}"),
+                }
+            }
+            request_response::Event::InboundFailure {
+                peer,
+                request_id,
+                error,
+            } => {
+                Metrics::inc(&self.metrics.request_failures);
+                self.close_inbound_stream(peer, InboundStream::Msg(request_id));
+                warn!("RequestResponse: InboundFailure for request_id: {request_id:?} and peer: {peer:?}, with error: {error:?}");
+            }
+            request_response::Event::ResponseSent { peer, request_id } => {
+                self.close_inbound_stream(peer, InboundStream::Msg(request_id));
+                trace!("ResponseSent for request_id: {request_id:?} and peer: {peer:?}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Forwards raw requests to the upper layers and completes the pending raw requests.
+    pub(crate) async fn handle_raw_msg(
+        &mut self,
+        event: request_response::Event<Vec<u8>, RawResponse>,
+    ) -> Result<(), Error> {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message: Message::Request { request_id, .. },
+            } if !self.protocol_enabled(RAW_PROTOCOL_NAME) => {
+                trace!("Dropping raw request {request_id:?} from {peer:?}, {RAW_PROTOCOL_NAME} being disabled");
+            }
+            request_response::Event::Message { peer, message } => match message {
+                Message::Request {
+                    request,
+                    channel,
+                    request_id,
+                    ..
+                } => {
+                    trace!("Received raw request with id: {request_id:?}");
+                    Metrics::inc(&self.metrics.requests_received);
+                    if self.shedding_load {
+                        let _ = self
+                            .swarm
+                            .behaviour_mut()
+                            .raw_request_response
+                            .send_response(channel, RawResponse::Overloaded);
+                        return Ok(());
+                    }
+                    if !self
+                        .open_inbound_stream(peer, InboundStream::Raw(request_id))
+                        .await?
+                    {
+                        return Ok(());
+                    }
+                    self.send_event(NetworkEvent::RawRequestReceived {
+                        bytes: request,
+                        channel: ResponseHandle::new(peer, channel),
+                    })
+                    .await?
+                }
+                Message::Response {
+                    request_id,
+                    response,
+                } => {
+                    trace!("Got raw response for id: {request_id:?}");
+                    Metrics::inc(&self.metrics.responses_received);
+                    let response = match response {
+                        RawResponse::Bytes(bytes) => Ok(bytes),
+                        RawResponse::Overloaded => Err(Error::Overloaded),
+                    };
                     let _ = self
-                        .pending_requests
+                        .pending_raw_requests
                         .remove(&request_id)
-                        .ok_or(Error::Other("Request to still be pending".to_string()))?
-                        .send(Ok(response));
+                        .ok_or(Error::Other("Raw request to still be pending".to_string()))?
+                        .send(response);
                 }
             },
             request_response::Event::OutboundFailure {
                 request_id, error, ..
             } => {
+                Metrics::inc(&self.metrics.request_failures);
                 let _ = self
-                    .pending_requests
+                    .pending_raw_requests
                     .remove(&request_id)
-                    .ok_or(Error::Other("Request to still be pending.".to_string()))?
-                    .send(Err(error.into()));
+                    .ok_or(Error::Other("Raw request to still be pending.".to_string()))?
+                    .send(Err(Error::OutboundError(error)));
             }
             request_response::Event::InboundFailure {
                 peer,
                 request_id,
                 error,
             } => {
-                warn!("RequestResponse: InboundFailure for request_id: {request_id:?} and peer: {peer:?}, with error: {error:?}");
+                Metrics::inc(&self.metrics.request_failures);
+                self.close_inbound_stream(peer, InboundStream::Raw(request_id));
+                warn!("RawRequestResponse: InboundFailure for request_id: {request_id:?} and peer: {peer:?}, with error: {error:?}");
             }
             request_response::Event::ResponseSent { peer, request_id } => {
-                trace!("ResponseSent for request_id: {request_id:?} and peer: {peer:?}");
+                self.close_inbound_stream(peer, InboundStream::Raw(request_id));
+                trace!("Raw ResponseSent for request_id: {request_id:?} and peer: {peer:?}");
             }
         }
         Ok(())
     }
+
+    // Answers a liveness probe, at most once per `PING_MIN_INTERVAL` for a given peer; the
+    // probes received more often are dropped. The `Pong` being no larger than the `Ping`, the
+    // probes can't be used to amplify traffic.
+    fn answer_ping(&mut self, peer: PeerId, channel: ResponseChannel<Response>) {
+        let now = Instant::now();
+        if let Some(last_ping) = self.last_ping.get(&peer) {
+            if now.duration_since(*last_ping) < PING_MIN_INTERVAL {
+                trace!("Dropping ping from {peer:?}, received too soon after the previous one");
+                return;
+            }
+        }
+        let _ = self.last_ping.insert(peer, now);
+        self.record_message(peer, Direction::Outbound, || {
+            WireMessage::Response(Response::Pong)
+        });
+        if self
+            .swarm
+            .behaviour_mut()
+            .request_response
+            .send_response(channel, Response::Pong)
+            .is_err()
+        {
+            warn!("Failed to answer the ping from {peer:?}, the connection is closed");
+        }
+    }
 }
@@ -0,0 +1,118 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use futures::{AsyncRead, AsyncWrite};
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+/// How far the transfer of a request and of its response got, as reported by
+/// `Error::RequestFailed`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferProgress {
+    /// The number of bytes of the request written to the stream, i.e. accepted by it: these
+    /// may still be buffered, not sent yet, hence the count errs on the side of too many.
+    pub request_bytes_sent: usize,
+    /// The number of bytes of the response read from the stream.
+    pub response_bytes_received: usize,
+}
+
+impl TransferProgress {
+    /// Whether nothing of the request has been written to the stream, in which case the peer
+    /// can't have acted on it and retrying is safe. Otherwise the peer may have handled the
+    /// request, even if no response came back, so retrying is only safe for the idempotent
+    /// requests.
+    pub fn nothing_sent(&self) -> bool {
+        self.request_bytes_sent == 0
+    }
+}
+
+/// Tracks the transfer of an outbound request, shared between the event loop and the codec
+/// writing the request and reading its response.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TransferTracker(Arc<Counters>);
+
+#[derive(Debug, Default)]
+struct Counters {
+    request_bytes_sent: AtomicUsize,
+    response_bytes_received: AtomicUsize,
+}
+
+impl TransferTracker {
+    /// Returns the progress of the transfer so far.
+    pub(crate) fn progress(&self) -> TransferProgress {
+        TransferProgress {
+            request_bytes_sent: self.0.request_bytes_sent.load(Ordering::Relaxed),
+            response_bytes_received: self.0.response_bytes_received.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Wraps the stream the request is written to, counting the bytes it accepts, flushed or not.
+    pub(crate) fn request_stream<'a, IO>(&'a self, io: &'a mut IO) -> Counted<'a, IO> {
+        Counted {
+            io,
+            count: &self.0.request_bytes_sent,
+        }
+    }
+
+    /// Wraps the stream the response is read from, counting the bytes read.
+    pub(crate) fn response_stream<'a, IO>(&'a self, io: &'a mut IO) -> Counted<'a, IO> {
+        Counted {
+            io,
+            count: &self.0.response_bytes_received,
+        }
+    }
+}
+
+/// A stream counting the bytes going through it.
+pub(crate) struct Counted<'a, IO> {
+    io: &'a mut IO,
+    count: &'a AtomicUsize,
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for Counted<'_, IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut *self.io).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = &poll {
+            let _ = self.count.fetch_add(*written, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.io).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.io).poll_close(cx)
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for Counted<'_, IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut *self.io).poll_read(cx, buf);
+        if let Poll::Ready(Ok(read)) = &poll {
+            let _ = self.count.fetch_add(*read, Ordering::Relaxed);
+        }
+        poll
+    }
+}
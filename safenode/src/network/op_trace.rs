@@ -0,0 +1,217 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use libp2p::{kad::QueryId, request_response::RequestId, PeerId};
+use std::collections::HashMap;
+use tracing::{info_span, Span};
+
+/// The span of an operation started through `Network`, following it into the event loop.
+///
+/// With the `op-tracing` feature, the operations run within a span of the caller, e.g. through
+/// `tracing::Instrument`, are tagged for tracing: each of them gets a span, child of the
+/// caller's one, with a child span per step of the operation (`queue` until the event loop
+/// handles the command, `dial` when not connected to the peer yet, then `response` or
+/// `query`). The spans live as long as their step and are entered while the event loop works
+/// on it, the busy and idle times reported when they close telling where the time went, from
+/// which the flamegraph can be rendered. The other operations, and all of them without the
+/// feature, are not traced.
+#[derive(Debug, Default)]
+pub(crate) struct OpSpan {
+    op: Option<Span>,
+    queue: Option<Span>,
+}
+
+impl OpSpan {
+    /// Starts tracing the operation, if tagged, with the span built by `make`.
+    pub(super) fn new(make: impl FnOnce() -> Span) -> Self {
+        #[cfg(feature = "op-tracing")]
+        if !Span::current().is_disabled() {
+            let op = make();
+            let queue = info_span!(parent: &op, "queue");
+            return Self {
+                op: Some(op),
+                queue: Some(queue),
+            };
+        }
+        #[cfg(not(feature = "op-tracing"))]
+        let _ = make;
+        Self::default()
+    }
+
+    /// Ends the `queue` step, the event loop now handling the command.
+    pub(super) fn dequeued(&mut self) {
+        self.queue = None;
+    }
+
+    /// Runs `f` within the span of the operation, if traced.
+    pub(super) fn in_scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        match &self.op {
+            Some(op) => op.in_scope(f),
+            None => f(),
+        }
+    }
+
+    fn step(&self, make: impl FnOnce(&Span) -> Span) -> Option<Span> {
+        self.op.as_ref().map(make)
+    }
+}
+
+/// The steps of the traced operations in progress within the event loop.
+#[derive(Default)]
+pub(super) struct OpTraces {
+    requests: HashMap<RequestId, Span>,
+    queries: HashMap<QueryId, Span>,
+    dials: HashMap<PeerId, Vec<Span>>,
+}
+
+impl OpTraces {
+    /// Traces the request sent to the peer, and the dial to it unless `connected`.
+    pub(super) fn request_sent(
+        &mut self,
+        request_id: RequestId,
+        peer: PeerId,
+        connected: bool,
+        op: &OpSpan,
+    ) {
+        if !connected {
+            if let Some(dial) = op.step(|op| info_span!(parent: op, "dial", %peer)) {
+                self.dials.entry(peer).or_default().push(dial);
+            }
+        }
+        if let Some(response) = op.step(|op| info_span!(parent: op, "response")) {
+            let _ = self.requests.insert(request_id, response);
+        }
+    }
+
+    /// Ends the tracing of the request, on its response or failure, returning the span of the
+    /// step for its completion to be handled within.
+    pub(super) fn request_completed(&mut self, request_id: &RequestId) -> Option<Span> {
+        self.requests.remove(request_id)
+    }
+
+    /// Traces the Kademlia query.
+    pub(super) fn query_started(&mut self, query_id: QueryId, op: &OpSpan) {
+        if let Some(query) = op.step(|op| info_span!(parent: op, "query")) {
+            let _ = self.queries.insert(query_id, query);
+        }
+    }
+
+    /// Ends the tracing of the query, on its outcome, returning the span of the step for its
+    /// completion to be handled within.
+    pub(super) fn query_completed(&mut self, query_id: &QueryId) -> Option<Span> {
+        self.queries.remove(query_id)
+    }
+
+    /// Ends the tracing of the dials to the peer, on their outcome.
+    pub(super) fn dial_completed(&mut self, peer: &PeerId) {
+        let _ = self.dials.remove(peer);
+    }
+
+    /// Abandons the tracing of all the operations.
+    pub(super) fn clear(&mut self) {
+        self.requests.clear();
+        self.queries.clear();
+        self.dials.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OpSpan, OpTraces};
+    use libp2p::{
+        kad::{store::MemoryStore, Kademlia, RecordKey},
+        PeerId,
+    };
+    use std::sync::{Arc, Mutex, PoisonError};
+    use tracing::{
+        info_span,
+        span::{Attributes, Id},
+        Subscriber,
+    };
+    use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+    // Records when the spans are created, entered and closed, by name.
+    #[derive(Clone, Default)]
+    struct SpanRecorder(Arc<Mutex<Vec<String>>>);
+
+    impl SpanRecorder {
+        fn push<S>(&self, what: &str, id: &Id, ctx: &Context<'_, S>)
+        where
+            S: Subscriber + for<'a> LookupSpan<'a>,
+        {
+            let name = ctx.span(id).map(|span| span.name()).unwrap_or_default();
+            self.0
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(format!("{what} {name}"));
+        }
+
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+        }
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanRecorder {
+        fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            self.push("new", id, &ctx);
+        }
+
+        fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+            self.push("enter", id, &ctx);
+        }
+
+        fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+            self.push("close", &id, &ctx);
+        }
+    }
+
+    #[test]
+    fn the_steps_are_entered_while_handled_and_completed_once() {
+        let recorder = SpanRecorder::default();
+        let subscriber = tracing_subscriber::registry().with(recorder.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let peer = PeerId::random();
+            let mut kademlia = Kademlia::new(peer, MemoryStore::new(peer));
+            let mut traces = OpTraces::default();
+            let op = info_span!("get_data_providers");
+            let mut op = OpSpan {
+                queue: Some(info_span!(parent: &op, "queue")),
+                op: Some(op),
+            };
+            op.dequeued();
+            let query_id = op.in_scope(|| kademlia.get_providers(RecordKey::new(&[1; 32])));
+            traces.query_started(query_id, &op);
+            assert_eq!(
+                recorder.take(),
+                [
+                    "new get_data_providers",
+                    "new queue",
+                    "close queue",
+                    "enter get_data_providers",
+                    "new query",
+                ]
+            );
+
+            let query = traces
+                .query_completed(&query_id)
+                .expect("the query to be traced");
+            drop(query.enter());
+            drop(query);
+            assert!(traces.query_completed(&query_id).is_none());
+            assert_eq!(recorder.take(), ["enter query", "close query"]);
+
+            // The operations not tagged for tracing have no span.
+            let untraced = OpSpan::default();
+            let query_id = untraced.in_scope(|| kademlia.get_providers(RecordKey::new(&[2; 32])));
+            traces.query_started(query_id, &untraced);
+            assert!(traces.query_completed(&query_id).is_none());
+            drop(op);
+            assert_eq!(recorder.take(), ["close get_data_providers"]);
+        });
+    }
+}
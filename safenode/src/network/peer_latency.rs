@@ -0,0 +1,146 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{config::LatencyBuckets, NetworkSwarmLoop, ProviderSelectionStrategy};
+use libp2p::{request_response::RequestId, PeerId};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// The weight of a new round trip sample in the smoothed latency of a peer.
+const LATENCY_SMOOTHING: f64 = 0.25;
+
+impl NetworkSwarmLoop {
+    /// Starts timing the request just sent, if the peer is already connected: the round trip
+    /// of a request that has to dial first measures the dial rather than the peer's latency.
+    pub(super) fn start_request_timing(
+        &mut self,
+        request_id: RequestId,
+        peer: PeerId,
+        connected: bool,
+    ) {
+        if connected {
+            let _ = self
+                .request_timings
+                .insert(request_id, (peer, Instant::now()));
+        }
+    }
+
+    /// Folds the round trip of the request into the smoothed latency of its peer, on response.
+    pub(super) fn record_request_latency(&mut self, request_id: &RequestId) {
+        if let Some((peer, sent_at)) = self.request_timings.remove(request_id) {
+            let sample = sent_at.elapsed();
+            let latency = match self.peer_latencies.get(&peer) {
+                Some(latency) => {
+                    latency.mul_f64(1.0 - LATENCY_SMOOTHING) + sample.mul_f64(LATENCY_SMOOTHING)
+                }
+                None => sample,
+            };
+            let _ = self.peer_latencies.insert(peer, latency);
+        }
+    }
+
+    /// Selects up to `count` of the peers as per the `NetworkConfig::provider_selection`.
+    pub(super) fn select_peers(&self, peers: Vec<PeerId>, count: usize) -> Vec<PeerId> {
+        match &self.provider_selection {
+            ProviderSelectionStrategy::Unordered => peers.into_iter().take(count).collect(),
+            ProviderSelectionStrategy::LatencyBucketed(buckets) => {
+                select_by_latency(peers, &self.peer_latencies, buckets, count)
+            }
+        }
+    }
+}
+
+// Returns the index of the bucket the latency falls into, the peers of unknown latency being
+// put past the last one.
+fn bucket_of(latency: Option<&Duration>, buckets: &LatencyBuckets) -> usize {
+    match latency {
+        Some(latency) => buckets
+            .bounds()
+            .iter()
+            .position(|bound| latency <= bound)
+            .unwrap_or(buckets.bounds().len()),
+        None => buckets.bounds().len() + 1,
+    }
+}
+
+// Takes the nearest peers first, keeping `distant_fraction` of the `count` for the peers
+// outside the nearest bucket, picked from the farthest ones. The slots left unused by the
+// missing distant peers go to the nearest ones, and conversely.
+fn select_by_latency(
+    mut peers: Vec<PeerId>,
+    latencies: &HashMap<PeerId, Duration>,
+    buckets: &LatencyBuckets,
+    count: usize,
+) -> Vec<PeerId> {
+    peers.sort_by_key(|peer| {
+        let latency = latencies.get(peer);
+        (bucket_of(latency, buckets), latency.copied())
+    });
+    let nearest_bucket = match peers.first() {
+        Some(peer) => bucket_of(latencies.get(peer), buckets),
+        None => return peers,
+    };
+    let in_nearest_bucket = peers
+        .iter()
+        .take_while(|peer| bucket_of(latencies.get(*peer), buckets) == nearest_bucket)
+        .count();
+
+    let distant_slots = (count as f64 * buckets.distant_fraction()).floor() as usize;
+    let distant = distant_slots.min(peers.len() - in_nearest_bucket);
+    let nearest = count.saturating_sub(distant).min(peers.len() - distant);
+
+    let mut selected: Vec<PeerId> = peers.iter().take(nearest).copied().collect();
+    selected.extend(peers.iter().rev().take(distant));
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::select_by_latency;
+    use crate::network::{error::Result, LatencyBuckets};
+    use libp2p::PeerId;
+    use std::{collections::HashMap, time::Duration};
+
+    #[test]
+    fn latency_bucketed_selection_keeps_a_fraction_of_distant_peers() -> Result<()> {
+        let buckets = LatencyBuckets::new(
+            vec![Duration::from_millis(20), Duration::from_millis(200)],
+            0.25,
+        )?;
+        let near: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+        let far = PeerId::random();
+        let unknown = PeerId::random();
+        let mut latencies: HashMap<PeerId, Duration> = near
+            .iter()
+            .enumerate()
+            .map(|(i, peer)| (*peer, Duration::from_millis(5 + i as u64)))
+            .collect();
+        let _ = latencies.insert(far, Duration::from_millis(150));
+
+        let mut peers = vec![unknown, far];
+        peers.extend(near.iter().rev());
+
+        // One slot out of four goes to the farthest peer, the others to the nearest ones.
+        let selected = select_by_latency(peers.clone(), &latencies, &buckets, 4);
+        assert_eq!(selected, vec![near[0], near[1], near[2], unknown]);
+
+        // Too few slots to keep a distant one.
+        let selected = select_by_latency(peers.clone(), &latencies, &buckets, 3);
+        assert_eq!(selected, near[..3].to_vec());
+
+        // All the peers, nearest first, once there are enough slots.
+        let selected = select_by_latency(peers, &latencies, &buckets, 8);
+        assert_eq!(
+            selected,
+            vec![near[0], near[1], near[2], near[3], unknown, far]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    error::{Error, Result},
+    msg::{MSG_PROTOCOL_NAME, RAW_PROTOCOL_NAME},
+    NetworkEvent, NetworkSwarmLoop,
+};
+use libp2p::{
+    core::Endpoint,
+    identify,
+    swarm::{
+        ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, PollParameters, THandler,
+        THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+use std::{
+    collections::HashSet,
+    task::{Context, Poll},
+};
+use tracing::info;
+
+/// The request protocols that can be enabled and disabled at runtime.
+const TOGGLEABLE_PROTOCOLS: [&str; 2] = [MSG_PROTOCOL_NAME, RAW_PROTOCOL_NAME];
+
+impl NetworkSwarmLoop {
+    /// Whether the given request protocol is enabled, see `Network::disable_protocol`.
+    pub(super) fn protocol_enabled(&self, protocol: &'static str) -> bool {
+        !self.disabled_protocols.contains(protocol)
+    }
+
+    /// The request protocols currently enabled.
+    pub(super) fn active_protocols(&self) -> Vec<String> {
+        TOGGLEABLE_PROTOCOLS
+            .iter()
+            .filter(|protocol| self.protocol_enabled(protocol))
+            .map(|protocol| protocol.to_string())
+            .collect()
+    }
+
+    /// Enables or disables the request protocol called `name`, emitting a
+    /// `NetworkEvent::ProtocolsChanged` if that changed the active protocols.
+    pub(super) async fn set_protocol_enabled(&mut self, name: &str, enabled: bool) -> Result<()> {
+        let protocol = TOGGLEABLE_PROTOCOLS
+            .into_iter()
+            .find(|protocol| *protocol == name)
+            .ok_or_else(|| Error::UnknownProtocol(name.to_string()))?;
+        let changed = if enabled {
+            self.disabled_protocols.remove(protocol)
+        } else {
+            self.disabled_protocols.insert(protocol)
+        };
+        if changed {
+            self.swarm.behaviour_mut().identify.hidden = self.disabled_protocols.clone();
+            // For the connected peers to learn about the change.
+            let peers: Vec<PeerId> = self.swarm.connected_peers().copied().collect();
+            self.swarm.behaviour_mut().identify.inner.push(peers);
+            let active = self.active_protocols();
+            info!("Protocol {protocol} enabled: {enabled}, active protocols: {active:?}");
+            self.send_event(NetworkEvent::ProtocolsChanged { active })
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// The identify protocol, leaving the disabled request protocols out of those it advertises.
+///
+/// The protocols identify lists are those of the connection handlers, which still accept the
+/// disabled ones: these are hidden from the `PollParameters` identify takes them from.
+pub(crate) struct FilteredIdentify {
+    pub(super) inner: identify::Behaviour,
+    hidden: HashSet<&'static str>,
+}
+
+impl FilteredIdentify {
+    pub(super) fn new(inner: identify::Behaviour) -> Self {
+        Self {
+            inner,
+            hidden: HashSet::new(),
+        }
+    }
+}
+
+impl NetworkBehaviour for FilteredIdentify {
+    type ConnectionHandler = THandler<identify::Behaviour>;
+    type OutEvent = identify::Event;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        self.inner
+            .handle_pending_inbound_connection(connection_id, local_addr, remote_addr)
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.inner.handle_established_inbound_connection(
+            connection_id,
+            peer,
+            local_addr,
+            remote_addr,
+        )
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        addresses: &[Multiaddr],
+        effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        self.inner.handle_pending_outbound_connection(
+            connection_id,
+            maybe_peer,
+            addresses,
+            effective_role,
+        )
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.inner
+            .handle_established_outbound_connection(connection_id, peer, addr, role_override)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        self.inner.on_swarm_event(event)
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        self.inner
+            .on_connection_handler_event(peer_id, connection_id, event)
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        params: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::OutEvent, THandlerInEvent<Self>>> {
+        let mut params = FilteredParams {
+            inner: params,
+            hidden: &self.hidden,
+        };
+        self.inner.poll(cx, &mut params)
+    }
+}
+
+// The `PollParameters` of the swarm, without the hidden protocols.
+struct FilteredParams<'a, P> {
+    inner: &'a P,
+    hidden: &'a HashSet<&'static str>,
+}
+
+impl<P: PollParameters> PollParameters for FilteredParams<'_, P> {
+    type SupportedProtocolsIter = std::vec::IntoIter<Vec<u8>>;
+    type ListenedAddressesIter = P::ListenedAddressesIter;
+    type ExternalAddressesIter = P::ExternalAddressesIter;
+
+    fn supported_protocols(&self) -> Self::SupportedProtocolsIter {
+        self.inner
+            .supported_protocols()
+            .filter(|protocol| {
+                !self
+                    .hidden
+                    .iter()
+                    .any(|hidden| hidden.as_bytes() == protocol.as_slice())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[allow(deprecated)]
+    fn listened_addresses(&self) -> Self::ListenedAddressesIter {
+        self.inner.listened_addresses()
+    }
+
+    #[allow(deprecated)]
+    fn external_addresses(&self) -> Self::ExternalAddressesIter {
+        self.inner.external_addresses()
+    }
+
+    #[allow(deprecated)]
+    fn local_peer_id(&self) -> &PeerId {
+        self.inner.local_peer_id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        error::Result,
+        msg::RAW_PROTOCOL_NAME,
+        testing::{isolated_config, listen_addr},
+        NetworkSwarmLoop,
+    };
+    use async_std::{future::timeout, task};
+    use futures::{channel::mpsc, StreamExt};
+    use libp2p::identify;
+    use std::time::Duration;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[async_std::test]
+    async fn a_disabled_protocol_is_no_longer_advertised() -> Result<()> {
+        let (mut remote, remote_events, remote_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _remote_loop = task::spawn(remote_loop.run());
+        let _remote_events = task::spawn(remote_events.for_each(|_| async {}));
+        let remote_addr = listen_addr(&mut remote).await?;
+
+        let (advertised, mut identified) = mpsc::unbounded();
+        let (mut network, events, event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let event_loop = event_loop.with_raw_event_tap(move |event| {
+            if let Some(identify::Event::Received { info, .. }) = event.identify() {
+                let _ = advertised.unbounded_send(info.protocols.clone());
+            }
+        });
+        let _event_loop = task::spawn(event_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+
+        timeout(
+            TEST_TIMEOUT,
+            network.dial(remote.local_peer_id(), remote_addr),
+        )
+        .await
+        .expect("the dial to complete")?;
+        let protocols = timeout(TEST_TIMEOUT, identified.next())
+            .await
+            .expect("the remote to identify")
+            .unwrap_or_default();
+        assert!(protocols.iter().any(|p| p == RAW_PROTOCOL_NAME));
+
+        // The update is pushed to the connected peers.
+        remote.disable_protocol(RAW_PROTOCOL_NAME).await?;
+        let protocols = timeout(TEST_TIMEOUT, identified.next())
+            .await
+            .expect("the remote to push its update")
+            .unwrap_or_default();
+        assert!(!protocols.is_empty());
+        assert!(!protocols.iter().any(|p| p == RAW_PROTOCOL_NAME));
+
+        remote.enable_protocol(RAW_PROTOCOL_NAME).await?;
+        let protocols = timeout(TEST_TIMEOUT, identified.next())
+            .await
+            .expect("the remote to push its update")
+            .unwrap_or_default();
+        assert!(protocols.iter().any(|p| p == RAW_PROTOCOL_NAME));
+        Ok(())
+    }
+}
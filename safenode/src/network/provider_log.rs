@@ -0,0 +1,180 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{error::Result, NetworkSwarmLoop};
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+use xor_name::XorName;
+
+/// An append-only log of the keys the node provides, one hex encoded `XorName` per line, see
+/// `NetworkConfig::provider_log`.
+///
+/// Each key is synced to disk before it is advertised, so a crash never loses a key the node
+/// started to provide. A line torn by a crash is skipped when the log is read back. The log is
+/// compacted on open, and rewritten once a key is no longer provided.
+pub(super) struct ProviderLog {
+    path: PathBuf,
+    file: File,
+    keys: HashSet<XorName>,
+}
+
+impl ProviderLog {
+    /// Opens the log at `path`, creating it if needed, and returns the keys already logged.
+    pub(super) fn open(path: &Path) -> Result<(Self, Vec<XorName>)> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut keys = HashSet::new();
+        let mut logged = Vec::new();
+        for line in contents.lines() {
+            match parse_key(line) {
+                Some(key) => {
+                    if keys.insert(key) {
+                        logged.push(key);
+                    }
+                }
+                None => warn!("Skipping the invalid provider log entry {line:?}"),
+            }
+        }
+        // Drops the duplicate and invalid lines, e.g. one torn by a crash.
+        let compacted = encode_keys(&logged);
+        if compacted != contents {
+            write_atomically(path, &compacted)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let log = Self {
+            path: path.to_path_buf(),
+            file,
+            keys,
+        };
+        Ok((log, logged))
+    }
+
+    /// Logs `key`, if not already logged.
+    pub(super) fn append(&mut self, key: XorName) -> Result<()> {
+        if self.keys.contains(&key) {
+            return Ok(());
+        }
+        self.file.write_all(encode_keys(&[key]).as_bytes())?;
+        self.file.sync_data()?;
+        let _ = self.keys.insert(key);
+        Ok(())
+    }
+
+    /// Removes `key` from the log, if logged, rewriting it.
+    pub(super) fn remove(&mut self, key: &XorName) -> Result<()> {
+        if !self.keys.remove(key) {
+            return Ok(());
+        }
+        let keys: Vec<XorName> = self.keys.iter().copied().collect();
+        write_atomically(&self.path, &encode_keys(&keys))?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+}
+
+fn encode_keys(keys: &[XorName]) -> String {
+    keys.iter()
+        .map(|key| format!("{}\n", hex::encode(key.0)))
+        .collect()
+}
+
+// Replaces the log with `contents`, through a temporary file for a crash to leave either.
+fn write_atomically(path: &Path, contents: &str) -> Result<()> {
+    let mut temp_path = path.as_os_str().to_os_string();
+    temp_path.push(".tmp");
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+fn parse_key(line: &str) -> Option<XorName> {
+    let bytes = hex::decode(line.trim()).ok()?;
+    Some(XorName(bytes.try_into().ok()?))
+}
+
+impl NetworkSwarmLoop {
+    /// Logs `key` as provided, if a `ProviderLog` is configured.
+    pub(super) fn log_provided(&mut self, key: XorName) -> Result<()> {
+        match &mut self.provider_log {
+            Some(log) => log.append(key),
+            None => Ok(()),
+        }
+    }
+
+    /// Forgets about `key` in the `ProviderLog`, if configured, once no longer provided.
+    pub(super) fn unlog_provided(&mut self, key: &XorName) -> Result<()> {
+        match &mut self.provider_log {
+            Some(log) => log.remove(key),
+            None => Ok(()),
+        }
+    }
+
+    /// Advertises again the keys read back from the `ProviderLog`, through persistent stores as
+    /// the network may still be settling. Done once, as soon as the routing table gets a peer
+    /// or a bootstrap succeeds.
+    pub(super) async fn resume_providing(&mut self) -> Result<()> {
+        let keys = std::mem::take(&mut self.resumed_provided_keys);
+        if !keys.is_empty() {
+            info!("Resuming the advertisement of {} logged keys", keys.len());
+        }
+        for key in keys {
+            self.start_persistent_store(key).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProviderLog;
+    use crate::network::error::{Error, Result};
+    use assert_fs::TempDir;
+    use std::fs;
+    use xor_name::XorName;
+
+    #[test]
+    fn the_log_is_compacted_on_open_and_rewritten_on_removal() -> Result<()> {
+        let dir = TempDir::new().map_err(|err| Error::Other(err.to_string()))?;
+        let path = dir.path().join("provided");
+        let (first, second) = (XorName([1; 32]), XorName([2; 32]));
+        let first_line = hex::encode(first.0);
+        let second_line = hex::encode(second.0);
+        // A duplicate, then a line torn by a crash.
+        fs::write(
+            &path,
+            format!(
+                "{first_line}\n{second_line}\n{first_line}\n{}",
+                &first_line[..10]
+            ),
+        )?;
+
+        let (mut log, logged) = ProviderLog::open(&path)?;
+        assert_eq!(logged, vec![first, second]);
+        assert_eq!(
+            fs::read_to_string(&path)?,
+            format!("{first_line}\n{second_line}\n")
+        );
+
+        log.remove(&first)?;
+        log.append(XorName([3; 32]))?;
+        drop(log);
+        let (_log, logged) = ProviderLog::open(&path)?;
+        assert_eq!(logged, vec![second, XorName([3; 32])]);
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::NetworkSwarmLoop;
+use libp2p::kad::{self, QueryId};
+use std::time::Duration;
+
+/// The kind of a Kademlia query, see `QueryInfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryKind {
+    /// Populating the routing table, see `Network::bootstrap`
+    Bootstrap,
+    /// Looking up the peers closest to a key, e.g. to refresh the buckets
+    GetClosestPeers,
+    /// Looking up the providers of a key, see `Network::get_data_providers`
+    GetProviders,
+    /// Advertising the local node as a provider, see `Network::store_data`
+    AddProvider,
+    /// Storing a record on the peers closest to its key
+    PutRecord,
+    /// Looking up a record
+    GetRecord,
+}
+
+/// A Kademlia query in flight, see `Network::active_queries`.
+#[derive(Debug, Clone)]
+pub struct QueryInfo {
+    /// The id of the query
+    pub id: QueryId,
+    /// What the query is doing
+    pub kind: QueryKind,
+    /// How long the query has been running for; `None` until it contacts its first peer
+    pub running_for: Option<Duration>,
+    /// The number of requests to peers the query is still waiting on
+    pub pending_requests: u32,
+}
+
+impl NetworkSwarmLoop {
+    /// Lists the queries in flight, whether started through the `Network` API or internally,
+    /// e.g. by the bucket refresh or the periodic re-publishing of the records.
+    pub(super) fn active_queries(&mut self) -> Vec<QueryInfo> {
+        self.swarm
+            .behaviour_mut()
+            .kademlia
+            .iter_queries()
+            .map(|query| QueryInfo {
+                id: query.id(),
+                kind: match query.info() {
+                    kad::QueryInfo::Bootstrap { .. } => QueryKind::Bootstrap,
+                    kad::QueryInfo::GetClosestPeers { .. } => QueryKind::GetClosestPeers,
+                    kad::QueryInfo::GetProviders { .. } => QueryKind::GetProviders,
+                    kad::QueryInfo::AddProvider { .. } => QueryKind::AddProvider,
+                    kad::QueryInfo::PutRecord { .. } => QueryKind::PutRecord,
+                    kad::QueryInfo::GetRecord { .. } => QueryKind::GetRecord,
+                },
+                running_for: query.stats().duration(),
+                pending_requests: query.stats().num_pending(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{error::Result, event::NodeEvent, metrics::Metrics, NetworkEvent, NetworkSwarmLoop};
+use libp2p::{autonat, gossipsub, identify, kad::KademliaEvent, request_response, PeerId};
+use std::time::Instant;
+use tracing::info;
+
+impl NetworkSwarmLoop {
+    /// Records activity with the given peer, postponing its reaping.
+    pub(super) fn record_peer_activity(&mut self, peer: PeerId) {
+        if self.swarm.is_connected(&peer) {
+            let _ = self.peer_last_activity.insert(peer, Instant::now());
+        }
+    }
+
+    /// Records activity with the peer the event of a protocol is about, if any. The peers
+    /// evicted from the routing table are no longer considered explicitly dialed.
+    pub(super) fn on_behaviour_event(&mut self, event: &NodeEvent) {
+        if let NodeEvent::Kademlia(KademliaEvent::RoutingUpdated {
+            old_peer: Some(evicted),
+            ..
+        }) = event
+        {
+            let _ = self.explicit_peers.remove(evicted);
+        }
+        if let Some(peer) = event_peer(event) {
+            self.record_peer_activity(peer);
+        }
+    }
+
+    /// Forgets about the peer having been explicitly dialed, e.g. once disconnected from it or
+    /// removed from the routing table, for it to be reaped if it ever gets idle again.
+    pub(super) fn forget_explicit_peer(&mut self, peer: &PeerId) {
+        let _ = self.explicit_peers.remove(peer);
+    }
+
+    /// Disconnects from the peers that have been idle for longer than the configured
+    /// `NetworkConfig::idle_connection_timeout`. The peers we explicitly dialed or bootstrapped
+    /// from are never reaped.
+    pub(super) async fn reap_idle_peers(&mut self) -> Result<()> {
+        let idle_timeout = match self.idle_connection_timeout {
+            Some(timeout) => timeout,
+            None => return Ok(()),
+        };
+        let idle_peers: Vec<PeerId> = self
+            .peer_last_activity
+            .iter()
+            .filter(|(peer, last_activity)| {
+                last_activity.elapsed() > idle_timeout && !self.explicit_peers.contains(peer)
+            })
+            .map(|(peer, _)| *peer)
+            .collect();
+
+        for peer in idle_peers {
+            let _ = self.peer_last_activity.remove(&peer);
+            if self.swarm.disconnect_peer_id(peer).is_ok() {
+                info!("Reaped idle peer {peer:?}");
+                Metrics::inc(&self.metrics.peers_reaped);
+                self.send_event(NetworkEvent::PeerReaped { peer }).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The peer the event of a protocol is about, if any.
+fn event_peer(event: &NodeEvent) -> Option<PeerId> {
+    match event {
+        NodeEvent::RequestResponse(event) => request_response_peer(event),
+        NodeEvent::RawRequestResponse(event) => request_response_peer(event),
+        NodeEvent::Kademlia(event) => match event {
+            KademliaEvent::RoutingUpdated { peer, .. }
+            | KademliaEvent::UnroutablePeer { peer }
+            | KademliaEvent::RoutablePeer { peer, .. }
+            | KademliaEvent::PendingRoutablePeer { peer, .. } => Some(*peer),
+            _ => None,
+        },
+        // A peer being discovered on the local network is not traffic with it.
+        NodeEvent::Mdns(_) => None,
+        NodeEvent::Autonat(event) => match event {
+            autonat::Event::InboundProbe(
+                autonat::InboundProbeEvent::Request { peer, .. }
+                | autonat::InboundProbeEvent::Response { peer, .. }
+                | autonat::InboundProbeEvent::Error { peer, .. },
+            )
+            | autonat::Event::OutboundProbe(
+                autonat::OutboundProbeEvent::Request { peer, .. }
+                | autonat::OutboundProbeEvent::Response { peer, .. },
+            ) => Some(*peer),
+            autonat::Event::OutboundProbe(autonat::OutboundProbeEvent::Error { peer, .. }) => *peer,
+            autonat::Event::StatusChanged { .. } => None,
+        },
+        NodeEvent::Identify(event) => match event.as_ref() {
+            identify::Event::Received { peer_id, .. }
+            | identify::Event::Sent { peer_id }
+            | identify::Event::Pushed { peer_id }
+            | identify::Event::Error { peer_id, .. } => Some(*peer_id),
+        },
+        NodeEvent::Gossipsub(event) => match event.as_ref() {
+            gossipsub::Event::Message {
+                propagation_source, ..
+            } => Some(*propagation_source),
+            gossipsub::Event::Subscribed { peer_id, .. }
+            | gossipsub::Event::Unsubscribed { peer_id, .. }
+            | gossipsub::Event::GossipsubNotSupported { peer_id } => Some(*peer_id),
+        },
+    }
+}
+
+fn request_response_peer<Req, Resp>(event: &request_response::Event<Req, Resp>) -> Option<PeerId> {
+    match event {
+        request_response::Event::Message { peer, .. }
+        | request_response::Event::OutboundFailure { peer, .. }
+        | request_response::Event::InboundFailure { peer, .. }
+        | request_response::Event::ResponseSent { peer, .. } => Some(*peer),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::event_peer;
+    use crate::network::{
+        error::Result, event::NodeEvent, testing::isolated_config, NetworkSwarmLoop,
+    };
+    use libp2p::{
+        gossipsub, identify,
+        kad::{kbucket::Distance, Addresses, KademliaEvent},
+        Multiaddr, PeerId,
+    };
+
+    #[test]
+    fn every_protocol_event_about_a_peer_counts_as_activity() -> Result<()> {
+        let peer = PeerId::random();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/12000".parse()?;
+
+        let sent = NodeEvent::Identify(Box::new(identify::Event::Sent { peer_id: peer }));
+        assert_eq!(event_peer(&sent), Some(peer));
+        let subscribed = NodeEvent::Gossipsub(Box::new(gossipsub::Event::Subscribed {
+            peer_id: peer,
+            topic: gossipsub::IdentTopic::new("topic").hash(),
+        }));
+        assert_eq!(event_peer(&subscribed), Some(peer));
+        let routable = NodeEvent::Kademlia(KademliaEvent::RoutablePeer {
+            peer,
+            address: addr,
+        });
+        assert_eq!(event_peer(&routable), Some(peer));
+
+        Ok(())
+    }
+
+    #[test]
+    fn a_peer_evicted_from_the_routing_table_is_no_longer_explicit() -> Result<()> {
+        let (_network, _events, mut event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let evicted = PeerId::random();
+        let kept = PeerId::random();
+        let _ = event_loop.explicit_peers.insert(evicted);
+        let _ = event_loop.explicit_peers.insert(kept);
+
+        event_loop.on_behaviour_event(&NodeEvent::Kademlia(KademliaEvent::RoutingUpdated {
+            peer: PeerId::random(),
+            is_new_peer: true,
+            addresses: Addresses::new("/ip4/127.0.0.1/tcp/12000".parse()?),
+            bucket_range: (Distance::default(), Distance::default()),
+            old_peer: Some(evicted),
+        }));
+        assert!(!event_loop.explicit_peers.contains(&evicted));
+        assert!(event_loop.explicit_peers.contains(&kept));
+
+        event_loop.forget_explicit_peer(&kept);
+        assert!(event_loop.explicit_peers.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::error::{Error, Result};
+use libp2p::{kad, PeerId};
+use std::time::Instant;
+use xor_name::XorName;
+
+/// A record fetched from the DHT, with the metadata Kademlia holds about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The key the record is stored under.
+    pub key: XorName,
+    /// The value of the record.
+    pub value: Vec<u8>,
+    /// The peer that originally put the record, if known.
+    pub publisher: Option<PeerId>,
+    /// When the record expires, if it does; past it, the peers holding it drop it.
+    pub expires: Option<Instant>,
+}
+
+impl TryFrom<kad::Record> for Record {
+    type Error = Error;
+
+    fn try_from(record: kad::Record) -> Result<Self> {
+        let key = <[u8; 32]>::try_from(record.key.as_ref())
+            .map_err(|_| Error::Other(format!("Record key is not an XorName: {:?}", record.key)))?;
+        Ok(Self {
+            key: XorName(key),
+            value: record.value,
+            publisher: record.publisher,
+            expires: record.expires,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        error::{Error, Result},
+        testing::{isolated_config, listen_addr},
+        NetworkSwarmLoop,
+    };
+    use async_std::{future::timeout, task};
+    use futures::StreamExt;
+    use libp2p::kad::{PutRecordError, Quorum};
+    use std::{num::NonZeroUsize, time::Duration};
+    use xor_name::XorName;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[async_std::test]
+    async fn a_record_is_put_only_if_the_quorum_of_peers_stored_it() -> Result<()> {
+        let (mut putter, putter_events, putter_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let (mut holder, holder_events, holder_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _putter_loop = task::spawn(putter_loop.run());
+        let _holder_loop = task::spawn(holder_loop.run());
+        let _putter_events = task::spawn(putter_events.for_each(|_| async {}));
+        let _holder_events = task::spawn(holder_events.for_each(|_| async {}));
+
+        let addr = listen_addr(&mut holder).await?;
+        timeout(TEST_TIMEOUT, putter.dial(holder.local_peer_id(), addr))
+            .await
+            .expect("the dial to complete")?;
+
+        let key = XorName::random(&mut rand::thread_rng());
+        timeout(
+            TEST_TIMEOUT,
+            putter.put_record_with_quorum(key, vec![1], Quorum::One),
+        )
+        .await
+        .expect("the put to complete")?;
+        let record = timeout(TEST_TIMEOUT, holder.get_record(key))
+            .await
+            .expect("the get to complete")?
+            .expect("the record to be stored by the peer");
+        assert_eq!(record.key, key);
+        assert_eq!(record.value, vec![1]);
+        assert_eq!(record.publisher, Some(putter.local_peer_id()));
+
+        // A single peer can't make a quorum of two.
+        let quorum = Quorum::N(NonZeroUsize::new(2).expect("2 is not zero"));
+        let other_key = XorName::random(&mut rand::thread_rng());
+        let result = timeout(
+            TEST_TIMEOUT,
+            putter.put_record_with_quorum(other_key, vec![2], quorum),
+        )
+        .await
+        .expect("the put to complete");
+        assert!(
+            matches!(
+                result,
+                Err(Error::PutRecordError(PutRecordError::QuorumFailed { .. }))
+            ),
+            "{result:?}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{config::RecordConflictPolicy, error::Result, NetworkEvent, NetworkSwarmLoop};
+use libp2p::{
+    kad::{store::RecordStore, Record},
+    PeerId,
+};
+use std::time::Instant;
+use tracing::{info, warn};
+
+impl NetworkSwarmLoop {
+    /// Applies the `NetworkConfig::record_conflict_policy` to a record about to be stored
+    /// locally, put by us if `source` is `None`, by that peer otherwise. Returns whether the
+    /// record may replace the one already held, if any.
+    ///
+    /// There is a conflict only if a record with a different value is already held; storing
+    /// the same value again, e.g. when re-publishing immutable data, is always accepted. Each
+    /// conflict is reported by a `NetworkEvent::RecordConflict`.
+    pub(super) async fn accept_record(
+        &mut self,
+        record: &Record,
+        source: Option<PeerId>,
+    ) -> Result<bool> {
+        let kademlia = &mut self.swarm.behaviour_mut().kademlia;
+        let existing_expires = match kademlia.store_mut().get(&record.key) {
+            Some(existing) if existing.value != record.value => existing.expires,
+            _ => return Ok(true),
+        };
+        let accepted = replaces_existing(
+            self.record_conflict_policy,
+            source.is_none(),
+            record.expires,
+            existing_expires,
+        );
+        if accepted {
+            info!("Replacing the record {:?}, put by {source:?}", record.key);
+        } else {
+            warn!(
+                "Keeping the existing record {:?}, put by {source:?}",
+                record.key
+            );
+        }
+        self.send_event(NetworkEvent::RecordConflict {
+            key: record.key.to_vec(),
+            source,
+            kept_existing: !accepted,
+        })
+        .await?;
+        Ok(accepted)
+    }
+
+    /// Stores the record put by the peer, unless the `NetworkConfig::record_conflict_policy`
+    /// keeps the one already held.
+    pub(super) async fn store_inbound_record(
+        &mut self,
+        record: Record,
+        source: PeerId,
+    ) -> Result<()> {
+        if self.accept_record(&record, Some(source)).await? {
+            let store = self.swarm.behaviour_mut().kademlia.store_mut();
+            if let Err(err) = store.put(record) {
+                warn!("Failed to store the record put by {source:?}: {err}");
+            }
+        }
+        Ok(())
+    }
+}
+
+// Whether a record with a different value than the one held, expiring at `expires`, replaces it
+// as per the `policy`; `local` for our own puts.
+fn replaces_existing(
+    policy: RecordConflictPolicy,
+    local: bool,
+    expires: Option<Instant>,
+    existing_expires: Option<Instant>,
+) -> bool {
+    match policy {
+        RecordConflictPolicy::Overwrite => true,
+        RecordConflictPolicy::FailIfExists => false,
+        // Our own puts are the latest by definition. `None` never expires, i.e. is the
+        // largest timestamp; ties keep the existing record.
+        RecordConflictPolicy::KeepLargerTimestamp => match expires {
+            _ if local => true,
+            None => existing_expires.is_some(),
+            Some(expires) => existing_expires.is_some_and(|e| expires > e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::replaces_existing;
+    use crate::network::config::RecordConflictPolicy::{FailIfExists, KeepLargerTimestamp};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn the_record_expiring_last_is_kept() {
+        let earlier = Some(Instant::now());
+        let later = earlier.map(|at| at + Duration::from_secs(60));
+
+        assert!(replaces_existing(
+            KeepLargerTimestamp,
+            false,
+            later,
+            earlier
+        ));
+        assert!(!replaces_existing(
+            KeepLargerTimestamp,
+            false,
+            earlier,
+            later
+        ));
+        // Ties keep the existing record.
+        assert!(!replaces_existing(KeepLargerTimestamp, false, later, later));
+        // Never expiring is the largest timestamp.
+        assert!(replaces_existing(KeepLargerTimestamp, false, None, later));
+        assert!(!replaces_existing(KeepLargerTimestamp, false, later, None));
+        assert!(!replaces_existing(KeepLargerTimestamp, false, None, None));
+        // Our own puts are the latest.
+        assert!(replaces_existing(KeepLargerTimestamp, true, earlier, None));
+    }
+
+    #[test]
+    fn an_existing_record_is_never_replaced_if_it_must_not_exist() {
+        let now = Some(Instant::now());
+        assert!(!replaces_existing(FailIfExists, false, None, now));
+        assert!(!replaces_existing(FailIfExists, true, None, None));
+    }
+}
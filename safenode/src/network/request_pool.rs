@@ -0,0 +1,170 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    error::Result, EventFilter, Network, NetworkEvent, Request, RequestPoolConfig, Response,
+    ResponseHandle,
+};
+use async_std::{
+    channel::{self, TrySendError},
+    task,
+};
+use futures::{Future, StreamExt};
+use std::sync::Arc;
+use tracing::{trace, warn};
+
+impl Network {
+    /// Serve the inbound `Request`s with `handler`, run on a pool of
+    /// `RequestPoolConfig::workers` tasks, so a slow handler doesn't hold up the networking.
+    ///
+    /// Requests are queued until a worker picks them up; once `RequestPoolConfig::queue_capacity`
+    /// requests are waiting, the new ones are answered right away with `Response::Overloaded`.
+    /// The `NetworkEvent::RequestReceived` events are no longer emitted on the other event
+    /// streams. The pool runs for as long as the `NetworkSwarmLoop`, which it keeps alive by
+    /// holding `Network` handles; use `Network::shutdown_graceful` to stop both.
+    pub async fn serve_requests<H, F>(
+        &mut self,
+        config: RequestPoolConfig,
+        handler: H,
+    ) -> Result<()>
+    where
+        H: Fn(Request) -> F + Send + Sync + 'static,
+        F: Future<Output = Response> + Send,
+    {
+        let mut requests = self
+            .subscribe_events(EventFilter::new(|event| {
+                matches!(event, NetworkEvent::RequestReceived { .. })
+            }))
+            .await?;
+        let (queue, jobs) =
+            channel::bounded::<(Request, ResponseHandle<Response>)>(config.queue_capacity.max(1));
+
+        let handler = Arc::new(handler);
+        for _ in 0..config.workers.max(1) {
+            let jobs = jobs.clone();
+            let handler = handler.clone();
+            let mut network = self.clone();
+            let _worker = task::spawn(async move {
+                while let Ok((req, channel)) = jobs.recv().await {
+                    let resp = handler(req).await;
+                    if let Err(err) = network.send_response(resp, channel).await {
+                        warn!("Failed to send the response from the request pool: {err}");
+                    }
+                }
+            });
+        }
+
+        let mut network = self.clone();
+        let _dispatcher = task::spawn(async move {
+            while let Some(event) = requests.next().await {
+                let (req, channel) = match event {
+                    NetworkEvent::RequestReceived { req, channel, .. } => (req, channel),
+                    _ => continue,
+                };
+                match queue.try_send((req, channel)) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full((req, channel))) => {
+                        trace!("Request pool full, shedding {req:?}");
+                        if let Err(err) = network.send_response(Response::Overloaded, channel).await
+                        {
+                            warn!("Failed to shed the request: {err}");
+                        }
+                    }
+                    Err(TrySendError::Closed(_)) => break,
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        config::RequestPoolConfig,
+        error::Result,
+        testing::{isolated_config, listen_addr},
+        NetworkSwarmLoop, Request, Response,
+    };
+    use async_std::{channel, future::timeout, task};
+    use futures::{future, StreamExt};
+    use std::time::Duration;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[async_std::test]
+    async fn the_requests_beyond_the_queue_are_shed_while_the_workers_are_busy() -> Result<()> {
+        let (mut server, server_events, server_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _server_loop = task::spawn(server_loop.run());
+        let _server_events = task::spawn(server_events.for_each(|_| async {}));
+        let server_addr = listen_addr(&mut server).await?;
+        let server_id = server.local_peer_id();
+
+        // The handler waits for the test to release it, telling when it started.
+        let (started, mut handling) = futures::channel::mpsc::unbounded();
+        let (release, gate) = channel::unbounded::<()>();
+        server
+            .serve_requests(
+                RequestPoolConfig {
+                    workers: 1,
+                    queue_capacity: 1,
+                },
+                move |_req| {
+                    let started = started.clone();
+                    let gate = gate.clone();
+                    async move {
+                        let _ = started.unbounded_send(());
+                        let _ = gate.recv().await;
+                        Response::DBC
+                    }
+                },
+            )
+            .await?;
+
+        let (mut client, events, client_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let _client_loop = task::spawn(client_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+        timeout(TEST_TIMEOUT, client.dial(server_id, server_addr))
+            .await
+            .expect("the dial to complete")?;
+
+        let mut handled = client.clone();
+        let first =
+            task::spawn(async move { handled.send_request(Request::GetDBC, server_id).await });
+        let _ = timeout(TEST_TIMEOUT, handling.next())
+            .await
+            .expect("the worker to pick the first request up");
+        // The loop still answers while the handler is busy.
+        assert_eq!(
+            timeout(TEST_TIMEOUT, client.send_request(Request::Ping, server_id))
+                .await
+                .expect("the ping to be answered")?,
+            Response::Pong
+        );
+
+        // The worker being busy, one more request is queued and the next one is shed.
+        let (mut second, mut third) = (client.clone(), client.clone());
+        let queued_or_shed = future::select(
+            task::spawn(async move { second.send_request(Request::GetDBC, server_id).await }),
+            task::spawn(async move { third.send_request(Request::GetDBC, server_id).await }),
+        );
+        let (shed, queued) = timeout(TEST_TIMEOUT, queued_or_shed)
+            .await
+            .expect("a request to be shed")
+            .factor_first();
+        assert_eq!(shed?, Response::Overloaded);
+
+        for _ in 0..2 {
+            let _ = release.send(()).await;
+        }
+        assert_eq!(first.await?, Response::DBC);
+        assert_eq!(queued.await?, Response::DBC);
+        Ok(())
+    }
+}
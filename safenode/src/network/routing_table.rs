@@ -0,0 +1,214 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{error::Result, AddressSource, NetworkEvent, NetworkSwarmLoop};
+use libp2p::{kad::RoutingUpdate, multiaddr::Protocol, Multiaddr, PeerId};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+use tracing::{debug, info, warn};
+
+/// How often the occupancy of the routing table is checked, see `check_routing_table`.
+pub(super) const ROUTING_CHECK_TICK: Duration = Duration::from_secs(1);
+
+impl NetworkSwarmLoop {
+    /// Reports the routing table becoming empty, and getting peers again afterwards.
+    ///
+    /// Kademlia removes the entries without notice, e.g. once all the addresses of a peer failed
+    /// to be dialed, hence the occupancy is checked every `ROUTING_CHECK_TICK` rather than
+    /// tracked through its events.
+    pub(super) async fn check_routing_table(&mut self) -> Result<()> {
+        // `kbuckets` only yields the non-empty buckets.
+        let empty = self
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .kbuckets()
+            .next()
+            .is_none();
+        match self.routing_table_empty {
+            Some(was_empty) if was_empty == empty => Ok(()),
+            // Not populated yet.
+            None if empty => Ok(()),
+            None => {
+                self.routing_table_empty = Some(false);
+                Ok(())
+            }
+            Some(_) => {
+                self.routing_table_empty = Some(empty);
+                if empty {
+                    warn!("The routing table is empty");
+                    self.send_event(NetworkEvent::RoutingTableEmpty).await
+                } else {
+                    info!("The routing table got peers again");
+                    self.send_event(NetworkEvent::RoutingTableRecovered).await
+                }
+            }
+        }
+    }
+
+    /// Adds the peers of a trusted snapshot to the routing table, without dialing them, and
+    /// returns the number of peers added.
+    ///
+    /// The entries of the local peer, without an address, or with an address for another peer,
+    /// are skipped, and the duplicate peers and addresses are merged. The peers not added are
+    /// those Kademlia had no room for, their buckets being full.
+    pub(super) fn import_routing_snapshot(
+        &mut self,
+        snapshot: Vec<(PeerId, Vec<Multiaddr>)>,
+    ) -> usize {
+        let local_peer_id = *self.swarm.local_peer_id();
+        let mut peers: HashMap<PeerId, Vec<Multiaddr>> = HashMap::new();
+        for (peer_id, addrs) in snapshot {
+            if peer_id == local_peer_id {
+                debug!("Skipping the local peer from the routing snapshot");
+                continue;
+            }
+            let known = peers.entry(peer_id).or_default();
+            for addr in addrs {
+                match snapshot_addr(peer_id, addr) {
+                    Some(addr) if !known.contains(&addr) => known.push(addr),
+                    Some(_) => {}
+                    None => debug!(
+                        "Skipping an invalid address of {peer_id:?} from the routing snapshot"
+                    ),
+                }
+            }
+        }
+
+        let mut added = HashSet::new();
+        for (peer_id, addrs) in peers {
+            for addr in addrs {
+                self.address_book
+                    .record(peer_id, addr.clone(), AddressSource::Snapshot);
+                let update = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .add_address(&peer_id, addr);
+                if matches!(update, RoutingUpdate::Success) {
+                    let _ = added.insert(peer_id);
+                }
+            }
+        }
+        info!("Imported {} peers from the routing snapshot", added.len());
+        added.len()
+    }
+}
+
+// Returns the address without its trailing `/p2p` component, if any, or `None` if the address is
+// empty or meant for another peer.
+fn snapshot_addr(peer_id: PeerId, mut addr: Multiaddr) -> Option<Multiaddr> {
+    if let Some(Protocol::P2p(hash)) = addr.iter().last() {
+        if PeerId::from_multihash(hash).ok()? != peer_id {
+            return None;
+        }
+        let _ = addr.pop();
+    }
+    if addr
+        .iter()
+        .any(|protocol| matches!(protocol, Protocol::P2p(_)))
+        || addr.is_empty()
+    {
+        return None;
+    }
+    Some(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{error::Result, testing::isolated_config, NetworkEvent, NetworkSwarmLoop};
+    use futures::{FutureExt, StreamExt};
+    use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+
+    #[async_std::test]
+    async fn the_routing_table_is_reported_empty_once_populated_then_recovered() -> Result<()> {
+        let (_network, events, mut event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let mut events = Box::pin(events);
+        let peer = PeerId::random();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/12000".parse()?;
+
+        // A starting node isn't isolated, only not populated yet.
+        event_loop.check_routing_table().await?;
+        let _ = event_loop
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .add_address(&peer, addr.clone());
+        event_loop.check_routing_table().await?;
+        assert!(events.next().now_or_never().is_none());
+
+        let _ = event_loop.swarm.behaviour_mut().kademlia.remove_peer(&peer);
+        event_loop.check_routing_table().await?;
+        event_loop.check_routing_table().await?;
+        assert!(matches!(
+            events.next().await,
+            Some(NetworkEvent::RoutingTableEmpty)
+        ));
+        assert!(events.next().now_or_never().is_none());
+
+        let _ = event_loop
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .add_address(&peer, addr);
+        event_loop.check_routing_table().await?;
+        assert!(matches!(
+            events.next().await,
+            Some(NetworkEvent::RoutingTableRecovered)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn a_snapshot_is_imported_deduped_and_validated() -> Result<()> {
+        let (network, _events, mut event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let (peer, other) = (PeerId::random(), PeerId::random());
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/12000".parse()?;
+        let other_addr: Multiaddr = "/ip4/127.0.0.1/tcp/12001".parse()?;
+        let imported = event_loop.import_routing_snapshot(vec![
+            (
+                peer,
+                vec![
+                    addr.clone(),
+                    // The same address once its `/p2p` suffix is stripped.
+                    addr.clone().with(Protocol::P2p(peer.into())),
+                    // For another peer.
+                    addr.clone().with(Protocol::P2p(other.into())),
+                    Multiaddr::empty(),
+                ],
+            ),
+            // Merged into the entry above.
+            (peer, vec![addr.clone()]),
+            (network.local_peer_id(), vec![other_addr.clone()]),
+            (other, vec![]),
+        ]);
+        assert_eq!(imported, 1);
+
+        let entries: Vec<(PeerId, Vec<Multiaddr>)> = event_loop
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .kbuckets()
+            .flat_map(|bucket| {
+                bucket
+                    .iter()
+                    .map(|entry| {
+                        (
+                            *entry.node.key.preimage(),
+                            entry.node.value.iter().cloned().collect(),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(entries, vec![(peer, vec![addr])]);
+        Ok(())
+    }
+}
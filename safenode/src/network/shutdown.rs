@@ -0,0 +1,248 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    error::{Error, Result},
+    NetworkSwarmLoop,
+};
+use futures::channel::oneshot;
+use libp2p::PeerId;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+/// The outcome of `Network::shutdown_graceful`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    /// The number of in-flight requests and queries that completed while draining.
+    pub completed: usize,
+    /// The number of in-flight requests and queries that were abandoned at the deadline.
+    pub abandoned: usize,
+}
+
+/// A graceful shutdown in progress.
+pub(super) struct Shutdown {
+    deadline: Instant,
+    /// The number of requests and queries in flight when last checked.
+    in_flight: usize,
+    /// The number of requests and queries that completed since the shutdown started.
+    completed: usize,
+    sender: oneshot::Sender<Result<ShutdownSummary>>,
+}
+
+impl NetworkSwarmLoop {
+    /// Stops listening and starts draining the in-flight requests and queries; the new work
+    /// is rejected from now on.
+    pub(super) fn start_shutdown(
+        &mut self,
+        drain_timeout: Duration,
+        sender: oneshot::Sender<Result<ShutdownSummary>>,
+    ) {
+        for (listener_id, _) in self.listeners.drain() {
+            let _ = self.swarm.remove_listener(listener_id);
+        }
+        self.abandon_queued_dials();
+        let in_flight = self.in_flight_count();
+        info!("Shutting down, draining {in_flight} in-flight requests and queries");
+        self.shutdown = Some(Shutdown {
+            deadline: Instant::now() + drain_timeout,
+            in_flight,
+            completed: 0,
+            sender,
+        });
+    }
+
+    /// Returns the deadline of the shutdown in progress, if any.
+    pub(super) fn shutdown_deadline(&self) -> Option<Instant> {
+        self.shutdown.as_ref().map(|shutdown| shutdown.deadline)
+    }
+
+    /// Completes the shutdown in progress once everything has been drained or the deadline
+    /// passed, abandoning the work left. Returns whether the event loop is to stop.
+    pub(super) fn try_complete_shutdown(&mut self) -> bool {
+        let in_flight = self.in_flight_count();
+        match &mut self.shutdown {
+            Some(shutdown) => {
+                // Checked after every event and command, each completing at most a few.
+                shutdown.completed += shutdown.in_flight.saturating_sub(in_flight);
+                shutdown.in_flight = in_flight;
+                if in_flight > 0 && Instant::now() < shutdown.deadline {
+                    return false;
+                }
+            }
+            None => return false,
+        }
+        let shutdown = match self.shutdown.take() {
+            Some(shutdown) => shutdown,
+            None => return false,
+        };
+
+        let abandoned = in_flight;
+        abandon(&mut self.pending_dial);
+        abandon(&mut self.pending_start_providing);
+        abandon(&mut self.pending_get_providers);
+        abandon(&mut self.pending_requests);
+        self.abandon_coalesced_requests();
+        self.request_transfers.clear();
+        self.request_timings.clear();
+        self.request_deadlines.clear();
+        self.dial_deadlines.clear();
+        abandon(&mut self.pending_raw_requests);
+        for sender in self
+            .pending_bootstrap
+            .drain()
+            .filter_map(|(_, sender)| sender)
+        {
+            let _ = sender.send(Err(Error::Shutdown));
+        }
+        abandon(&mut self.pending_put_record);
+        abandon(&mut self.pending_get_record);
+        abandon(&mut self.pending_get_closest_peers);
+        self.staggered_dials.clear();
+        self.op_traces.clear();
+        self.persistent_stores.clear();
+        self.store_retry_queue.clear();
+        if let Some(dials) = self.bootstrap_dials.take() {
+            dials.abandon(Error::Shutdown);
+        }
+        let connected_peers: Vec<PeerId> = self.swarm.connected_peers().copied().collect();
+        for peer_id in connected_peers {
+            let _ = self.swarm.disconnect_peer_id(peer_id);
+        }
+
+        let summary = ShutdownSummary {
+            completed: shutdown.completed,
+            abandoned,
+        };
+        info!("Shut down: {summary:?}");
+        let _ = shutdown.sender.send(Ok(summary));
+        true
+    }
+
+    // The number of requests and queries we are still waiting the outcome of.
+    pub(super) fn in_flight_count(&self) -> usize {
+        self.pending_dial.len()
+            + self.queued_dials.len()
+            + self.pending_start_providing.len()
+            + self.pending_get_providers.len()
+            + self.pending_requests.len()
+            + self.coalesced_waiter_count()
+            + self.pending_raw_requests.len()
+            + self.pending_bootstrap.len()
+            + self.pending_put_record.len()
+            + self.pending_get_record.len()
+            + self.pending_get_closest_peers.len()
+            + self.persistent_stores.len()
+            + usize::from(self.bootstrap_dials.is_some())
+    }
+}
+
+// Fails all the pending operations of the given kind with `Error::Shutdown`.
+fn abandon<K, T>(pending: &mut HashMap<K, oneshot::Sender<Result<T>>>) {
+    for (_, sender) in pending.drain() {
+        let _ = sender.send(Err(Error::Shutdown));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShutdownSummary;
+    use crate::network::{
+        error::{Error, Result},
+        testing::{isolated_config, listen_addr},
+        Network, NetworkEvent, NetworkSwarmLoop, Request, Response,
+    };
+    use async_std::{future::timeout, task};
+    use futures::StreamExt;
+    use libp2p::PeerId;
+    use std::time::Duration;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    // Spawns a node answering the requests with `Pong` after `delay`, or never if `None`, and
+    // a node connected to it, returning the handle of the latter and the id of the former.
+    async fn connected_to_responder(delay: Option<Duration>) -> Result<(Network, PeerId)> {
+        let (mut responder, mut responder_events, responder_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _responder_loop = task::spawn(responder_loop.run());
+        let responder_id = responder.local_peer_id();
+        let responder_addr = listen_addr(&mut responder).await?;
+        let _responder = task::spawn(async move {
+            let mut unanswered = Vec::new();
+            while let Some(event) = responder_events.next().await {
+                if let NetworkEvent::RequestReceived { channel, .. } = event {
+                    match delay {
+                        Some(delay) => {
+                            task::sleep(delay).await;
+                            let _ = responder.send_response(Response::Pong, channel).await;
+                        }
+                        None => unanswered.push(channel),
+                    }
+                }
+            }
+        });
+
+        let (mut network, events, event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+        let _event_loop = task::spawn(event_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+        timeout(TEST_TIMEOUT, network.dial(responder_id, responder_addr))
+            .await
+            .expect("the dial to complete")?;
+        Ok((network, responder_id))
+    }
+
+    #[async_std::test]
+    async fn a_graceful_shutdown_drains_the_requests_in_flight() -> Result<()> {
+        let (mut network, responder) =
+            connected_to_responder(Some(Duration::from_millis(300))).await?;
+        let mut requester = network.clone();
+        let request =
+            task::spawn(async move { requester.send_request(Request::GetDBC, responder).await });
+        task::sleep(Duration::from_millis(100)).await;
+
+        let summary = timeout(TEST_TIMEOUT, network.shutdown_graceful(TEST_TIMEOUT))
+            .await
+            .expect("the shutdown to complete")?;
+        assert_eq!(
+            summary,
+            ShutdownSummary {
+                completed: 1,
+                abandoned: 0
+            }
+        );
+        assert_eq!(request.await?, Response::Pong);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn the_requests_in_flight_at_the_deadline_are_abandoned() -> Result<()> {
+        let (mut network, responder) = connected_to_responder(None).await?;
+        let mut requester = network.clone();
+        let request =
+            task::spawn(async move { requester.send_request(Request::GetDBC, responder).await });
+        task::sleep(Duration::from_millis(100)).await;
+
+        let summary = timeout(
+            TEST_TIMEOUT,
+            network.shutdown_graceful(Duration::from_millis(300)),
+        )
+        .await
+        .expect("the shutdown to complete")?;
+        assert_eq!(
+            summary,
+            ShutdownSummary {
+                completed: 0,
+                abandoned: 1
+            }
+        );
+        assert!(matches!(request.await, Err(Error::Shutdown)));
+        Ok(())
+    }
+}
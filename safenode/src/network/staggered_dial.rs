@@ -0,0 +1,326 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{NetworkSwarmLoop, NodeBehaviour};
+use libp2p::{
+    core::Endpoint,
+    swarm::{
+        dial_opts::{DialOpts, PeerCondition},
+        dummy, ConnectionDenied, ConnectionId, DialError, FromSwarm, NetworkBehaviour,
+        PollParameters, Swarm, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+use std::{
+    collections::{HashSet, VecDeque},
+    convert::Infallible,
+    fmt,
+    task::{Context, Poll},
+    time::Instant,
+};
+use tracing::debug;
+
+/// A dial to the addresses of a peer, attempted one after the other, happy-eyeballs style.
+pub(super) struct StaggeredDial {
+    /// The addresses not dialed yet.
+    remaining: VecDeque<Multiaddr>,
+    /// The time to dial the next address at, unless connected by then.
+    next_at: Instant,
+    /// The number of dials to the peer yet to complete.
+    in_flight: usize,
+}
+
+impl NetworkSwarmLoop {
+    /// Dials the peer on the given addresses, in order.
+    ///
+    /// With a `NetworkConfig::dial_stagger`, the next address is dialed once the stagger elapsed
+    /// or as soon as the previous dials failed, while those still in flight keep going; the
+    /// first connection established ends the dial, those of the dials still in flight being
+    /// refused by the `StaggeredDialGuard`. Otherwise the addresses are dialed at once.
+    pub(super) fn dial_addresses(
+        &mut self,
+        peer_id: PeerId,
+        mut addrs: Vec<Multiaddr>,
+    ) -> Result<(), DialError> {
+        let stagger = match self.dial_stagger {
+            Some(stagger) if addrs.len() > 1 => stagger,
+            _ => {
+                return self
+                    .swarm
+                    .dial(DialOpts::peer_id(peer_id).addresses(addrs).build())
+            }
+        };
+        let first = addrs.remove(0);
+        dial_staggered(&mut self.swarm, peer_id, first)?;
+        let _ = self.staggered_dials.insert(
+            peer_id,
+            StaggeredDial {
+                remaining: addrs.into(),
+                next_at: Instant::now() + stagger,
+                in_flight: 1,
+            },
+        );
+        Ok(())
+    }
+
+    /// Dials the next address of the staggered dials whose stagger elapsed.
+    pub(super) fn advance_staggered_dials(&mut self) {
+        let now = Instant::now();
+        let due: Vec<PeerId> = self
+            .staggered_dials
+            .iter()
+            .filter(|(_, dial)| dial.next_at <= now && !dial.remaining.is_empty())
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        for peer_id in due {
+            // The addresses left are dialed on the next ticks.
+            if self.dial_limit_reached() {
+                break;
+            }
+            let _dialed = self.dial_next_address(peer_id);
+        }
+    }
+
+    /// Ends the staggered dial to the peer, if any, now that we are connected to it; the
+    /// remaining addresses are not dialed.
+    pub(super) fn on_staggered_dial_connected(&mut self, peer_id: PeerId) {
+        let _ = self.staggered_dials.remove(&peer_id);
+    }
+
+    /// Moves on to the next address of the staggered dial to the peer, if any, after one of its
+    /// dials failed. Returns whether the dial is still in progress, i.e. whether the failure is
+    /// not to be reported yet.
+    pub(super) fn on_staggered_dial_failed(&mut self, peer_id: PeerId) -> bool {
+        match self.staggered_dials.get_mut(&peer_id) {
+            Some(dial) => dial.in_flight = dial.in_flight.saturating_sub(1),
+            None => return false,
+        }
+        if self.dial_next_address(peer_id) {
+            return true;
+        }
+        let in_progress = self
+            .staggered_dials
+            .get(&peer_id)
+            .is_some_and(|dial| dial.in_flight > 0);
+        if !in_progress {
+            let _ = self.staggered_dials.remove(&peer_id);
+        }
+        in_progress
+    }
+
+    // Dials the next address of the staggered dial to the peer, skipping those that can't be
+    // dialed. Returns whether a dial has been started.
+    fn dial_next_address(&mut self, peer_id: PeerId) -> bool {
+        let dial = match self.staggered_dials.get_mut(&peer_id) {
+            Some(dial) => dial,
+            None => return false,
+        };
+        while let Some(addr) = dial.remaining.pop_front() {
+            match dial_staggered(&mut self.swarm, peer_id, addr.clone()) {
+                Ok(()) => {
+                    dial.in_flight += 1;
+                    dial.next_at = Instant::now() + self.dial_stagger.unwrap_or_default();
+                    return true;
+                }
+                Err(err) => debug!("Not dialing {peer_id:?} on {addr:?}: {err}"),
+            }
+        }
+        false
+    }
+}
+
+// Dials a single address, unless connected to the peer in the meantime.
+fn dial_opts(peer_id: PeerId, addr: Multiaddr) -> DialOpts {
+    DialOpts::peer_id(peer_id)
+        .condition(PeerCondition::Disconnected)
+        .addresses(vec![addr])
+        .build()
+}
+
+// Dials one address of a staggered dial, its connection to be refused if another one to the
+// peer is established first.
+fn dial_staggered(
+    swarm: &mut Swarm<NodeBehaviour>,
+    peer_id: PeerId,
+    addr: Multiaddr,
+) -> Result<(), DialError> {
+    let opts = dial_opts(peer_id, addr);
+    let _ = swarm
+        .behaviour_mut()
+        .staggered_dials
+        .dials
+        .insert(opts.connection_id());
+    swarm.dial(opts)
+}
+
+/// Refuses the connections of the staggered dials to the peers we got connected to in the
+/// meantime. libp2p can't abort a single dial in flight, hence the slower dials of a staggered
+/// dial go on after the first connection is established, and would each open another one.
+#[derive(Default)]
+pub(crate) struct StaggeredDialGuard {
+    /// The staggered dials in flight.
+    dials: HashSet<ConnectionId>,
+    connected: HashSet<PeerId>,
+}
+
+/// Why the connection of a staggered dial is refused.
+#[derive(Debug)]
+struct AlreadyConnected;
+
+impl fmt::Display for AlreadyConnected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Already connected through another address of the staggered dial")
+    }
+}
+
+impl std::error::Error for AlreadyConnected {}
+
+impl NetworkBehaviour for StaggeredDialGuard {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type OutEvent = Infallible;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        if self.dials.remove(&connection_id) && self.connected.contains(&peer) {
+            return Err(ConnectionDenied::new(AlreadyConnected));
+        }
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        match event {
+            FromSwarm::ConnectionEstablished(established) => {
+                let _ = self.dials.remove(&established.connection_id);
+                let _ = self.connected.insert(established.peer_id);
+            }
+            FromSwarm::ConnectionClosed(closed) if closed.remaining_established == 0 => {
+                let _ = self.connected.remove(&closed.peer_id);
+            }
+            FromSwarm::DialFailure(failure) => {
+                let _ = self.dials.remove(&failure.connection_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer_id: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+        _params: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::OutEvent, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        error::Result,
+        testing::{isolated_config, listen_addr},
+        AddressSource, NetworkConfig, NetworkSwarmLoop,
+    };
+    use async_std::{
+        future::timeout,
+        io,
+        net::{TcpListener, TcpStream},
+        task,
+    };
+    use futures::StreamExt;
+    use libp2p::{multiaddr::Protocol, Multiaddr};
+    use std::{net::SocketAddr, time::Duration};
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    // Forwards the connections to `target` once `delay` elapsed, returning the address to dial.
+    async fn delaying_proxy(target: SocketAddr, delay: Duration) -> Result<Multiaddr> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let _proxy = task::spawn(async move {
+            while let Some(Ok(inbound)) = listener.incoming().next().await {
+                let _forward = task::spawn(async move {
+                    task::sleep(delay).await;
+                    let outbound = TcpStream::connect(target).await?;
+                    let upstream = io::copy(inbound.clone(), outbound.clone());
+                    let downstream = io::copy(outbound, inbound);
+                    let _ = futures::future::try_join(upstream, downstream).await?;
+                    io::Result::Ok(())
+                });
+            }
+        });
+        Ok(format!("/ip4/127.0.0.1/tcp/{port}")
+            .parse()
+            .expect("a valid multiaddr"))
+    }
+
+    fn socket_addr(addr: &Multiaddr) -> SocketAddr {
+        let mut port = 0;
+        for protocol in addr.iter() {
+            if let Protocol::Tcp(tcp_port) = protocol {
+                port = tcp_port;
+            }
+        }
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[async_std::test]
+    async fn the_slower_dials_do_not_open_duplicate_connections() -> Result<()> {
+        let (mut remote, remote_events, remote_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _remote_loop = task::spawn(remote_loop.run());
+        let _remote_events = task::spawn(remote_events.for_each(|_| async {}));
+        let remote_addr = listen_addr(&mut remote).await?;
+
+        let (mut network, events, mut event_loop) = NetworkSwarmLoop::with_config(NetworkConfig {
+            dial_stagger: Some(Duration::from_millis(50)),
+            ..isolated_config()
+        })?;
+        // The known address is dialed after the given one, which connects well after it.
+        let slow_addr =
+            delaying_proxy(socket_addr(&remote_addr), Duration::from_millis(500)).await?;
+        event_loop
+            .address_book
+            .record(remote.local_peer_id(), remote_addr, AddressSource::Dialed);
+        let _event_loop = task::spawn(event_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+
+        timeout(
+            TEST_TIMEOUT,
+            network.dial(remote.local_peer_id(), slow_addr),
+        )
+        .await
+        .expect("the dial to complete")?;
+
+        task::sleep(Duration::from_secs(1)).await;
+        assert_eq!(network.metrics_snapshot().open_connections, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{error::Result, NetworkEvent, NetworkSwarmLoop};
+use libp2p::kad::QueryId;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use xor_name::XorName;
+
+/// How often the persistent stores are checked for a due retry.
+pub(super) const STORE_RETRY_TICK: Duration = Duration::from_secs(1);
+
+/// A `Network::store_data_persistent` in progress.
+#[derive(Debug)]
+pub(super) struct PersistentStore {
+    xor_name: XorName,
+    attempts: u32,
+    backoff: Duration,
+    deadline: Instant,
+}
+
+impl NetworkSwarmLoop {
+    /// Starts advertising the data, retrying as per the `NetworkConfig::store_retry` until it
+    /// succeeds.
+    pub(super) async fn start_persistent_store(&mut self, xor_name: XorName) -> Result<()> {
+        let store = PersistentStore {
+            xor_name,
+            attempts: 0,
+            backoff: self.store_retry.initial_backoff,
+            deadline: Instant::now() + self.store_retry.deadline,
+        };
+        self.attempt_persistent_store(store).await
+    }
+
+    /// Accounts for the outcome of a persistent store query, if `query_id` is one of them.
+    /// Returns whether it was.
+    pub(super) async fn on_persistent_store_completed(
+        &mut self,
+        query_id: QueryId,
+        succeeded: bool,
+    ) -> Result<bool> {
+        let store = match self.persistent_stores.remove(&query_id) {
+            Some(store) => store,
+            None => return Ok(false),
+        };
+        if succeeded {
+            info!(
+                "Stored {:?} after {} attempts",
+                store.xor_name, store.attempts
+            );
+            self.send_event(NetworkEvent::DataStored {
+                xor_name: store.xor_name,
+                attempts: store.attempts,
+            })
+            .await?;
+        } else {
+            self.retry_persistent_store(store).await?;
+        }
+        Ok(true)
+    }
+
+    /// Attempts the persistent stores whose backoff has elapsed.
+    pub(super) async fn retry_due_persistent_stores(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let (due, waiting) = self
+            .store_retry_queue
+            .drain(..)
+            .partition(|(retry_at, _)| *retry_at <= now);
+        self.store_retry_queue = waiting;
+        for (_, store) in due {
+            self.attempt_persistent_store(store).await?;
+        }
+        Ok(())
+    }
+
+    // An attempt fails right away while the routing table is empty, as there is no one to
+    // advertise the data to. The store is given up on once the data is no longer held.
+    async fn attempt_persistent_store(&mut self, mut store: PersistentStore) -> Result<()> {
+        store.attempts += 1;
+        if self.verify_held(store.xor_name).await.is_err() {
+            return self
+                .send_event(NetworkEvent::DataStoreFailed {
+                    xor_name: store.xor_name,
+                    attempts: store.attempts,
+                })
+                .await;
+        }
+        let kademlia = &mut self.swarm.behaviour_mut().kademlia;
+        if kademlia.kbuckets().next().is_none() {
+            return self.retry_persistent_store(store).await;
+        }
+        match kademlia.start_providing(store.xor_name.0.to_vec().into()) {
+            Ok(query_id) => {
+                let _ = self.persistent_stores.insert(query_id, store);
+                Ok(())
+            }
+            Err(err) => {
+                warn!("Failed to store {:?}: {err}", store.xor_name);
+                self.retry_persistent_store(store).await
+            }
+        }
+    }
+
+    async fn retry_persistent_store(&mut self, mut store: PersistentStore) -> Result<()> {
+        let retry_at = Instant::now() + store.backoff;
+        if retry_at > store.deadline {
+            warn!(
+                "Giving up on storing {:?} after {} attempts",
+                store.xor_name, store.attempts
+            );
+            return self
+                .send_event(NetworkEvent::DataStoreFailed {
+                    xor_name: store.xor_name,
+                    attempts: store.attempts,
+                })
+                .await;
+        }
+        store.backoff = (store.backoff * 2).min(self.store_retry.max_backoff);
+        self.store_retry_queue.push((retry_at, store));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        config::StoreRetryConfig,
+        error::Result,
+        testing::{isolated_config, listen_addr},
+        NetworkConfig, NetworkEvent, NetworkSwarmLoop,
+    };
+    use async_std::{future::timeout, task};
+    use futures::StreamExt;
+    use std::time::Duration;
+    use xor_name::XorName;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[async_std::test]
+    async fn a_persistent_store_is_retried_until_there_is_a_peer_to_store_to() -> Result<()> {
+        let (mut remote, remote_events, remote_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _remote_loop = task::spawn(remote_loop.run());
+        let _remote_events = task::spawn(remote_events.for_each(|_| async {}));
+        let remote_addr = listen_addr(&mut remote).await?;
+
+        let (mut network, mut events, event_loop) = NetworkSwarmLoop::with_config(NetworkConfig {
+            store_retry: StoreRetryConfig {
+                initial_backoff: Duration::from_millis(100),
+                ..Default::default()
+            },
+            ..isolated_config()
+        })?;
+        let _event_loop = task::spawn(event_loop.run());
+
+        // Fails right away, the routing table being empty.
+        let xor_name = XorName::random(&mut rand::thread_rng());
+        network.store_data_persistent(xor_name).await?;
+        task::sleep(Duration::from_millis(200)).await;
+        timeout(
+            TEST_TIMEOUT,
+            network.dial(remote.local_peer_id(), remote_addr),
+        )
+        .await
+        .expect("the dial to complete")?;
+
+        let attempts = timeout(TEST_TIMEOUT, async {
+            while let Some(event) = events.next().await {
+                match event {
+                    NetworkEvent::DataStored {
+                        xor_name: stored,
+                        attempts,
+                    } if stored == xor_name => return Some(attempts),
+                    NetworkEvent::DataStoreFailed { .. } => return None,
+                    _ => {}
+                }
+            }
+            None
+        })
+        .await
+        .expect("the data to be stored");
+        assert!(attempts.is_some_and(|attempts| attempts > 1));
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{error::Result, metrics::Metrics, NetworkEvent, NetworkSwarmLoop};
+use futures::{channel::mpsc, SinkExt};
+use std::fmt;
+use tracing::warn;
+
+/// Selects the `NetworkEvent`s delivered to a subscriber, see `Network::subscribe_events`.
+pub struct EventFilter(Box<dyn Fn(&NetworkEvent) -> bool + Send>);
+
+impl EventFilter {
+    /// Creates a filter letting through the events `predicate` returns `true` for, e.g.
+    /// `EventFilter::new(|event| matches!(event, NetworkEvent::RequestReceived { .. }))`.
+    pub fn new(predicate: impl Fn(&NetworkEvent) -> bool + Send + 'static) -> Self {
+        Self(Box::new(predicate))
+    }
+
+    fn matches(&self, event: &NetworkEvent) -> bool {
+        (self.0)(event)
+    }
+}
+
+impl fmt::Debug for EventFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventFilter")
+    }
+}
+
+/// A consumer of the events matching its filter.
+#[derive(Debug)]
+pub(crate) struct Subscriber {
+    pub(super) filter: EventFilter,
+    pub(super) sender: mpsc::Sender<NetworkEvent>,
+}
+
+impl NetworkSwarmLoop {
+    /// Delivers the event to the subscribers whose filter matches it, and to the main event
+    /// stream.
+    ///
+    /// The events carrying a response channel can only be answered once, hence they are
+    /// delivered to the first matching subscriber only, the main stream getting the ones no
+    /// subscriber is interested in.
+    ///
+    /// The subscribers never hold up the event loop: one whose stream is full is disconnected,
+    /// its stream ending, and the event goes on to the next one. Only the main stream is waited
+    /// for.
+    pub(super) async fn send_event(&mut self, event: NetworkEvent) -> Result<()> {
+        let mut event = Some(event);
+        self.subscribers.retain_mut(|subscriber| {
+            let delivered = match &event {
+                Some(pending) if subscriber.filter.matches(pending) => match pending.try_clone() {
+                    Some(copy) => copy,
+                    None => match event.take() {
+                        Some(pending) => pending,
+                        None => return true,
+                    },
+                },
+                _ => return !subscriber.sender.is_closed(),
+            };
+            match subscriber.sender.try_send(delivered) {
+                Ok(()) => true,
+                Err(err) => {
+                    if err.is_full() {
+                        warn!("Disconnected an event subscriber falling behind");
+                        Metrics::inc(&self.metrics.lagging_subscribers_dropped);
+                    }
+                    let undelivered = err.into_inner();
+                    if undelivered.carries_channel() {
+                        event = Some(undelivered);
+                    }
+                    false
+                }
+            }
+        });
+        if let Some(event) = event {
+            self.event_sender.send(event).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EventFilter;
+    use crate::network::{
+        error::{Error, Result},
+        testing::isolated_config,
+        NetworkConfig, NetworkEvent, NetworkSwarmLoop,
+    };
+    use async_std::{future::timeout, task};
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[async_std::test]
+    async fn a_lagging_subscriber_is_disconnected_without_stalling_the_loop() -> Result<()> {
+        let (mut network, events, event_loop) = NetworkSwarmLoop::with_config(NetworkConfig {
+            event_channel_capacity: 1,
+            ..isolated_config()
+        })?;
+        let _event_loop = task::spawn(event_loop.run());
+        let _events = task::spawn(events.for_each(|_| async {}));
+        let lagging = network
+            .subscribe_events(EventFilter::new(|event| {
+                matches!(event, NetworkEvent::ProtocolsChanged { .. })
+            }))
+            .await?;
+
+        // Never drained, the subscriber falls behind after a few events.
+        timeout(TEST_TIMEOUT, async {
+            for _ in 0..5 {
+                network.disable_protocol("/raw/2").await?;
+                network.enable_protocol("/raw/2").await?;
+            }
+            Ok::<_, Error>(())
+        })
+        .await
+        .expect("the loop not to wait for the subscriber")?;
+
+        assert_eq!(network.metrics_snapshot().lagging_subscribers_dropped, 1);
+        // The events buffered before the disconnection are still delivered, then it ends.
+        let buffered = timeout(TEST_TIMEOUT, lagging.count())
+            .await
+            .expect("the stream to end");
+        assert!(buffered < 10, "got all the {buffered} events");
+        Ok(())
+    }
+}
@@ -0,0 +1,331 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    error::{Error, Result},
+    Network, NetworkSwarmLoop, Request, Response,
+};
+#[cfg(test)]
+use super::{NetworkConfig, TransportKind};
+use libp2p::PeerId;
+#[cfg(test)]
+use libp2p::{multiaddr::Protocol, Multiaddr};
+#[cfg(test)]
+use std::net::Ipv4Addr;
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+use xor_name::XorName;
+
+/// The interval between two rounds of lookups in [`await_convergence`].
+const CONVERGENCE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long [`listen_addr`] waits for the node to be listening.
+#[cfg(test)]
+const LISTEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Polls until every node is able to find a provider for every key, or until `timeout`.
+///
+/// On timeout, `Error::ConvergenceTimeout` reports the index of the first node, in `nodes`,
+/// that could not find the key. The lookups failing, e.g. timing out before the network
+/// converged, count as not finding the key. An empty `nodes` or `keys` list has trivially
+/// converged.
+pub async fn await_convergence(
+    nodes: &mut [Network],
+    keys: &[XorName],
+    timeout: Duration,
+) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let (node, key) = match first_missing_key(nodes, keys, deadline).await {
+            None => return Ok(()),
+            Some(missing) => missing,
+        };
+        // No round is started past the deadline, its lookups failing at once whatever the key.
+        if Instant::now() + CONVERGENCE_POLL_INTERVAL >= deadline {
+            return Err(Error::ConvergenceTimeout { node, key });
+        }
+        async_std::task::sleep(CONVERGENCE_POLL_INTERVAL).await;
+    }
+}
+
+// Returns the first (node index, key) pair for which no provider could be found before the
+// deadline, if any.
+async fn first_missing_key(
+    nodes: &mut [Network],
+    keys: &[XorName],
+    deadline: Instant,
+) -> Option<(usize, XorName)> {
+    for (index, node) in nodes.iter_mut().enumerate() {
+        for key in keys {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let found =
+                match async_std::future::timeout(remaining, node.get_data_providers(*key)).await {
+                    Ok(Ok(providers)) => !providers.is_empty(),
+                    Ok(Err(_)) | Err(_) => false,
+                };
+            if !found {
+                return Some((index, *key));
+            }
+        }
+    }
+    None
+}
+
+/// Records the `Request`s and `Response`s a node exchanges with its peers, as decoded from or
+/// to be encoded on the wire, for the tests to assert on the protocol behaviour.
+///
+/// Attach it to a node through `NetworkSwarmLoop::with_message_recorder`; the recorder is a
+/// handle to a shared buffer, so a clone of it can be kept to read the messages. The requests
+/// sent again by the retries are recorded every time, as are the liveness probes.
+#[derive(Debug, Clone, Default)]
+pub struct MessageRecorder {
+    messages: Arc<Mutex<Vec<RecordedMessage>>>,
+}
+
+/// A message recorded by a `MessageRecorder`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedMessage {
+    /// The peer the message was received from or sent to.
+    pub peer: PeerId,
+    /// Whether the message was received or sent.
+    pub direction: Direction,
+    /// The message.
+    pub msg: WireMessage,
+}
+
+/// The direction of a `RecordedMessage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Received from the peer.
+    Inbound,
+    /// Sent to the peer.
+    Outbound,
+}
+
+/// The content of a `RecordedMessage`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WireMessage {
+    /// A request.
+    Request(Request),
+    /// A response.
+    Response(Response),
+}
+
+impl MessageRecorder {
+    /// Creates an empty recorder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of the messages recorded so far, in the order they were exchanged.
+    pub fn messages(&self) -> Vec<RecordedMessage> {
+        self.lock().clone()
+    }
+
+    /// Returns the messages recorded so far, clearing the buffer.
+    pub fn take(&self) -> Vec<RecordedMessage> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    pub(super) fn record(&self, msg: RecordedMessage) {
+        self.lock().push(msg);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<RecordedMessage>> {
+        self.messages.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl NetworkSwarmLoop {
+    /// Records the message, if a `MessageRecorder` is attached; `msg` is only called then.
+    pub(super) fn record_message(
+        &self,
+        peer: PeerId,
+        direction: Direction,
+        msg: impl FnOnce() -> WireMessage,
+    ) {
+        if let Some(recorder) = &self.message_recorder {
+            recorder.record(RecordedMessage {
+                peer,
+                direction,
+                msg: msg(),
+            });
+        }
+    }
+}
+
+/// Returns the config of a test node only reachable by the nodes told about it: TCP only and
+/// without mDNS, so that the nodes of the tests running in parallel don't find each other. The
+/// node listens on the loopback interface, on the port the OS assigns, see [`listen_addr`].
+#[cfg(test)]
+pub(crate) fn isolated_config() -> NetworkConfig {
+    NetworkConfig {
+        transport: TransportKind::Tcp,
+        mdns: false,
+        listen_addr: Some(
+            Multiaddr::empty()
+                .with(Protocol::Ip4(Ipv4Addr::LOCALHOST))
+                .with(Protocol::Tcp(0)),
+        ),
+        ..Default::default()
+    }
+}
+
+/// Waits for the node to be listening and returns the address it is bound to, i.e. with the
+/// port the OS assigned. The `NetworkSwarmLoop` of the node has to be running.
+#[cfg(test)]
+pub(crate) async fn listen_addr(network: &mut Network) -> Result<Multiaddr> {
+    let listening = async {
+        loop {
+            if let Some(addr) = network.listeners().await?.into_iter().next() {
+                return Ok(addr);
+            }
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    async_std::future::timeout(LISTEN_TIMEOUT, listening)
+        .await
+        .map_err(|_elapsed| Error::Other("The node is not listening".to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        await_convergence, isolated_config, listen_addr, Direction, MessageRecorder,
+        RecordedMessage, WireMessage,
+    };
+    use crate::network::{
+        error::{Error, Result},
+        NetworkEvent, NetworkSwarmLoop, Request, Response,
+    };
+    use async_std::{future::timeout, task};
+    use futures::StreamExt;
+    use std::time::Duration;
+    use xor_name::XorName;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[async_std::test]
+    async fn message_recorder_records_the_messages_on_both_ends() -> Result<()> {
+        let requester_recorder = MessageRecorder::new();
+        let responder_recorder = MessageRecorder::new();
+        let (mut requester, requester_events, requester_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let (mut responder, mut responder_events, responder_loop) =
+            NetworkSwarmLoop::with_config(isolated_config())?;
+        let _requester_loop = task::spawn(
+            requester_loop
+                .with_message_recorder(requester_recorder.clone())
+                .run(),
+        );
+        let _responder_loop = task::spawn(
+            responder_loop
+                .with_message_recorder(responder_recorder.clone())
+                .run(),
+        );
+        let _requester_events = task::spawn(requester_events.for_each(|_| async {}));
+
+        let addr = listen_addr(&mut responder).await?;
+        let requester_id = requester.local_peer_id();
+        let responder_id = responder.local_peer_id();
+
+        let chunk = XorName::random(&mut rand::thread_rng());
+        let mut responding = responder.clone();
+        let _responder = task::spawn(async move {
+            while let Some(event) = responder_events.next().await {
+                if let NetworkEvent::RequestReceived { channel, .. } = event {
+                    let _ = responding.send_response(Response::Pong, channel).await;
+                }
+            }
+        });
+
+        timeout(TEST_TIMEOUT, requester.dial(responder_id, addr))
+            .await
+            .expect("the dial to complete")?;
+        let response = timeout(
+            TEST_TIMEOUT,
+            requester.send_request(Request::GetChunk(chunk), responder_id),
+        )
+        .await
+        .expect("the request to complete")?;
+        assert_eq!(response, Response::Pong);
+
+        assert_eq!(
+            requester_recorder.take(),
+            vec![
+                RecordedMessage {
+                    peer: responder_id,
+                    direction: Direction::Outbound,
+                    msg: WireMessage::Request(Request::GetChunk(chunk)),
+                },
+                RecordedMessage {
+                    peer: responder_id,
+                    direction: Direction::Inbound,
+                    msg: WireMessage::Response(Response::Pong),
+                },
+            ]
+        );
+        assert!(requester_recorder.messages().is_empty());
+        assert_eq!(
+            responder_recorder.messages(),
+            vec![
+                RecordedMessage {
+                    peer: requester_id,
+                    direction: Direction::Inbound,
+                    msg: WireMessage::Request(Request::GetChunk(chunk)),
+                },
+                RecordedMessage {
+                    peer: requester_id,
+                    direction: Direction::Outbound,
+                    msg: WireMessage::Response(Response::Pong),
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn convergence_is_awaited_until_every_node_finds_the_keys() -> Result<()> {
+        let mut nodes = Vec::new();
+        for _ in 0..3 {
+            let (network, events, event_loop) = NetworkSwarmLoop::with_config(isolated_config())?;
+            let _loop = task::spawn(event_loop.run());
+            let _events = task::spawn(events.for_each(|_| async {}));
+            nodes.push(network);
+        }
+        let provider_id = nodes[0].local_peer_id();
+        let addr = listen_addr(&mut nodes[0]).await?;
+        for node in &mut nodes[1..] {
+            timeout(TEST_TIMEOUT, node.dial(provider_id, addr.clone()))
+                .await
+                .expect("the dial to complete")?;
+        }
+
+        let provided = XorName::random(&mut rand::thread_rng());
+        timeout(TEST_TIMEOUT, nodes[0].store_data(provided))
+            .await
+            .expect("the providing to complete")?;
+        await_convergence(&mut nodes, &[provided], TEST_TIMEOUT).await?;
+
+        // Nobody provides the key; the first node already misses it.
+        let missing = XorName::random(&mut rand::thread_rng());
+        let result =
+            await_convergence(&mut nodes, &[provided, missing], Duration::from_millis(500)).await;
+        assert!(
+            matches!(
+                result,
+                Err(Error::ConvergenceTimeout { node: 0, key }) if key == missing
+            ),
+            "{result:?}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{error::Result, NetworkSwarmLoop};
+use tracing::{trace, warn};
+
+/// The periodic tasks of the event loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Tick {
+    BucketRefresh,
+    Reaper,
+    DialStagger,
+    Timeout,
+    Republish,
+    StoreRetry,
+    LoadCheck,
+    RoutingCheck,
+}
+
+impl NetworkSwarmLoop {
+    /// Runs the periodic task of `tick`, logging its failure.
+    pub(super) async fn run_tick(&mut self, tick: Tick) {
+        if let Err(err) = self.on_tick(tick).await {
+            warn!("Error while running the {tick:?} tick: {err}");
+        }
+    }
+
+    /// Runs the periodic task of `tick`. While networking is paused only the timeouts go on,
+    /// for the operations already in flight to complete or time out.
+    pub(super) async fn on_tick(&mut self, tick: Tick) -> Result<()> {
+        if self.paused_listen_addrs.is_some() && tick != Tick::Timeout {
+            trace!("Skipping the {tick:?} tick, networking being paused");
+            return Ok(());
+        }
+        match tick {
+            Tick::BucketRefresh => {
+                self.refresh_buckets();
+                Ok(())
+            }
+            Tick::Reaper => self.reap_idle_peers().await,
+            Tick::DialStagger => {
+                self.advance_staggered_dials();
+                Ok(())
+            }
+            Tick::Timeout => self.expire_requests_and_dials(),
+            Tick::Republish => self.republish_provided_data().await,
+            Tick::StoreRetry => self.retry_due_persistent_stores().await,
+            Tick::LoadCheck => self.check_load().await,
+            Tick::RoutingCheck => self.check_routing_table().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tick;
+    use crate::network::{
+        command::SwarmCmd,
+        config::{DataClass, DataClassSchedule, DataClassSchedules, LoadSheddingConfig},
+        error::Result,
+        testing::isolated_config,
+        NetworkConfig, NetworkSwarmLoop,
+    };
+    use futures::channel::oneshot;
+    use libp2p::kad::RecordKey;
+    use std::time::Duration;
+
+    #[async_std::test]
+    async fn the_ticks_are_skipped_while_paused() -> Result<()> {
+        let schedule = DataClassSchedule {
+            republish_interval: Duration::from_millis(10),
+            ttl: None,
+        };
+        let (_network, _events, mut event_loop) = NetworkSwarmLoop::with_config(NetworkConfig {
+            data_classes: DataClassSchedules {
+                critical: schedule,
+                standard: schedule,
+                cache: schedule,
+            },
+            load_shedding: Some(LoadSheddingConfig {
+                max_in_flight: 0,
+                ..Default::default()
+            }),
+            ..isolated_config()
+        })?;
+        let key = RecordKey::new(&[1; 32]);
+        let _ = event_loop
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .start_providing(key.clone())?;
+        event_loop.track_provided(key, DataClass::Standard);
+        async_std::task::sleep(Duration::from_millis(20)).await;
+
+        let (sender, receiver) = oneshot::channel();
+        event_loop
+            .handle_command(SwarmCmd::Pause { sender })
+            .await?;
+        receiver.await??;
+        event_loop.on_tick(Tick::Republish).await?;
+        event_loop.on_tick(Tick::LoadCheck).await?;
+        assert!(event_loop.republish_queries.is_empty());
+        assert!(!event_loop.shedding_load);
+        // The timeouts still go on.
+        event_loop.on_tick(Tick::Timeout).await?;
+
+        let (sender, receiver) = oneshot::channel();
+        event_loop
+            .handle_command(SwarmCmd::Resume { sender })
+            .await?;
+        receiver.await??;
+        event_loop.on_tick(Tick::Republish).await?;
+        event_loop.on_tick(Tick::LoadCheck).await?;
+        assert_eq!(event_loop.republish_queries.len(), 1);
+        assert!(event_loop.shedding_load);
+        Ok(())
+    }
+}
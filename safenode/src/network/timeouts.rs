@@ -0,0 +1,238 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    error::{Error, Result},
+    metrics::Metrics,
+    msg::{MsgRequest, Request, TransferProgress, TransferTracker},
+    op_trace::OpSpan,
+    testing::{Direction, WireMessage},
+    NetworkSwarmLoop,
+};
+use libp2p::{request_response::RequestId, PeerId};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, Span};
+
+/// The shortest interval between two checks for the expired requests and dials.
+const MIN_TIMEOUT_TICK: Duration = Duration::from_millis(50);
+
+/// A request sent through `Network::send_request`, to be given up on, or sent again, once its
+/// `NetworkConfig::request_timeout` elapsed.
+pub(super) struct RequestDeadline {
+    req: Request,
+    peer: PeerId,
+    op: OpSpan,
+    deadline: Instant,
+    retries_left: u32,
+}
+
+impl NetworkSwarmLoop {
+    /// The interval between two checks for the expired requests and dials, a few times per
+    /// timeout for them to expire close to their deadline; `None` if neither is timed out.
+    pub(super) fn timeout_tick(&self) -> Option<Duration> {
+        self.request_timeout
+            .into_iter()
+            .chain(self.dial_timeout)
+            .min()
+            .map(|timeout| (timeout / 4).max(MIN_TIMEOUT_TICK))
+    }
+
+    /// Sends the request to the peer, returning its id.
+    pub(super) fn send_msg_request(
+        &mut self,
+        peer: PeerId,
+        req: Request,
+        op: &OpSpan,
+    ) -> RequestId {
+        self.record_message(peer, Direction::Outbound, || {
+            WireMessage::Request(req.clone())
+        });
+        let connected = self.swarm.is_connected(&peer);
+        let tracker = TransferTracker::default();
+        let request_id = self
+            .swarm
+            .behaviour_mut()
+            .request_response
+            .send_request(&peer, MsgRequest::new(req, tracker.clone()));
+        let _ = self.request_transfers.insert(request_id, tracker);
+        self.start_request_timing(request_id, peer, connected);
+        self.op_traces.request_sent(request_id, peer, connected, op);
+        Metrics::inc(&self.metrics.requests_sent);
+        request_id
+    }
+
+    /// Starts timing out the request just sent, if `NetworkConfig::request_timeout` is set.
+    pub(super) fn track_request_deadline(
+        &mut self,
+        request_id: RequestId,
+        peer: PeerId,
+        req: Request,
+        op: OpSpan,
+    ) {
+        if let Some(timeout) = self.request_timeout {
+            let _ = self.request_deadlines.insert(
+                request_id,
+                RequestDeadline {
+                    req,
+                    peer,
+                    op,
+                    deadline: Instant::now() + timeout,
+                    retries_left: self.request_retries,
+                },
+            );
+        }
+    }
+
+    /// Starts timing out the dial just started, if `NetworkConfig::dial_timeout` is set.
+    pub(super) fn track_dial_deadline(&mut self, peer_id: PeerId) {
+        if let Some(timeout) = self.dial_timeout {
+            let _ = self
+                .dial_deadlines
+                .insert(peer_id, Instant::now() + timeout);
+        }
+    }
+
+    /// Sends again the expired requests with retries left, and fails the others, as well as the
+    /// expired dials, with `Error::Timeout`.
+    pub(super) fn expire_requests_and_dials(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let expired: Vec<RequestId> = self
+            .request_deadlines
+            .iter()
+            .filter(|(_, request)| request.deadline <= now)
+            .map(|(request_id, _)| *request_id)
+            .collect();
+        for request_id in expired {
+            if let Some(request) = self.request_deadlines.remove(&request_id) {
+                self.expire_request(request_id, request)?;
+            }
+        }
+
+        let timeout = self.dial_timeout.unwrap_or_default();
+        let mut expired_dials = Vec::new();
+        self.dial_deadlines.retain(|peer_id, deadline| {
+            let expired = *deadline <= now;
+            if expired {
+                expired_dials.push(*peer_id);
+            }
+            !expired
+        });
+        for peer_id in expired_dials {
+            if let Some(sender) = self.pending_dial.remove(&peer_id) {
+                info!("Dial to {peer_id:?} timed out");
+                let _ = self.staggered_dials.remove(&peer_id);
+                let _ = sender.send(Err(Error::Timeout(timeout)));
+            }
+        }
+        Ok(())
+    }
+
+    // Sends the request again if it has retries left and sending it again is safe, failing it
+    // with `Error::Timeout` otherwise. The response to the expired request, if it ever comes, is dropped.
+    fn expire_request(
+        &mut self,
+        request_id: RequestId,
+        mut request: RequestDeadline,
+    ) -> Result<()> {
+        let sender = self
+            .pending_requests
+            .remove(&request_id)
+            .ok_or(Error::Other("Request to still be pending".to_string()))?;
+        let progress = self
+            .request_transfers
+            .remove(&request_id)
+            .map(|tracker| tracker.progress())
+            .unwrap_or_default();
+        let step = self.op_traces.request_completed(&request_id);
+        let _entered = step.as_ref().map(Span::enter);
+
+        let timeout = self.request_timeout.unwrap_or_default();
+        if request.retries_left == 0 || !retry_is_safe(&request.req, progress) {
+            info!("Request {request_id:?} to {:?} timed out", request.peer);
+            for waiter in self.take_coalesced_waiters(&request_id) {
+                let _ = waiter.send(Err(Error::Timeout(timeout)));
+            }
+            let _ = sender.send(Err(Error::Timeout(timeout)));
+            return Ok(());
+        }
+
+        request.retries_left -= 1;
+        request.deadline = Instant::now() + timeout;
+        let retry_id = self.send_msg_request(request.peer, request.req.clone(), &request.op);
+        debug!(
+            "Request {request_id:?} to {:?} timed out, sent again as {retry_id:?}",
+            request.peer
+        );
+        self.rekey_coalesced_request(&request_id, retry_id);
+        let _ = self.pending_requests.insert(retry_id, sender);
+        let _ = self.request_deadlines.insert(retry_id, request);
+        Ok(())
+    }
+}
+
+// Whether the expired request can be sent again: the peer may have acted on a request it got
+// some bytes of, which only the idempotent requests allow for.
+fn retry_is_safe(req: &Request, progress: TransferProgress) -> bool {
+    req.is_idempotent() || progress.nothing_sent()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{
+        command::SwarmCmd, error::Error, op_trace::OpSpan, testing::isolated_config, NetworkConfig,
+        NetworkSwarmLoop, Request,
+    };
+    use futures::channel::oneshot;
+    use libp2p::PeerId;
+    use std::time::Duration;
+    use tracing::info_span;
+
+    #[async_std::test]
+    async fn a_timed_out_request_leaves_nothing_behind() -> Result<(), Error> {
+        let timeout = Duration::from_millis(50);
+        let config = NetworkConfig {
+            request_timeout: Some(timeout),
+            request_retries: 1,
+            ..isolated_config()
+        };
+        let (_network, _events, mut event_loop) = NetworkSwarmLoop::with_config(config)?;
+
+        // The swarm isn't polled, hence the request never gets anywhere.
+        let peer = PeerId::random();
+        let (sender, mut receiver) = oneshot::channel();
+        event_loop
+            .handle_command(SwarmCmd::SendRequest {
+                req: Request::GetDBC,
+                peer,
+                coalesce_key: None,
+                sender,
+                op: OpSpan::new(|| info_span!("send_request", %peer)),
+            })
+            .await?;
+        assert_eq!(event_loop.request_deadlines.len(), 1);
+
+        // Sent again once, its state now under the id of the retry.
+        async_std::task::sleep(timeout).await;
+        event_loop.expire_requests_and_dials()?;
+        assert!(receiver.try_recv()?.is_none());
+        assert_eq!(event_loop.pending_requests.len(), 1);
+        assert_eq!(event_loop.request_deadlines.len(), 1);
+        assert_eq!(event_loop.request_transfers.len(), 1);
+
+        async_std::task::sleep(timeout).await;
+        event_loop.expire_requests_and_dials()?;
+        assert!(matches!(
+            receiver.try_recv()?,
+            Some(Err(Error::Timeout(elapsed))) if elapsed == timeout
+        ));
+        assert!(event_loop.pending_requests.is_empty());
+        assert!(event_loop.request_deadlines.is_empty());
+        assert!(event_loop.request_transfers.is_empty());
+        Ok(())
+    }
+}
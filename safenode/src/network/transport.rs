@@ -0,0 +1,80 @@
+// Copyright 2023 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+#[cfg(feature = "chaos")]
+use super::chaos;
+use super::{error::Result, NetworkConfig, TransportKind};
+use libp2p::{
+    core::{muxing::StreamMuxerBox, transport::Boxed, upgrade},
+    identity::Keypair,
+    noise, tcp, yamux, PeerId, Transport,
+};
+
+/// Builds the transport stack of the node: QUIC and/or TCP as per the
+/// `NetworkConfig::transport`, plus Unix domain sockets with the `uds` feature, the
+/// `NetworkConfig::chaos` conditions being applied on top with the `chaos` feature.
+pub(super) fn build(
+    keypair: &Keypair,
+    config: &NetworkConfig,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>> {
+    let transport = match config.transport {
+        TransportKind::Quic => quic(keypair),
+        TransportKind::Tcp => tcp(keypair)?,
+        TransportKind::QuicAndTcp => quic(keypair)
+            .or_transport(tcp(keypair)?)
+            .map(|either, _| either.into_inner())
+            .boxed(),
+    };
+    #[cfg(all(feature = "uds", unix))]
+    let transport = transport
+        .or_transport(uds(keypair)?)
+        .map(|either, _| either.into_inner())
+        .boxed();
+    #[cfg(feature = "chaos")]
+    let transport = match config.chaos {
+        Some(chaos) => chaos::apply(transport, chaos),
+        None => transport,
+    };
+    Ok(transport)
+}
+
+// QUIC, which carries its own encryption and multiplexing.
+fn quic(keypair: &Keypair) -> Boxed<(PeerId, StreamMuxerBox)> {
+    let quic_config = libp2p_quic::Config::new(keypair);
+    libp2p_quic::async_std::Transport::new(quic_config)
+        .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+        .boxed()
+}
+
+// TCP, to listen on and dial the `/tcp/<port>` addresses where UDP is blocked, upgraded with
+// Noise and Yamux.
+fn tcp(keypair: &Keypair) -> Result<Boxed<(PeerId, StreamMuxerBox)>> {
+    Ok(
+        tcp::async_io::Transport::new(tcp::Config::default().nodelay(true))
+            .upgrade(upgrade::Version::V1)
+            .authenticate(noise::Config::new(keypair)?)
+            .multiplex(yamux::Config::default())
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+            .boxed(),
+    )
+}
+
+// Unix domain sockets, to listen on and dial the `/unix/<path>` addresses, e.g. for the
+// components running on the same host. Unlike QUIC the sockets carry neither encryption nor
+// multiplexing, hence are upgraded with Noise and Yamux.
+#[cfg(all(feature = "uds", unix))]
+fn uds(keypair: &Keypair) -> Result<Boxed<(PeerId, StreamMuxerBox)>> {
+    use libp2p_uds::UdsConfig;
+
+    Ok(UdsConfig::new()
+        .upgrade(upgrade::Version::V1)
+        .authenticate(noise::Config::new(keypair)?)
+        .multiplex(yamux::Config::default())
+        .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+        .boxed())
+}
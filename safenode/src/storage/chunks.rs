@@ -6,6 +6,9 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+// The `custom_debug` derive expands to a non-local impl on newer compilers.
+#![allow(non_local_definitions)]
+
 use super::errors::{Error, Result};
 use super::prefix_tree_path;
 use async_std::fs::{create_dir_all, read, File};
@@ -21,7 +24,7 @@ use std::{
 //     fs::{create_dir_all, metadata, read, remove_file, File},
 //     io::AsyncWriteExt,
 // };
-use tracing::{debug, info, trace};
+use tracing::{info, trace};
 use xor_name::XorName;
 
 const CHUNKS_STORE_DIR_NAME: &str = "chunks";
@@ -193,7 +193,7 @@ impl Testnet {
     /// # Arguments
     ///
     /// * `address` - Optional address for where the genesis node will listen for connections. If
-    /// not specified, the 127.0.0.1:12000 local address will be used.
+    ///   not specified, the 127.0.0.1:12000 local address will be used.
     /// * `node_args` - Additional arguments to pass to the node process, e.g., --json-logs.
     ///
     /// # Errors
@@ -690,7 +690,7 @@ mod test {
         let mut node_launcher = MockNodeLauncher::new();
         for i in 1..=20 {
             let node_data_dir = nodes_dir
-                .join(&format!("safenode-{i}"))
+                .join(format!("safenode-{i}"))
                 .to_str()
                 .ok_or_else(|| eyre!("Unable to obtain path"))?
                 .to_string();
@@ -809,7 +809,7 @@ mod test {
 
         let mut node_launcher = MockNodeLauncher::new();
         for i in 1..=20 {
-            let node_data_dir = nodes_dir.join(&format!("safenode-{i}"));
+            let node_data_dir = nodes_dir.join(format!("safenode-{i}"));
             let graph_output_file_path = node_data_dir
                 .join(format!("safenode-{i}-flame.svg"))
                 .to_str()
@@ -874,7 +874,7 @@ mod test {
         let mut node_launcher = MockNodeLauncher::new();
         for i in 1..=30 {
             let node_data_dir = nodes_dir
-                .join(&format!("safenode-{i}"))
+                .join(format!("safenode-{i}"))
                 .to_str()
                 .ok_or_else(|| eyre!("Unable to obtain path"))?
                 .to_string();